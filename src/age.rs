@@ -0,0 +1,200 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta};
+use std::fmt;
+use std::str::FromStr;
+
+/// A span of time given on the command line, like `12h`, `30d`, `2w` or `1y`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age {
+    amount: i64,
+    unit: char,
+}
+
+impl Age {
+    const UNITS: [(char, i64); 4] = [
+        ('h', 3600),
+        ('d', 86400),
+        ('w', 7 * 86400),
+        ('y', 365 * 86400),
+    ];
+
+    pub fn duration(self) -> TimeDelta {
+        let secs = Self::UNITS
+            .iter()
+            .find(|(u, _)| *u == self.unit)
+            .map_or(0, |(_, secs)| *secs);
+        TimeDelta::seconds(self.amount.saturating_mul(secs))
+    }
+
+    /// Whether something created at `time` is older than this, as of `now`.
+    pub fn exceeded_by(self, time: DateTime<Local>, now: DateTime<Local>) -> bool {
+        now - time > self.duration()
+    }
+}
+
+/// A moment given on the command line: a date (`2024-03-01`, optionally with
+/// `12:30`), or a time ago (`2 weeks ago`, `3d`, `yesterday`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Moment(pub DateTime<Local>);
+
+impl FromStr for Moment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid time \"{}\" (expected e.g. 2024-03-01, \"2024-03-01 12:30\", \"2 weeks ago\" or 3d)",
+                s
+            )
+        };
+        let now = Local::now();
+        let s = s.trim();
+        let local = |t: NaiveDateTime| t.and_local_timezone(Local).earliest();
+        let moment = match s {
+            "now" => Some(now),
+            "yesterday" => Some(now - TimeDelta::days(1)),
+            _ => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|t| t.with_timezone(&Local))
+                .or_else(|| {
+                    [
+                        "%Y-%m-%d %H:%M:%S",
+                        "%Y-%m-%d %H:%M",
+                        "%Y-%m-%dT%H:%M:%S",
+                        "%Y-%m-%dT%H:%M",
+                    ]
+                    .iter()
+                    .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+                    .and_then(local)
+                })
+                // a bare date means the end of that day
+                .or_else(|| {
+                    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(23, 59, 59))
+                        .and_then(local)
+                })
+                .or_else(|| ago(s).map(|delta| now - delta)),
+        };
+        moment.map(Moment).ok_or_else(invalid)
+    }
+}
+
+/// `2 weeks ago`, `1 day ago`, `2w` or `2w ago`.
+fn ago(s: &str) -> Option<TimeDelta> {
+    let s = s.strip_suffix("ago").unwrap_or(s).trim();
+    if let Ok(age) = s.parse::<Age>() {
+        return Some(age.duration());
+    }
+    let (amount, unit) = s.split_once(char::is_whitespace)?;
+    let amount: i64 = amount.parse().ok()?;
+    let secs = match unit.trim().trim_end_matches('s') {
+        "minute" | "min" => 60,
+        "hour" => 3600,
+        "day" => 86400,
+        "week" => 7 * 86400,
+        "month" => 30 * 86400,
+        "year" => 365 * 86400,
+        _ => return None,
+    };
+    Some(TimeDelta::seconds(amount.checked_mul(secs)?))
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.amount, self.unit)
+    }
+}
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid age \"{}\" (expected e.g. 12h, 30d, 2w or 1y)", s);
+        let unit = s.chars().last().ok_or_else(invalid)?;
+        if !Self::UNITS.iter().any(|(u, _)| *u == unit) {
+            return Err(invalid());
+        }
+        let amount = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+        if amount < 0 {
+            return Err(invalid());
+        }
+        Ok(Age { amount, unit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ages() {
+        let age: Age = "12h".parse().unwrap();
+        assert_eq!(age.duration(), TimeDelta::hours(12));
+        assert_eq!(
+            "30d".parse::<Age>().unwrap().duration(),
+            TimeDelta::days(30)
+        );
+        assert_eq!("2w".parse::<Age>().unwrap().duration(), TimeDelta::days(14));
+        assert_eq!(
+            "1y".parse::<Age>().unwrap().duration(),
+            TimeDelta::days(365)
+        );
+        assert_eq!("2w".parse::<Age>().unwrap().to_string(), "2w");
+    }
+
+    #[test]
+    fn rejects_bad_ages() {
+        for s in ["", "d", "12", "12m", "-3d", "1.5d", "3 d"] {
+            assert!(s.parse::<Age>().is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn age_exceeded_by() {
+        let now = Local::now();
+        let age: Age = "1d".parse().unwrap();
+        assert!(age.exceeded_by(now - TimeDelta::hours(25), now));
+        assert!(!age.exceeded_by(now - TimeDelta::hours(23), now));
+    }
+
+    fn local(s: &str) -> DateTime<Local> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_dates() {
+        let Moment(t) = "2024-03-01 12:30".parse().unwrap();
+        assert_eq!(t, local("2024-03-01 12:30:00"));
+        let Moment(t) = "2024-03-01T12:30:15".parse().unwrap();
+        assert_eq!(t, local("2024-03-01 12:30:15"));
+        // a bare date is the end of the day
+        let Moment(t) = "2024-03-01".parse().unwrap();
+        assert_eq!(t, local("2024-03-01 23:59:59"));
+    }
+
+    #[test]
+    fn parses_times_ago() {
+        let close_to = |s: &str, delta: TimeDelta| {
+            let Moment(t) = s.parse().unwrap();
+            let expected = Local::now() - delta;
+            assert!((t - expected).abs() < TimeDelta::seconds(5), "{:?}", s);
+        };
+        close_to("now", TimeDelta::zero());
+        close_to("yesterday", TimeDelta::days(1));
+        close_to("2 weeks ago", TimeDelta::weeks(2));
+        close_to("1 day ago", TimeDelta::days(1));
+        close_to("90 minutes ago", TimeDelta::minutes(90));
+        close_to("3d", TimeDelta::days(3));
+        close_to("2w ago", TimeDelta::weeks(2));
+    }
+
+    #[test]
+    fn rejects_bad_moments() {
+        for s in ["", "soon", "2024-13-01", "2 fortnights ago", "ago"] {
+            assert!(s.parse::<Moment>().is_err(), "{:?} was accepted", s);
+        }
+    }
+}
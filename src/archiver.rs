@@ -0,0 +1,694 @@
+use crate::capsule::Capsule;
+use crate::config::{Config, Profile};
+use crate::environment::Environment;
+use crate::error::{Error, Result};
+use crate::filter::EntryFilter;
+use crate::journal::{journal_path, part_path, replay, with_suffix, Journal};
+use crate::manifest::{Manifest, ManifestEntry, SymlinkEntry, MANIFEST_NAME};
+use crate::plugins::PACKER_SNAPSHOT_DIR;
+use crate::progress::Progress;
+use crate::restorer::symlink;
+use crate::size::ByteSize;
+use crate::snapshot::{partial_path, same_file_metadata, set_file_metadata, set_mode, Snapshot};
+use crate::sources::{archive_path, SourceKind, DEFAULT_APPNAME};
+use crate::stats::{largest, PathSize};
+use crate::volume::{volume_path, volume_paths};
+use crate::writer::CapsuleWriter;
+use chrono::{DateTime, Local};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, trace, warn};
+use walkdir::{DirEntry, WalkDir};
+
+/// Outcome of [`Archiver::preview`].
+#[derive(Debug, Default)]
+pub struct CreatePreview {
+    /// Name, path, file count and total size per source directory.
+    pub sources: Vec<(String, PathBuf, u64, u64)>,
+    /// Entries that would be left out (sockets, FIFOs, broken symlinks, ...).
+    pub excluded: Vec<PathBuf>,
+    /// The biggest directories one and two levels into each source
+    /// directory, by archive path.
+    pub largest_dirs: Vec<PathSize>,
+}
+
+/// What [`Archiver`] does with symbolic links in the source directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Symlinks {
+    /// Archive the file a link points to; links to directories are left out.
+    #[default]
+    Files,
+    /// Archive whatever a link points to, including whole directories.
+    Follow,
+    /// Archive the links themselves, to be recreated as links on restore.
+    Preserve,
+}
+
+/// How a walked entry goes into the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Symlink,
+    Dir,
+    /// Sockets, FIFOs, devices and links that aren't archived.
+    Skipped,
+}
+
+impl CreatePreview {
+    pub fn file_count(&self) -> u64 {
+        self.sources.iter().map(|s| s.2).sum()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.sources.iter().map(|s| s.3).sum()
+    }
+}
+
+/// Packs the Neovim directories into a capsule archive.
+#[derive(Debug, Clone)]
+pub struct Archiver {
+    home: PathBuf,
+    appname: String,
+    sources: Vec<SourceKind>,
+    /// Name and directories of a [`Profile`], archived instead of `sources`.
+    profile: Option<String>,
+    dirs: Vec<String>,
+    /// Globs of entries to archive and to leave out, compiled into `filter`.
+    include: Vec<String>,
+    exclude: Vec<String>,
+    filter: EntryFilter,
+    portable: bool,
+    plugins_only: bool,
+    symlinks: Symlinks,
+    reproducible: bool,
+    volume_size: u64,
+}
+
+/// Globs of what [`Archiver::portable`] leaves out: binaries mason.nvim
+/// installed, compiled tree-sitter parsers and other native libraries, which
+/// only work on the OS and architecture they were built for.
+pub const PORTABLE_EXCLUDES: [&str; 8] = [
+    "mason/bin/**",
+    "mason/packages/**",
+    "mason/share/**",
+    "mason/opt/**",
+    "**/parser-info/*",
+    "**/*.so",
+    "**/*.dylib",
+    "**/*.dll",
+];
+
+/// Smallest volume size [`Archiver::split`] accepts.
+pub const MIN_VOLUME_SIZE: u64 = 1 << 20;
+
+impl Archiver {
+    /// Archive the default Neovim directories under `home`.
+    pub fn new(home: impl Into<PathBuf>) -> Self {
+        let home = home.into();
+        Archiver {
+            sources: SourceKind::distinct(&SourceKind::ALL, &home, DEFAULT_APPNAME),
+            appname: DEFAULT_APPNAME.to_string(),
+            profile: None,
+            dirs: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            filter: EntryFilter::default(),
+            portable: false,
+            plugins_only: false,
+            symlinks: Symlinks::default(),
+            reproducible: false,
+            volume_size: u64::MAX,
+            home,
+        }
+    }
+
+    /// Archive the directories `capsule` was made of, as they are now: those
+    /// of its profile, leaving out what `config` has the profile leave out,
+    /// or else the Neovim directories of its `NVIM_APPNAME`.
+    pub fn for_capsule(
+        home: impl Into<PathBuf>,
+        capsule: &Capsule,
+        config: &Config,
+    ) -> Result<Self> {
+        let archiver = Self::new(home);
+        match config.capsule_profile(capsule)? {
+            Some((name, profile)) => archiver.profile(&name, &profile),
+            None => Ok(archiver.appname(&capsule.appname()?)),
+        }
+    }
+
+    /// Archive the directories of another `NVIM_APPNAME`, e.g.
+    /// `~/.config/<appname>`; the name is recorded in the manifest.
+    pub fn appname(mut self, appname: &str) -> Self {
+        self.appname = appname.to_string();
+        self.sources = SourceKind::distinct(&SourceKind::ALL, &self.home, appname);
+        self
+    }
+
+    /// Archive the sources of the profile `name` instead of the Neovim
+    /// directories, leaving out what its `exclude` globs match.
+    pub fn profile(mut self, name: &str, profile: &Profile) -> Result<Self> {
+        self.profile = Some(name.to_string());
+        self.dirs = profile.dirs();
+        self.exclude(&profile.exclude)
+    }
+
+    /// Leave out what only works on this OS and architecture
+    /// ([`PORTABLE_EXCLUDES`]), for a small capsule that can be restored on
+    /// another machine, where plugin managers rebuild those anyway.
+    pub fn portable(mut self) -> Result<Self> {
+        self.portable = true;
+        let globs: Vec<String> = PORTABLE_EXCLUDES.iter().map(|g| g.to_string()).collect();
+        self.exclude(&globs)
+    }
+
+    /// Only archive the configuration, which includes lazy.nvim's
+    /// `lazy-lock.json`, and packer.nvim's snapshots from the cache
+    /// directory: a tiny capsule from which the plugin manager reinstalls
+    /// the locked plugins. Restoring it leaves the other directories alone.
+    pub fn plugins_only(mut self) -> Result<Self> {
+        self.plugins_only = true;
+        self.sources = SourceKind::distinct(
+            &[SourceKind::Config, SourceKind::Cache],
+            &self.home,
+            &self.appname,
+        );
+        self.include = vec![
+            format!("{}/**", SourceKind::Config.archive_dir()),
+            format!(
+                "{}/{}/*",
+                SourceKind::Cache.archive_dir(),
+                PACKER_SNAPSHOT_DIR
+            ),
+        ];
+        self.exclude(&[])
+    }
+
+    fn exclude(mut self, globs: &[String]) -> Result<Self> {
+        self.exclude.extend_from_slice(globs);
+        self.filter = EntryFilter::new(&self.include, &self.exclude)?;
+        Ok(self)
+    }
+
+    /// How to archive symbolic links [default: [`Symlinks::Files`]].
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Make the archive depend on the archived files only: the creation time
+    /// in the manifest and the time stamps of entries without one of their
+    /// own come from `SOURCE_DATE_EPOCH`, or else the newest file, instead of
+    /// the clock. Archiving an unchanged tree again gives the same bytes.
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Split the archive into volumes of `volume_size` bytes, `<name>.zip`,
+    /// `<name>.zip.002`, ..., e.g. to stay below the 4 GiB file size limit
+    /// of FAT file systems.
+    pub fn split(mut self, volume_size: u64) -> Result<Self> {
+        if volume_size < MIN_VOLUME_SIZE {
+            return Err(Error::InvalidInput(format!(
+                "volumes must be at least {}",
+                ByteSize(MIN_VOLUME_SIZE)
+            )));
+        }
+        self.volume_size = volume_size;
+        Ok(self)
+    }
+
+    pub fn sources(&self) -> &[SourceKind] {
+        &self.sources
+    }
+
+    /// Every directory to archive, as `(name, path, archive path)`.
+    fn roots(&self) -> Vec<(String, PathBuf, String)> {
+        if self.dirs.is_empty() {
+            self.sources
+                .iter()
+                .map(|k| {
+                    let dir = k.dir(&self.home, &self.appname);
+                    (k.name().to_string(), dir, k.archive_dir().to_string())
+                })
+                .collect()
+        } else {
+            self.dirs
+                .iter()
+                .map(|d| (d.clone(), self.home.join(d), d.clone()))
+                .collect()
+        }
+    }
+
+    /// Every entry below `dir`, following links as [`Symlinks`] says.
+    fn entries(&self, dir: &Path) -> impl Iterator<Item = DirEntry> {
+        // sorted, so the archive doesn't depend on the order the file system lists entries in
+        WalkDir::new(dir)
+            .sort_by_file_name()
+            .follow_links(self.symlinks == Symlinks::Follow)
+            .into_iter()
+            .filter_map(Result::ok)
+    }
+
+    /// How `entry` goes into the archive.
+    fn kind(&self, entry: &DirEntry) -> EntryKind {
+        let file_type = entry.file_type();
+        if file_type.is_file() {
+            EntryKind::File
+        } else if file_type.is_dir() {
+            EntryKind::Dir
+        } else if !file_type.is_symlink() {
+            EntryKind::Skipped
+        } else if self.symlinks == Symlinks::Preserve {
+            EntryKind::Symlink
+        } else if entry.path().is_file() {
+            // with Follow, only broken links are still links here
+            EntryKind::File
+        } else {
+            EntryKind::Skipped
+        }
+    }
+
+    /// Every entry below the source directories, as `(path, archive path, kind)`.
+    fn walk(&self) -> impl Iterator<Item = (PathBuf, String, EntryKind)> + '_ {
+        self.roots()
+            .into_iter()
+            .flat_map(move |(_, dir, archive_dir)| {
+                self.entries(&dir)
+                    // the source directory itself is implied by the sources
+                    .filter(|e| e.depth() > 0)
+                    .map(move |e| {
+                        let kind = self.kind(&e);
+                        let rel = e.path().strip_prefix(&dir).unwrap_or(e.path());
+                        let name = archive_path(&archive_dir, rel);
+                        (e.into_path(), name, kind)
+                    })
+                    .filter(|(_, name, _)| self.filter.matches(name))
+            })
+    }
+
+    /// Size and checksum of every file that would be archived now, sorted by path.
+    pub fn scan(&self) -> Result<Vec<ManifestEntry>> {
+        let mut files = Vec::new();
+        for (path, name, kind) in self.walk() {
+            if kind != EntryKind::File {
+                continue;
+            }
+            let buf = fs::read(&path)?;
+            files.push(ManifestEntry {
+                path: name,
+                size: buf.len() as u64,
+                sha256: format!("{:x}", Sha256::digest(&buf)),
+            });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    /// What [`create`](Self::create) would archive right now, from file
+    /// metadata only.
+    pub fn preview(&self) -> Result<CreatePreview> {
+        let mut preview = CreatePreview::default();
+        let mut dirs: HashMap<String, u64> = HashMap::new();
+        for (name, dir, archive_dir) in self.roots() {
+            let (mut files, mut bytes) = (0, 0);
+            for entry in self.entries(&dir) {
+                let path = entry.path();
+                let rel = path.strip_prefix(&dir).unwrap_or(path);
+                if !self.filter.matches(&archive_path(&archive_dir, rel)) {
+                    continue;
+                }
+                match self.kind(&entry) {
+                    EntryKind::File => {
+                        let size = fs::metadata(path)?.len();
+                        files += 1;
+                        bytes += size;
+                        let parts: Vec<_> = rel.iter().map(|p| p.to_string_lossy()).collect();
+                        for depth in 1..parts.len().min(3) {
+                            let dir = format!("{}/{}", archive_dir, parts[..depth].join("/"));
+                            *dirs.entry(dir).or_default() += size;
+                        }
+                    }
+                    EntryKind::Symlink => files += 1,
+                    EntryKind::Dir => {}
+                    EntryKind::Skipped => preview.excluded.push(entry.into_path()),
+                }
+            }
+            preview.sources.push((name, dir, files, bytes));
+        }
+        preview.largest_dirs = largest(dirs.into_iter(), 5);
+        Ok(preview)
+    }
+
+    /// Write a new archive to `dest`, reporting one step per file.
+    ///
+    /// The returned manifest is also embedded in the archive as `manifest.json`.
+    pub fn create(&self, dest: &Path, progress: &dyn Progress) -> Result<Manifest> {
+        self.write(dest, false, progress)
+    }
+
+    /// Finish the capsule an interrupted [`create`](Self::create) of `dest`
+    /// left behind as `<dest>.part`. What it archived intact is taken from
+    /// there instead of being read from the source directories again, which
+    /// is the slow part on a slow disk or network mount.
+    pub fn resume(&self, dest: &Path, progress: &dyn Progress) -> Result<Manifest> {
+        if self.volume_size != u64::MAX {
+            return Err(Error::InvalidInput(
+                "a capsule split into volumes can't be resumed".to_string(),
+            ));
+        }
+        self.write(dest, true, progress)
+    }
+
+    fn write(&self, dest: &Path, resume: bool, progress: &dyn Progress) -> Result<Manifest> {
+        let (total, total_bytes, newest) = self.count();
+        progress.start(total, total_bytes);
+
+        // the archive is written under another name, with a journal to
+        // resume from unless it is split, and only takes its own name once
+        // finished
+        let split = self.volume_size != u64::MAX;
+        let part = part_path(dest);
+        let interrupted = with_suffix(&part, ".old");
+        if resume {
+            fs::rename(&part, &interrupted)?;
+            fs::rename(journal_path(&part), journal_path(&interrupted))?;
+        }
+        let mut writer = CapsuleWriter::create_split(&part, self.archive_dirs(), self.volume_size)?;
+        if !split {
+            writer.set_journal(Journal::create(&journal_path(&part))?);
+        }
+        self.describe(writer.manifest_mut());
+        if self.reproducible {
+            let time = source_date_epoch()
+                .unwrap_or(newest)
+                .max(UNIX_EPOCH + Duration::from_secs(ZIP_EPOCH_SECS));
+            writer.manifest_mut().created = DateTime::<Local>::from(time);
+            writer.manifest_mut().reproducible = true;
+            writer.set_default_mtime(time);
+        }
+        let done = if resume {
+            let done = replay(&interrupted, &mut writer, progress)?;
+            info!(capsule = %dest.display(), entries = done.len(), "resuming capsule");
+            done
+        } else {
+            HashSet::new()
+        };
+        for (path, name, kind) in self.walk() {
+            if done.contains(&name) {
+                continue;
+            }
+            match kind {
+                EntryKind::File => {
+                    let meta = fs::metadata(&path)?;
+                    let contents = fs::read(&path)?;
+                    writer.add_file_with(&name, &contents, mode(&meta), meta.modified()?)?;
+                    progress.inc(Path::new(&name), contents.len() as u64);
+                }
+                EntryKind::Symlink => {
+                    let target = fs::read_link(&path)?;
+                    writer.add_symlink(&name, &target.to_string_lossy())?;
+                    progress.inc(Path::new(&name), 0);
+                }
+                EntryKind::Dir => {
+                    let meta = fs::metadata(&path)?;
+                    writer.add_dir_with(&name, mode(&meta), meta.modified()?)?;
+                }
+                EntryKind::Skipped => {
+                    warn!(path = %path.display(), "left out: not a regular file or directory");
+                    continue;
+                }
+            }
+            trace!(file = %name, "archived");
+        }
+
+        let manifest = writer.finish()?;
+        if split {
+            for old in volume_paths(dest).iter().skip(1) {
+                fs::remove_file(old)?;
+            }
+            for (n, volume) in volume_paths(&part).iter().enumerate() {
+                fs::rename(volume, volume_path(dest, n + 1))?;
+            }
+        } else {
+            fs::rename(&part, dest)?;
+            fs::remove_file(journal_path(&part))?;
+        }
+        if resume {
+            fs::remove_file(journal_path(&interrupted))?;
+            fs::remove_file(&interrupted)?;
+        }
+        info!(
+            capsule = %dest.display(),
+            appname = %self.appname,
+            files = manifest.file_count,
+            bytes = manifest.total_size,
+            "created capsule"
+        );
+        progress.finish();
+        Ok(manifest)
+    }
+
+    /// Write a snapshot to the new directory `dest`: the files as they are,
+    /// uncompressed, with the manifest as `manifest.json`. Files unchanged
+    /// since `previous` are hard links into it rather than copies.
+    ///
+    /// Returns the manifest and how many files were linked.
+    pub fn snapshot(
+        &self,
+        dest: &Path,
+        previous: Option<&Snapshot>,
+        progress: &dyn Progress,
+    ) -> Result<(Manifest, u64)> {
+        let (total, total_bytes, _) = self.count();
+        progress.start(total, total_bytes);
+
+        let mut manifest = Manifest::new(self.archive_dirs());
+        self.describe(&mut manifest);
+        let earlier: HashMap<String, ManifestEntry> = match previous {
+            Some(snapshot) => snapshot
+                .manifest()?
+                .files
+                .into_iter()
+                .map(|e| (e.path.clone(), e))
+                .collect(),
+            None => HashMap::new(),
+        };
+        // left over by an interrupted run
+        let partial = partial_path(dest);
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        fs::create_dir_all(&partial)?;
+        let mut dirs = Vec::new();
+        let mut linked = 0;
+        for (path, name, kind) in self.walk() {
+            let target = partial.join(&name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match kind {
+                EntryKind::File => {
+                    let meta = fs::metadata(&path)?;
+                    let contents = fs::read(&path)?;
+                    let entry = ManifestEntry {
+                        path: name.clone(),
+                        size: contents.len() as u64,
+                        sha256: format!("{:x}", Sha256::digest(&contents)),
+                    };
+                    let unchanged = previous
+                        .filter(|_| earlier.get(&name).is_some_and(|e| e.sha256 == entry.sha256))
+                        .map(|s| s.path().join(&name))
+                        .filter(|old| same_file_metadata(old, &meta));
+                    // another file system gets a copy
+                    match unchanged {
+                        Some(old) if fs::hard_link(&old, &target).is_ok() => linked += 1,
+                        _ => {
+                            fs::write(&target, &contents)?;
+                            set_file_metadata(&target, mode(&meta), meta.modified()?)?;
+                        }
+                    }
+                    progress.inc(Path::new(&name), entry.size);
+                    manifest.push(entry);
+                }
+                EntryKind::Symlink => {
+                    let link = fs::read_link(&path)?.to_string_lossy().into_owned();
+                    symlink(&link, &target)?;
+                    manifest.symlinks.push(SymlinkEntry {
+                        path: name.clone(),
+                        target: link,
+                    });
+                    progress.inc(Path::new(&name), 0);
+                }
+                EntryKind::Dir => {
+                    fs::create_dir_all(&target)?;
+                    dirs.push((target, mode(&fs::metadata(&path)?)));
+                }
+                EntryKind::Skipped => {
+                    warn!(path = %path.display(), "left out: not a regular file or directory");
+                    continue;
+                }
+            }
+            trace!(file = %name, "archived");
+        }
+        // deepest first, so a read-only directory is only closed once it's filled
+        for (dir, mode) in dirs.iter().rev() {
+            set_mode(dir, *mode)?;
+        }
+        fs::write(partial.join(MANIFEST_NAME), manifest.to_json()?)?;
+        fs::rename(&partial, dest)?;
+        info!(
+            snapshot = %dest.display(),
+            appname = %self.appname,
+            files = manifest.file_count,
+            linked,
+            "created snapshot"
+        );
+        progress.finish();
+        Ok((manifest, linked))
+    }
+
+    /// Number and total size of the files to archive, and the newest
+    /// modification time among them.
+    fn count(&self) -> (u64, u64, SystemTime) {
+        self.walk().fold(
+            (0, 0, UNIX_EPOCH),
+            |(n, bytes, newest), (path, _, kind)| match kind {
+                EntryKind::File => {
+                    let meta = fs::metadata(path).ok();
+                    let size = meta.as_ref().map_or(0, |m| m.len());
+                    let mtime = meta.and_then(|m| m.modified().ok()).unwrap_or(UNIX_EPOCH);
+                    (n + 1, bytes + size, newest.max(mtime))
+                }
+                EntryKind::Symlink => (n + 1, bytes, newest),
+                EntryKind::Dir | EntryKind::Skipped => (n, bytes, newest),
+            },
+        )
+    }
+
+    /// The source directories recorded in the manifest, relative to HOME.
+    fn archive_dirs(&self) -> Vec<String> {
+        let mut sources: Vec<String> = self.roots().into_iter().map(|(_, _, d)| d).collect();
+        if self.plugins_only {
+            // the snapshots are layered into the cache on restore, not replacing it
+            sources.retain(|d| d != SourceKind::Cache.archive_dir());
+        }
+        sources
+    }
+
+    /// Fill in what the manifest says about where the files came from.
+    fn describe(&self, manifest: &mut Manifest) {
+        manifest.appname = self.appname.clone();
+        manifest.profile = self.profile.clone();
+        let mut environment = Environment::capture(&self.home, &self.appname);
+        if self.profile.is_some() {
+            // a profile's sources have nothing to do with Neovim's plugins
+            environment.lock_files.clear();
+        }
+        manifest.environment = Some(environment);
+        manifest.file_metadata = true;
+        manifest.portable = self.portable;
+        manifest.plugins_only = self.plugins_only;
+    }
+}
+
+/// 1980-01-01 in seconds since the epoch, the earliest time a zip entry can carry.
+const ZIP_EPOCH_SECS: u64 = 315_532_800;
+
+/// The build time set by `SOURCE_DATE_EPOCH`, as for other reproducible builds.
+fn source_date_epoch() -> Option<SystemTime> {
+    let secs = env::var("SOURCE_DATE_EPOCH").ok()?.trim().parse().ok()?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Unix permission bits of a file; read-only or not on other platforms.
+pub(crate) fn mode(meta: &fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        if meta.permissions().readonly() {
+            0o444
+        } else {
+            0o644
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn archives_what_a_profile_capsule_was_made_of() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        for file in [".config/tmux/tmux.conf", ".config/tmux/old.bak", ".tmux/x"] {
+            fs::create_dir_all(home.join(file).parent().unwrap()).unwrap();
+            fs::write(home.join(file), b"").unwrap();
+        }
+        let path = dir.path().join("tmux_capsule_20250513120000.zip");
+        let capsule = testing::capsule(&path, &[".config/tmux"], |w| {
+            w.manifest_mut().profile = Some("tmux".to_string());
+        });
+        let config = Config {
+            profiles: [(
+                "tmux".to_string(),
+                Profile {
+                    sources: vec!["~/.config/tmux".to_string(), "~/.tmux".to_string()],
+                    exclude: vec!["**/*.bak".to_string()],
+                    prefix: None,
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let files: Vec<String> = Archiver::for_capsule(&home, &capsule, &config)
+            .unwrap()
+            .scan()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(files, [".config/tmux/tmux.conf"]);
+    }
+
+    #[test]
+    fn names_split_volumes_once_finished() {
+        struct NothingNamed<'a>(&'a Path);
+        impl Progress for NothingNamed<'_> {
+            fn inc(&self, _item: &Path, _bytes: u64) {
+                assert!(!self.0.exists());
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        fs::create_dir_all(home.join(".tmux")).unwrap();
+        fs::write(home.join(".tmux/a"), [b'a'; 300]).unwrap();
+        fs::write(home.join(".tmux/b"), [b'b'; 300]).unwrap();
+        let profile = Profile {
+            sources: vec!["~/.tmux".to_string()],
+            exclude: Vec::new(),
+            prefix: None,
+        };
+        let mut archiver = Archiver::new(&home).profile("tmux", &profile).unwrap();
+        archiver.volume_size = 64;
+        let dest = dir.path().join("tmux_capsule_20250513120000.zip");
+        archiver.create(&dest, &NothingNamed(&dest)).unwrap();
+
+        let capsule = Capsule::from_path(&dest);
+        assert!(capsule.volumes().len() > 2);
+        assert_eq!(capsule.manifest().unwrap().unwrap().file_count, 2);
+        assert!(volume_paths(&part_path(&dest)).iter().all(|p| !p.exists()));
+    }
+}
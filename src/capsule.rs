@@ -0,0 +1,291 @@
+use crate::environment::Environment;
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+use crate::sources::{SourceKind, DEFAULT_APPNAME};
+use crate::volume::{volume_paths, Volumes};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zip::extra_fields::ExtraField;
+use zip::read::{ZipArchive, ZipFile};
+use zip::result::ZipError;
+
+/// Extension used by capsule archives.
+pub const CAPSULE_EXTENSION: &str = "zip";
+
+/// Summary of a capsule, as shown by `list`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CapsuleInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub created: Option<DateTime<Local>>,
+    /// Size of the archive itself, with all of its volumes.
+    pub size: u64,
+    pub file_count: u64,
+    pub format_version: u32,
+    pub appname: String,
+    pub tags: BTreeSet<String>,
+    pub note: Option<String>,
+    pub pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Environment>,
+}
+
+/// A single time capsule archive inside a [`CapsuleStore`](crate::CapsuleStore).
+#[derive(Debug, Clone)]
+pub struct Capsule {
+    path: PathBuf,
+    name: String,
+    modified: Option<SystemTime>,
+}
+
+impl Capsule {
+    /// Build a capsule from the path of an existing archive.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Capsule {
+            path,
+            name,
+            modified,
+        }
+    }
+
+    /// Whether `path` looks like a capsule archive.
+    pub fn is_capsule_path(path: &Path) -> bool {
+        path.extension() == Some(OsStr::new(CAPSULE_EXTENSION))
+    }
+
+    /// File name of the archive, e.g. `nvim_backup_20250513120000.zip`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Full path to the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Last modification time of the archive, if available.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Creation time encoded in the file name (`...%Y%m%d%H%M%S...`), if any.
+    pub fn timestamp_from_name(&self) -> Option<DateTime<Local>> {
+        let bytes = self.name.as_bytes();
+        (0..bytes.len().saturating_sub(13))
+            .filter(|&i| bytes[i..i + 14].iter().all(u8::is_ascii_digit))
+            .find_map(|i| NaiveDateTime::parse_from_str(&self.name[i..i + 14], "%Y%m%d%H%M%S").ok())
+            .and_then(|t| t.and_local_timezone(Local).single())
+    }
+
+    /// When the capsule was created, going by its name and falling back to the file mtime.
+    pub fn created(&self) -> Option<DateTime<Local>> {
+        self.timestamp_from_name()
+            .or_else(|| self.modified.map(DateTime::<Local>::from))
+    }
+
+    /// The archive followed by its other volumes, for a capsule written with
+    /// [`Archiver::split`](crate::Archiver::split).
+    pub fn volumes(&self) -> Vec<PathBuf> {
+        volume_paths(&self.path)
+    }
+
+    /// Size of the archive, with all of its volumes.
+    pub fn size(&self) -> Result<u64> {
+        let mut size = 0;
+        for path in self.volumes() {
+            size += fs::metadata(path)?.len();
+        }
+        Ok(size)
+    }
+
+    /// Write the whole archive to `out`, joining the volumes of a split
+    /// capsule, and return the bytes written.
+    pub fn write_archive(&self, out: &mut dyn Write) -> Result<u64> {
+        Ok(io::copy(&mut self.volumes_reader()?, out)?)
+    }
+
+    /// Open the archive for reading entries.
+    pub(crate) fn archive(&self) -> Result<ZipArchive<Volumes>> {
+        ZipArchive::new(self.volumes_reader()?).map_err(|source| self.bad_archive(source))
+    }
+
+    /// The volumes of the archive, read as one file. A volume cut short makes
+    /// the whole capsule unreadable.
+    fn volumes_reader(&self) -> Result<Volumes> {
+        Volumes::open(&self.path).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => {
+                self.bad_archive(ZipError::InvalidArchive(e.to_string().into()))
+            }
+            _ => e.into(),
+        })
+    }
+
+    fn bad_archive(&self, source: ZipError) -> Error {
+        Error::BadArchive {
+            path: self.path.clone(),
+            source,
+        }
+    }
+
+    /// Read the embedded manifest; `None` for legacy (v1) capsules.
+    pub fn manifest(&self) -> Result<Option<Manifest>> {
+        let mut archive = self.archive()?;
+        Manifest::read_from(&mut archive)
+    }
+
+    /// Capsule format version: 1 for legacy archives without a manifest.
+    pub fn format_version(&self) -> Result<u32> {
+        Ok(self.manifest()?.map_or(1, |m| m.format_version))
+    }
+
+    /// Name, size and file count, without reading any archived files.
+    pub fn info(&self) -> Result<CapsuleInfo> {
+        let size = self.size()?;
+        let meta = self.meta()?;
+        let mut archive = self.archive()?;
+        let (file_count, format_version, appname, environment) =
+            match Manifest::read_from(&mut archive)? {
+                Some(manifest) => (
+                    manifest.file_count,
+                    manifest.format_version,
+                    manifest.appname,
+                    manifest.environment,
+                ),
+                None => {
+                    let files = archive.file_names().filter(|n| !n.ends_with('/')).count();
+                    (files as u64, 1, DEFAULT_APPNAME.to_string(), None)
+                }
+            };
+        Ok(CapsuleInfo {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            created: self.created(),
+            size,
+            file_count,
+            format_version,
+            appname,
+            tags: meta.tags,
+            note: meta.note,
+            pinned: meta.pinned,
+            environment,
+        })
+    }
+
+    /// The `NVIM_APPNAME` the capsule was made from.
+    pub fn appname(&self) -> Result<String> {
+        let mut archive = self.archive()?;
+        Ok(Manifest::read_from(&mut archive)?
+            .map_or_else(|| DEFAULT_APPNAME.to_string(), |m| m.appname))
+    }
+
+    /// The Neovim directories this capsule was made of; all of them for
+    /// legacy capsules, which don't record it.
+    pub fn sources(&self) -> Result<Vec<SourceKind>> {
+        let mut archive = self.archive()?;
+        Ok(match Manifest::read_from(&mut archive)? {
+            Some(manifest) => SourceKind::ALL
+                .into_iter()
+                .filter(|k| manifest.sources.iter().any(|s| s == k.archive_dir()))
+                .collect(),
+            None => SourceKind::ALL.to_vec(),
+        })
+    }
+
+    /// Source directories of a profile capsule that aren't Neovim directories,
+    /// e.g. `.config/tmux`; empty for Neovim capsules.
+    pub fn profile_dirs(&self) -> Result<Vec<String>> {
+        Ok(self
+            .manifest()?
+            .map(|m| m.sources)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|s| SourceKind::ALL.iter().all(|k| k.archive_dir() != s))
+            .collect())
+    }
+
+    /// Size and checksum of every archived file, sorted by path.
+    ///
+    /// Taken from the manifest when there is one; legacy capsules are read
+    /// and hashed in full.
+    pub fn files(&self) -> Result<Vec<ManifestEntry>> {
+        let mut archive = self.archive()?;
+        let mut files = match Manifest::read_from(&mut archive)? {
+            Some(manifest) => manifest.files,
+            None => {
+                let mut files = Vec::new();
+                for i in 0..archive.len() {
+                    let mut entry = archive.by_index(i)?;
+                    if entry.is_dir() {
+                        continue;
+                    }
+                    let mut buf = Vec::new();
+                    entry.read_to_end(&mut buf)?;
+                    files.push(ManifestEntry {
+                        path: entry.name().to_string(),
+                        size: buf.len() as u64,
+                        sha256: format!("{:x}", Sha256::digest(&buf)),
+                    });
+                }
+                files
+            }
+        };
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    /// Contents of the archived file at `path` (relative to HOME).
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.copy_file(path, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Stream the archived file at `path` into `out`, returning the bytes written.
+    pub fn copy_file(&self, path: &str, out: &mut dyn Write) -> Result<u64> {
+        let mut archive = self.archive()?;
+        let not_found = || Error::NotInCapsule {
+            capsule: self.name.clone(),
+            path: path.to_string(),
+        };
+        if path == MANIFEST_NAME {
+            return Err(not_found());
+        }
+        let mut entry = match archive.by_name(path) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::FileNotFound) => return Err(not_found()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(io::copy(&mut entry, out)?)
+    }
+}
+
+/// Modification time of an archive entry: the extended timestamp if it has
+/// one, else the DOS timestamp taken as local time.
+pub(crate) fn entry_mtime<R: io::Read>(entry: &ZipFile<'_, R>) -> Option<SystemTime> {
+    let extended = entry.extra_data_fields().find_map(|f| match f {
+        ExtraField::ExtendedTimestamp(ts) => ts.mod_time(),
+        _ => None,
+    });
+    if let Some(secs) = extended {
+        return Some(UNIX_EPOCH + Duration::from_secs(secs.into()));
+    }
+    let t = entry.last_modified()?;
+    NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?
+        .and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(SystemTime::from)
+}
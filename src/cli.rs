@@ -0,0 +1,828 @@
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use clap_complete::Shell;
+use nvim_time_machine::{
+    Age, ByteSize, CapsuleStore, ConflictStrategy, Moment, NameTemplate, RetentionPolicy,
+    SourceKind, Symlinks,
+};
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "nvimTimeMachine", version)]
+#[command(about = "Manage Neovim time capsules", long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Never prompt: take the default answer, or fail where a choice is required
+    #[arg(short, long, global = true, env = "NVIM_TM_NONINTERACTIVE", value_parser = BoolishValueParser::new())]
+    pub yes: bool,
+
+    /// When to use colors
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Icons to decorate output with
+    #[arg(long, global = true, value_enum, env = "NVIM_TM_ICONS", default_value_t = IconSet::Ascii)]
+    pub icons: IconSet,
+
+    /// Log more to stderr (-v info, -vv debug, -vvv trace)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log errors to stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// How to report progress: an interactive bar, or JSON lines on stderr
+    #[arg(long, global = true, value_enum, default_value_t = ProgressMode::Bar)]
+    pub progress: ProgressMode,
+
+    /// Don't exclude the capsule store from Time Machine backups when it is created (macOS)
+    #[arg(long, global = true, env = "NVIM_TM_NO_TM_EXCLUDE", value_parser = BoolishValueParser::new())]
+    pub no_tm_exclude: bool,
+
+    /// Create a new capsule (same as `create`)
+    #[arg(short = 'c', long, hide = true)]
+    pub create_capsule: bool,
+
+    /// List existing capsules (same as `list`)
+    #[arg(short = 'l', long, hide = true)]
+    pub list_capsules: bool,
+
+    /// Restore from a capsule (same as `restore`)
+    #[arg(short = 'r', long, hide = true)]
+    pub restore_capsule: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Create a new capsule
+    Create(CreateArgs),
+
+    /// List existing capsules
+    List(ListArgs),
+
+    /// Restore from a capsule
+    Restore(RestoreArgs),
+
+    /// Show files added, removed or modified between two capsules
+    Diff {
+        /// Older capsule
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        a: String,
+
+        /// Newer capsule
+        #[arg(required_unless_present = "live", add = ArgValueCompleter::new(capsule_names))]
+        b: Option<String>,
+
+        /// Compare the capsule against the current Neovim directories
+        #[arg(long, conflicts_with = "b")]
+        live: bool,
+
+        /// Show unified diffs for changed text files
+        #[arg(long)]
+        content: bool,
+
+        /// Only compare these directories (data, config, cache, state)
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<SourceKind>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Show every version of a file across all capsules
+    History {
+        /// File path, relative to HOME or to a Neovim directory (e.g. `lua/plugins/lsp.lua`)
+        path: String,
+
+        /// Restore the version from the capsule given by `--at`
+        #[arg(long, requires = "at")]
+        restore: bool,
+
+        /// Capsule to restore the file from
+        #[arg(long, requires = "restore", add = ArgValueCompleter::new(capsule_names))]
+        at: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Search text files inside one or all capsules
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+
+        /// Capsule to search [default: all capsules]
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: Option<String>,
+
+        /// Match case-insensitively
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+
+        /// Treat the pattern as a literal string
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Match archived file paths instead of file contents
+        #[arg(long)]
+        name: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Print the directory tree of a capsule with sizes
+    Inspect {
+        /// Capsule name
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+
+        /// Only show this directory, relative to HOME or to a Neovim directory
+        subpath: Option<String>,
+
+        /// Maximum depth to descend
+        #[arg(short, long)]
+        depth: Option<usize>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Print a file from a capsule to stdout
+    Show {
+        /// Capsule name
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+
+        /// File path, relative to HOME or to a Neovim directory
+        path: String,
+    },
+
+    /// Export a capsule as a plain archive
+    Export {
+        /// Capsule name
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+
+        /// Archive format to write
+        #[arg(long, value_enum, default_value_t = ExportFormat::Tar)]
+        format: ExportFormat,
+
+        /// Output file [default: <capsule>.<format> in the current directory]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Unpack the config directory into this chezmoi source directory instead
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["format", "output"])]
+        chezmoi: Option<PathBuf>,
+
+        /// Unpack the config directory as a package of this GNU stow directory instead
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["format", "output", "chezmoi"])]
+        stow: Option<PathBuf>,
+    },
+
+    /// Copy capsules into the store of another machine over SSH, verifying their checksums there
+    Clone {
+        /// Remote from the config file, or a host such as user@laptop
+        remote: String,
+
+        /// Capsules to copy [default: all]
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsules: Vec<String>,
+
+        /// Only copy these directories of each capsule (data, config, cache, state)
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<SourceKind>,
+
+        /// Transfer at most this many bytes per second, e.g. 500K
+        #[arg(long, value_name = "RATE", env = "NVIM_TM_LIMIT_RATE")]
+        limit_rate: Option<ByteSize>,
+    },
+
+    /// Adopt a zip, tar or tar.gz archive of an nvim setup as a new capsule
+    Import {
+        /// Archive to import
+        #[arg(required_unless_present = "from_git")]
+        path: Option<PathBuf>,
+
+        /// Clone this git repository (URL or path) of an nvim config and import it instead
+        #[arg(long, value_name = "REPO", conflicts_with = "path")]
+        from_git: Option<String>,
+    },
+
+    /// Extract a single file from a capsule
+    RestoreFile {
+        /// Capsule name
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+
+        /// File path, relative to HOME or to a Neovim directory
+        path: String,
+
+        /// Write the file here instead of its original location
+        #[arg(long)]
+        to: Option<PathBuf>,
+    },
+
+    /// Mount a capsule (or every capsule) as a read-only file system to browse it
+    Mount {
+        /// Capsule name
+        #[arg(required_unless_present = "store", add = ArgValueCompleter::new(capsule_names))]
+        capsule: Option<String>,
+
+        /// Directory to mount it on, created if missing
+        #[arg(required_unless_present = "store")]
+        mountpoint: Option<PathBuf>,
+
+        /// Mount all capsules here instead, each in a directory named after it
+        #[arg(long, value_name = "MOUNTPOINT", conflicts_with_all = ["capsule", "mountpoint"])]
+        store: Option<PathBuf>,
+    },
+
+    /// Unmount what `mount` mounted
+    Unmount {
+        /// Directory given to `mount` or `mount --store`
+        mountpoint: PathBuf,
+    },
+
+    /// Restore a capsule into an isolated NVIM_APPNAME profile to try it out
+    Try {
+        /// Capsule name
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+
+        /// Profile to restore into (`~/.config/<APPNAME>`, ...)
+        #[arg(long, default_value = "nvim-tm-preview")]
+        appname: String,
+
+        /// Launch Neovim with the profile afterwards
+        #[arg(long)]
+        launch: bool,
+    },
+
+    /// Delete capsules, picked from a list or with the options below
+    Delete {
+        /// Capsule to delete (repeatable)
+        #[arg(long, add = ArgValueCompleter::new(capsule_names))]
+        name: Vec<String>,
+
+        /// Number shown by `list` of a capsule to delete (repeatable)
+        #[arg(long)]
+        index: Vec<usize>,
+
+        /// Delete every capsule older than this, e.g. 30d, 2w or 1y; pinned ones are kept
+        #[arg(long)]
+        older_than: Option<Age>,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Give a capsule a meaningful name
+    Rename {
+        /// Capsule to rename
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        old: String,
+
+        /// New file name; `.zip` is added if left out
+        new: String,
+    },
+
+    /// Combine two capsules into one, the newer version of a file winning
+    Merge {
+        /// First capsule
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        a: String,
+
+        /// Second capsule
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        b: String,
+
+        /// File name of the merged capsule; `.zip` is added if left out
+        #[arg(short = 'o', long = "name", value_name = "NAME")]
+        name: String,
+    },
+
+    /// Add, remove or show capsule tags
+    #[command(subcommand)]
+    Tag(TagCommand),
+
+    /// Summarize the last capsule, changes since then and the store
+    Status {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Read capsules in full to detect damaged or truncated archives
+    #[command(group = ArgGroup::new("target").required(true))]
+    Verify {
+        /// Capsule to verify
+        #[arg(group = "target", add = ArgValueCompleter::new(capsule_names))]
+        capsule: Option<String>,
+
+        /// Verify every capsule in the store
+        #[arg(long, group = "target")]
+        all: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Browse, create, restore, diff, delete and tag capsules full-screen
+    Tui,
+
+    /// Check the environment and every stored capsule for problems
+    Doctor {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Show where the space in the capsule store goes
+    Stats {
+        /// How many of the largest files and directories to show
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    /// Delete capsules according to retention rules
+    Prune(PruneArgs),
+
+    /// Protect capsules from `delete --older-than` and pruning
+    Pin {
+        #[arg(required = true, add = ArgValueCompleter::new(capsule_names))]
+        capsules: Vec<String>,
+    },
+
+    /// Remove the protection added by `pin`
+    Unpin {
+        #[arg(required = true, add = ArgValueCompleter::new(capsule_names))]
+        capsules: Vec<String>,
+    },
+
+    /// Attach a note to a capsule
+    #[command(subcommand)]
+    Note(NoteCommand),
+
+    /// Upgrade legacy capsules to the current format
+    Migrate,
+
+    /// Print shell completions to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: Shell,
+    },
+
+    /// Write man pages for all commands
+    #[command(hide = true)]
+    Mangen {
+        /// Directory to write the pages into
+        #[arg(long, default_value = "man")]
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TagCommand {
+    /// Tag a capsule, e.g. `tag add <CAPSULE> stable`
+    Add {
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Remove tags from a capsule
+    Remove {
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Show the tags of a capsule, or every tag in use with its capsules
+    List {
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NoteCommand {
+    /// Edit a capsule's note in $VISUAL/$EDITOR
+    Edit {
+        #[arg(add = ArgValueCompleter::new(capsule_names))]
+        capsule: String,
+
+        /// Set the note to this instead of opening an editor ("" removes it)
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+}
+
+#[derive(Args)]
+#[command(group = ArgGroup::new("rules").multiple(true).required(true))]
+pub struct PruneArgs {
+    #[command(flatten)]
+    pub retention: RetentionArgs,
+
+    /// Show what would be kept and pruned without deleting anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+/// Retention rules; a capsule is kept if any of them keeps it.
+#[derive(Args, Default)]
+pub struct RetentionArgs {
+    /// Keep the N most recent capsules
+    #[arg(long, value_name = "N", group = "rules")]
+    pub keep_last: Option<usize>,
+
+    /// Keep the newest capsule of each of the last N days
+    #[arg(long, value_name = "N", group = "rules")]
+    pub keep_daily: Option<usize>,
+
+    /// Keep the newest capsule of each of the last N weeks
+    #[arg(long, value_name = "N", group = "rules")]
+    pub keep_weekly: Option<usize>,
+
+    /// Keep the newest capsule of each of the last N months
+    #[arg(long, value_name = "N", group = "rules")]
+    pub keep_monthly: Option<usize>,
+
+    /// Keep every capsule younger than this, e.g. 30d
+    #[arg(long, value_name = "AGE", group = "rules")]
+    pub keep_within: Option<Age>,
+
+    /// Then drop the oldest kept capsules until the rest fit, e.g. 5G
+    #[arg(long, value_name = "SIZE", group = "rules")]
+    pub max_total_size: Option<ByteSize>,
+}
+
+impl CreateArgs {
+    pub fn symlinks(&self) -> Symlinks {
+        if self.follow_symlinks {
+            Symlinks::Follow
+        } else if self.keep_symlinks {
+            Symlinks::Preserve
+        } else {
+            Symlinks::Files
+        }
+    }
+}
+
+impl RetentionArgs {
+    pub fn policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            keep_last: self.keep_last,
+            keep_daily: self.keep_daily,
+            keep_weekly: self.keep_weekly,
+            keep_monthly: self.keep_monthly,
+            keep_within: self.keep_within,
+            max_total_size: self.max_total_size,
+        }
+    }
+}
+
+#[derive(Args, Default)]
+pub struct ListArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Only list capsules with this tag
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// Only list capsules of this profile from the config file
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Order to list capsules in
+    #[arg(long, value_enum, default_value_t = SortKey::Date)]
+    pub sort: SortKey,
+
+    /// Reverse the order, e.g. newest or largest first
+    #[arg(short, long)]
+    pub reverse: bool,
+
+    /// Show at most this many capsules
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Also show the Neovim version, OS, architecture and host each capsule was created on
+    #[arg(long)]
+    pub long: bool,
+
+    /// List the capsules of this remote (or SSH host) instead
+    #[arg(long, value_name = "REMOTE", conflicts_with_all = ["tag", "profile", "long"])]
+    pub remote: Option<String>,
+
+    /// List the hard-link snapshots written by `create --snapshot` instead
+    #[arg(long, conflicts_with_all = ["tag", "profile", "long", "remote"])]
+    pub snapshots: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum SortKey {
+    /// Creation time, oldest first
+    #[default]
+    Date,
+    /// Archive size, smallest first
+    Size,
+    /// File name
+    Name,
+}
+
+#[derive(Args, Default)]
+#[command(group = ArgGroup::new("rules").multiple(true).requires("prune"))]
+pub struct CreateArgs {
+    /// Note describing the capsule, shown by `list` and `restore`
+    #[arg(short, long)]
+    pub message: Option<String>,
+
+    /// Report what would be archived without writing a capsule
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Back up the profile of this NVIM_APPNAME (`~/.config/<NAME>`, ...) [default: nvim]
+    #[arg(long, value_name = "NAME")]
+    pub appname: Option<String>,
+
+    /// Create one capsule for every profile with an init.lua or init.vim
+    #[arg(long, conflicts_with = "appname")]
+    pub all_appnames: bool,
+
+    /// Back up the sources of this profile from the config file instead
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["appname", "all_appnames"])]
+    pub profile: Option<String>,
+
+    /// Archive what symbolic links point to, including linked directories
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Archive symbolic links as links, recreated as such on restore
+    #[arg(long, conflicts_with = "follow_symlinks")]
+    pub keep_symlinks: bool,
+
+    /// Take time stamps from the files (or SOURCE_DATE_EPOCH), so an unchanged tree gives a byte-identical capsule
+    #[arg(long)]
+    pub reproducible: bool,
+
+    /// Leave out mason binaries, compiled tree-sitter parsers and other native libraries
+    #[arg(long)]
+    pub portable: bool,
+
+    /// Only archive the config directory and plugin lock files (lazy-lock.json, packer snapshots);
+    /// restoring it reinstalls the plugins
+    #[arg(long, conflicts_with = "profile")]
+    pub plugins_only: bool,
+
+    /// Split the capsule into volumes of at most this size, e.g. 1G; restore and verify join them
+    #[arg(long, value_name = "SIZE")]
+    pub split_size: Option<ByteSize>,
+
+    /// File name pattern of the capsule, e.g. "{hostname}-{profile}-{date}-{note-slug}"
+    /// [default: name_template from the config file, or "{prefix}{date}"]
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name: Option<NameTemplate>,
+
+    /// Refuse to create a capsule bigger than this before compression, e.g. 2G, unless confirmed
+    /// [default: max_size from the config file]
+    #[arg(long, value_name = "SIZE")]
+    pub max_size: Option<ByteSize>,
+
+    /// Continue the capsule an interrupted `create` left behind, if there is one,
+    /// instead of starting over
+    #[arg(long, conflicts_with_all = ["split_size", "snapshot"])]
+    pub resume: bool,
+
+    /// Write an uncompressed directory tree under snapshots/ in the store instead of an archive;
+    /// files unchanged since the previous snapshot are hard links to it
+    #[arg(long, conflicts_with_all = ["split_size", "reproducible", "verify", "message"])]
+    pub snapshot: bool,
+
+    /// Re-read the finished capsule and compare it with the checksums taken while archiving
+    #[arg(long, conflicts_with = "dry_run")]
+    pub verify: bool,
+
+    /// Afterwards, delete the capsules of the same profile the given retention rules don't keep
+    #[arg(long, requires = "rules", conflicts_with = "dry_run")]
+    pub prune: bool,
+
+    #[command(flatten)]
+    pub retention: RetentionArgs,
+}
+
+#[derive(Args, Default)]
+#[command(group = ArgGroup::new("selection"))]
+pub struct RestoreArgs {
+    /// Restore the most recent capsule without asking
+    #[arg(long, group = "selection")]
+    pub latest: bool,
+
+    /// Restore the capsule with this file name without asking
+    #[arg(long, group = "selection", add = ArgValueCompleter::new(capsule_names))]
+    pub name: Option<String>,
+
+    /// Restore the capsule with this number from `list` without asking
+    #[arg(long, group = "selection")]
+    pub index: Option<usize>,
+
+    /// Restore the newest capsule from this time or earlier, e.g. "2 weeks ago" or 2024-03-01
+    #[arg(long, group = "selection", value_name = "WHEN")]
+    pub at: Option<Moment>,
+
+    /// Restore the snapshot with this name (or `latest`) from `list --snapshots`
+    #[arg(long, group = "selection", value_name = "NAME", conflicts_with_all = ["tag", "profile"])]
+    pub snapshot: Option<String>,
+
+    /// Only offer capsules with this tag; with --latest, the newest one
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// Pick the capsule from this remote (or SSH host) and download only that one
+    #[arg(long, value_name = "REMOTE", conflicts_with_all = ["tag", "snapshot"])]
+    pub remote: Option<String>,
+
+    /// With --remote, download at most this many bytes per second, e.g. 500K
+    #[arg(long, value_name = "RATE", env = "NVIM_TM_LIMIT_RATE")]
+    pub limit_rate: Option<ByteSize>,
+
+    /// Only offer capsules of this profile from the config file; with --latest, the newest one
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Restore into the profile of this NVIM_APPNAME [default: the one the capsule was made from]
+    #[arg(long, value_name = "NAME")]
+    pub appname: Option<String>,
+
+    /// Only restore these directories (data, config, cache, state) [default: ask]
+    #[arg(long, value_delimiter = ',')]
+    pub only: Vec<SourceKind>,
+
+    /// Only extract entries matching this glob (repeatable), e.g. 'lua/plugins/**'
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Skip entries matching this glob (repeatable), e.g. '**/lazy-lock.json'
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Extract under this directory instead of HOME, leaving the live setup untouched
+    #[arg(long)]
+    pub to_dir: Option<PathBuf>,
+
+    /// Layer the capsule over the current tree instead of replacing the directories:
+    /// overwrite, skip-existing, merge (keep files changed since the capsule) or prompt
+    #[arg(long)]
+    pub strategy: Option<ConflictStrategy>,
+
+    /// Number of extraction workers [default: one per CPU]
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+
+    /// Report what would be created, overwritten and removed without touching anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Don't run the plugin manager after restoring a --plugins-only capsule
+    #[arg(long)]
+    pub no_sync: bool,
+
+    /// Don't ask for confirmation, and restore even if the capsule comes from
+    /// another OS, architecture or Neovim release
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum IconSet {
+    /// Plain ASCII
+    Ascii,
+    /// Nerd Font glyphs
+    Nerd,
+}
+
+impl IconSet {
+    /// Marker in front of each capsule in `list`.
+    pub fn capsule(self) -> &'static str {
+        match self {
+            IconSet::Ascii => "*",
+            IconSet::Nerd => "\u{f017}",
+        }
+    }
+
+    /// Marker after pinned capsules.
+    pub fn pinned(self) -> &'static str {
+        match self {
+            IconSet::Ascii => "(pinned)",
+            IconSet::Nerd => "\u{f0403}",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bar
+    Bar,
+    /// Newline-delimited JSON events
+    Json,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON, for scripts
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Plain tar, without the capsule manifest
+    Tar,
+    /// The capsule zip itself
+    Zip,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Tar => "tar",
+            ExportFormat::Zip => "zip",
+        }
+    }
+}
+
+impl Cli {
+    /// Whether the command works with the store, and so logs to a file in
+    /// it; printing completions or man pages leaves HOME alone.
+    pub fn uses_store(&self) -> bool {
+        match &self.command {
+            Some(Command::Completions { .. } | Command::Mangen { .. }) => false,
+            Some(_) => true,
+            None => self.create_capsule || self.list_capsules || self.restore_capsule,
+        }
+    }
+
+    /// The subcommand to run, mapping the legacy `-c`/`-l`/`-r` flags.
+    pub fn into_command(self) -> Option<Command> {
+        if self.command.is_some() {
+            self.command
+        } else if self.create_capsule {
+            Some(Command::Create(CreateArgs::default()))
+        } else if self.list_capsules {
+            Some(Command::List(ListArgs::default()))
+        } else if self.restore_capsule {
+            Some(Command::Restore(RestoreArgs::default()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The capsules in the store whose name starts with `current`, newest first,
+/// for completions registered with `COMPLETE=<shell> nvimTimeMachine`.
+fn capsule_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    let current = current.to_string_lossy();
+    let capsules = CapsuleStore::in_home(&home).list().unwrap_or_default();
+    capsules
+        .iter()
+        .rev()
+        .filter(|c| c.name().starts_with(&*current))
+        .map(|c| CompletionCandidate::new(c.name()))
+        .collect()
+}
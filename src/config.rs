@@ -0,0 +1,238 @@
+use crate::capsule::Capsule;
+use crate::error::{Error, Result};
+use crate::naming::{CapsuleNaming, NameTemplate};
+use crate::size::ByteSize;
+use crate::sources::SourceKind;
+use crate::store::capsule_prefix;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory of the configuration file, next to Neovim's own `~/.config/nvim`.
+const CONFIG_DIR_NAME: &str = "nvimTimeMachine";
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Settings read from `~/.config/nvimTimeMachine/config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Backup profiles by name, from `[profiles.<name>]` tables.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Other machines to copy capsules to, from `[remotes.<name>]` tables.
+    #[serde(default)]
+    pub remotes: BTreeMap<String, Remote>,
+    /// Commit the config directory to a git repository in the store with
+    /// every capsule (`git_history = true`).
+    #[serde(default)]
+    pub git_history: bool,
+    /// The largest capsule `create` makes without asking, e.g.
+    /// `max_size = "2G"`; `create --max-size` overrides it.
+    #[serde(default)]
+    pub max_size: Option<ByteSize>,
+    /// File name pattern of new capsules, e.g.
+    /// `name_template = "{hostname}-{profile}-{date}"`; `create --name`
+    /// overrides it.
+    #[serde(default)]
+    pub name_template: Option<NameTemplate>,
+}
+
+/// A set of directories backed up into their own timeline of capsules,
+/// instead of the Neovim directories.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Directories relative to HOME (a leading `~/` is allowed), e.g.
+    /// `~/.config/tmux`.
+    pub sources: Vec<String>,
+    /// Globs of archive paths to leave out, as for `restore --exclude`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// File name prefix of the profile's capsules [default: `<name>_capsule_`].
+    pub prefix: Option<String>,
+}
+
+/// A machine with a capsule store of its own, reached over SSH.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Remote {
+    /// `user@host`, or a host alias from `~/.ssh/config`.
+    pub host: String,
+    /// Store directory there [default: `~/.nvim_capsules`].
+    pub dir: Option<String>,
+}
+
+impl Config {
+    /// Where the configuration file of `home` lives.
+    pub fn path(home: &Path) -> PathBuf {
+        SourceKind::Config
+            .dir(home, CONFIG_DIR_NAME)
+            .join(CONFIG_FILE_NAME)
+    }
+
+    /// Read the configuration file; without one, everything is at its default.
+    pub fn load(home: &Path) -> Result<Self> {
+        let path = Self::path(home);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let config: Config = toml::from_str(&text)
+            .map_err(|e| Error::InvalidInput(format!("{}: {}", path.display(), e)))?;
+        for (name, profile) in &config.profiles {
+            profile.check(name)?;
+        }
+        Ok(config)
+    }
+
+    /// How the capsules with the file name prefix `prefix` of the profile or
+    /// `NVIM_APPNAME` called `name` are named, following `name_template`.
+    pub fn naming(&self, prefix: &str, name: &str) -> CapsuleNaming {
+        CapsuleNaming::new(self.name_template.clone().unwrap_or_default(), prefix, name)
+    }
+
+    /// The names of the capsules of the profile `name`, from any machine.
+    pub fn profile_naming(&self, name: &str) -> Result<CapsuleNaming> {
+        Ok(self
+            .naming(&self.profile(name)?.prefix(name), name)
+            .any_host())
+    }
+
+    /// The profile a capsule was made of, by name, with the directories the
+    /// capsule recorded and what the profile of that name leaves out and calls
+    /// its capsules today; `None` for a capsule of Neovim directories.
+    pub fn capsule_profile(&self, capsule: &Capsule) -> Result<Option<(String, Profile)>> {
+        let dirs = capsule.profile_dirs()?;
+        if dirs.is_empty() {
+            return Ok(None);
+        }
+        let name = capsule
+            .manifest()?
+            .and_then(|m| m.profile)
+            .unwrap_or_else(|| "profile".to_string());
+        let configured = self.profiles.get(&name).cloned().unwrap_or_default();
+        let profile = Profile {
+            sources: dirs,
+            ..configured
+        };
+        Ok(Some((name, profile)))
+    }
+
+    /// The names of the timeline `capsule` belongs to: that of the profile it
+    /// was made of, or else that of its `NVIM_APPNAME`.
+    pub fn capsule_naming(&self, capsule: &Capsule) -> Result<CapsuleNaming> {
+        Ok(match self.capsule_profile(capsule)? {
+            Some((name, profile)) => self.naming(&profile.prefix(&name), &name),
+            None => {
+                let appname = capsule.appname()?;
+                self.naming(&capsule_prefix(&appname), &appname)
+            }
+        })
+    }
+
+    /// The profile called `name`.
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            Error::InvalidInput(format!(
+                "no profile \"{}\" in the config file (defined: {})",
+                name,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            ))
+        })
+    }
+}
+
+impl Profile {
+    /// File name prefix of the capsules of the profile called `name`.
+    pub fn prefix(&self, name: &str) -> String {
+        self.prefix
+            .clone()
+            .unwrap_or_else(|| format!("{}_capsule_", name))
+    }
+
+    /// The source directories as archive paths, e.g. `.config/tmux`.
+    pub fn dirs(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .map(|s| s.trim_start_matches("~/").trim_end_matches('/').to_string())
+            .collect()
+    }
+
+    fn check(&self, name: &str) -> Result<()> {
+        let invalid = |what: String| {
+            Err(Error::InvalidInput(format!(
+                "profile \"{}\": {}",
+                name, what
+            )))
+        };
+        if self.sources.is_empty() {
+            return invalid("no sources".to_string());
+        }
+        for dir in self.dirs() {
+            let path = Path::new(&dir);
+            if dir.is_empty() || path.is_absolute() || dir.split('/').any(|p| p == "..") {
+                return invalid(format!(
+                    "source \"{}\" must be a directory inside HOME",
+                    dir
+                ));
+            }
+        }
+        if self.prefix(name).contains(['/', '\\']) {
+            return invalid("the prefix can't contain a path separator".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn config() -> Config {
+        Config {
+            profiles: [(
+                "tmux".to_string(),
+                Profile {
+                    sources: vec!["~/.config/tmux".to_string(), "~/.tmux".to_string()],
+                    exclude: vec!["**/*.bak".to_string()],
+                    prefix: Some("tmux-".to_string()),
+                },
+            )]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_the_profile_of_a_capsule() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tmux-20250513120000.zip");
+        let capsule = testing::capsule(&path, &[".config/tmux"], |w| {
+            w.manifest_mut().profile = Some("tmux".to_string());
+        });
+        let (name, profile) = config().capsule_profile(&capsule).unwrap().unwrap();
+        assert_eq!(name, "tmux");
+        // the directories the capsule has, not those configured now
+        assert_eq!(profile.dirs(), [".config/tmux"]);
+        assert_eq!(profile.exclude, ["**/*.bak"]);
+        assert_eq!(profile.prefix(&name), "tmux-");
+    }
+
+    #[test]
+    fn a_neovim_capsule_has_no_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvim_capsule_20250513120000.zip");
+        let capsule = testing::capsule(&path, &[".config/nvim", ".local/share/nvim"], |_| {});
+        assert!(config().capsule_profile(&capsule).unwrap().is_none());
+    }
+}
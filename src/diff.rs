@@ -0,0 +1,104 @@
+use crate::manifest::ManifestEntry;
+use serde::Serialize;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+
+/// How a file differs between two file lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A single changed path.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub change: Change,
+    /// The file on the old side, if present there.
+    pub old: Option<ManifestEntry>,
+    /// The file on the new side, if present there.
+    pub new: Option<ManifestEntry>,
+}
+
+/// Counts of a list of changes, as shown after `create`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DiffStat {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+    /// Bytes gained (or lost, if negative) over all changed files.
+    pub delta_bytes: i64,
+}
+
+impl DiffStat {
+    pub fn of(changes: &[FileChange]) -> Self {
+        let mut stat = DiffStat::default();
+        for change in changes {
+            match change.change {
+                Change::Added => stat.added += 1,
+                Change::Modified => stat.modified += 1,
+                Change::Removed => stat.removed += 1,
+            }
+            let size = |e: &Option<ManifestEntry>| e.as_ref().map_or(0, |e| e.size as i64);
+            stat.delta_bytes += size(&change.new) - size(&change.old);
+        }
+        stat
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added + self.modified + self.removed == 0
+    }
+}
+
+/// Compare two file lists by path and checksum, sorted by path.
+pub fn diff_files(old: &[ManifestEntry], new: &[ManifestEntry]) -> Vec<FileChange> {
+    let mut paths: BTreeMap<&str, (Option<&ManifestEntry>, Option<&ManifestEntry>)> =
+        BTreeMap::new();
+    for entry in old {
+        paths.entry(&entry.path).or_default().0 = Some(entry);
+    }
+    for entry in new {
+        paths.entry(&entry.path).or_default().1 = Some(entry);
+    }
+
+    paths
+        .into_iter()
+        .filter_map(|(path, (old, new))| {
+            let change = match (old, new) {
+                (None, Some(_)) => Change::Added,
+                (Some(_), None) => Change::Removed,
+                (Some(a), Some(b)) if a.sha256 != b.sha256 => Change::Modified,
+                _ => return None,
+            };
+            Some(FileChange {
+                path: path.to_string(),
+                change,
+                old: old.cloned(),
+                new: new.cloned(),
+            })
+        })
+        .collect()
+}
+
+/// Whether `contents` looks like text: valid UTF-8 without NUL bytes.
+pub fn is_text(contents: &[u8]) -> bool {
+    !contents.contains(&0) && std::str::from_utf8(contents).is_ok()
+}
+
+/// A unified diff of two versions of a text file; `None` if either is binary.
+pub fn unified_diff(old_label: &str, new_label: &str, old: &[u8], new: &[u8]) -> Option<String> {
+    if !is_text(old) || !is_text(new) {
+        return None;
+    }
+    let old = std::str::from_utf8(old).ok()?;
+    let new = std::str::from_utf8(new).ok()?;
+    Some(
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(old_label, new_label)
+            .to_string(),
+    )
+}
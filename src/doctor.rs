@@ -0,0 +1,356 @@
+use crate::archiver::Archiver;
+use crate::error::Error;
+use crate::sources::{SourceKind, DEFAULT_APPNAME};
+use crate::store::CapsuleStore;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Outcome of a single [`Check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// One environment check run by `doctor`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a warning or failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        status: CheckStatus,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Check that capsules can be created in and restored from `store` for the
+/// Neovim directories under `home`.
+pub fn run_checks(home: &Path, store: &CapsuleStore) -> Vec<Check> {
+    let mut checks = vec![Check::ok("home", home.display().to_string())];
+    checks.extend(SourceKind::ALL.iter().map(|&kind| check_source(home, kind)));
+    checks.push(check_store_writable(store));
+    checks.push(check_free_space(home, store));
+    checks.push(check_lock(store));
+    if cfg!(target_os = "macos") {
+        checks.push(check_time_machine(store));
+    }
+    checks.push(check_leftovers(home));
+    checks.extend(check_capsules(store));
+    checks
+}
+
+/// Where a scheduled `nvimTimeMachine create` is set up, if anywhere: the
+/// user's crontab, a systemd user timer or a launchd agent.
+pub fn find_schedule(home: &Path) -> Option<String> {
+    let crontab = Command::new("crontab").arg("-l").output().ok();
+    if crontab.is_some_and(|out| runs_us(&String::from_utf8_lossy(&out.stdout))) {
+        return Some("crontab".to_string());
+    }
+    scheduler_file(home).map(|p| p.display().to_string())
+}
+
+/// Whether a line of `crontab` that isn't commented out runs nvimTimeMachine.
+fn runs_us(crontab: &str) -> bool {
+    crontab
+        .lines()
+        .any(|l| !l.trim_start().starts_with('#') && l.contains("nvimTimeMachine"))
+}
+
+/// A systemd user unit or launchd agent of nvimTimeMachine below `home`.
+fn scheduler_file(home: &Path) -> Option<PathBuf> {
+    [".config/systemd/user", "Library/LaunchAgents"]
+        .iter()
+        .flat_map(|dir| fs::read_dir(home.join(dir)).into_iter().flatten())
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.to_string_lossy().contains("nvimTimeMachine"))
+}
+
+fn check_source(home: &Path, kind: SourceKind) -> Check {
+    let name = format!("{} dir", kind);
+    let dir = kind.dir(home, DEFAULT_APPNAME);
+    if !dir.exists() {
+        return Check::problem(
+            CheckStatus::Warn,
+            name,
+            format!("{} does not exist", dir.display()),
+            "nothing to back up there; ignore this if you don't use it",
+        );
+    }
+    let unreadable: Vec<String> = WalkDir::new(&dir)
+        .into_iter()
+        .filter_map(|e| e.err())
+        .map(|e| {
+            e.path()
+                .map_or_else(|| e.to_string(), |p| p.display().to_string())
+        })
+        .collect();
+    match unreadable.first() {
+        None => Check::ok(name, format!("{} is readable", dir.display())),
+        Some(first) => Check::problem(
+            CheckStatus::Fail,
+            name,
+            format!("{} unreadable entries, e.g. {}", unreadable.len(), first),
+            format!(
+                "fix the permissions, e.g. `chmod -R u+rX {}`",
+                dir.display()
+            ),
+        ),
+    }
+}
+
+fn check_store_writable(store: &CapsuleStore) -> Check {
+    let dir = store.dir();
+    // the store is created on first use, so its parent has to be writable
+    let probe_dir = if dir.exists() {
+        dir
+    } else {
+        dir.parent().unwrap_or(dir)
+    };
+    let probe = probe_dir.join(".nvim-tm-doctor");
+    match fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe)) {
+        Ok(()) => Check::ok("store", format!("{} is writable", dir.display())),
+        Err(e) => Check::problem(
+            CheckStatus::Fail,
+            "store",
+            format!("can't write to {}: {}", probe_dir.display(), e),
+            format!("make it writable, e.g. `chmod u+w {}`", probe_dir.display()),
+        ),
+    }
+}
+
+fn check_free_space(home: &Path, store: &CapsuleStore) -> Check {
+    let dir = if store.exists() { store.dir() } else { home };
+    let Some(free) = free_space(dir) else {
+        return Check::problem(
+            CheckStatus::Warn,
+            "free space",
+            "could not be determined (`df` failed)",
+            "check the free space of the store's disk yourself",
+        );
+    };
+    // the next capsule will be about as big as the last one
+    let needed = match store.latest() {
+        Ok(capsule) => capsule.size().unwrap_or(0),
+        Err(_) => Archiver::new(home).preview().map_or(0, |p| p.total_size()),
+    };
+    let detail = format!(
+        "{} MiB free, about {} MiB needed per capsule",
+        free >> 20,
+        needed >> 20
+    );
+    if free < needed {
+        Check::problem(
+            CheckStatus::Fail,
+            "free space",
+            detail,
+            "free some space, e.g. with `nvimTimeMachine prune`",
+        )
+    } else {
+        Check::ok("free space", detail)
+    }
+}
+
+/// Free bytes on the file system holding `dir`, as reported by `df`.
+fn free_space(dir: &Path) -> Option<u64> {
+    let out = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let kib: u64 = stdout
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+fn check_lock(store: &CapsuleStore) -> Check {
+    if !store.exists() {
+        return Check::ok("lock", "store not created yet");
+    }
+    match store.lock() {
+        Ok(_) => Check::ok("lock", "free"),
+        Err(Error::LockHeld(_)) => Check::problem(
+            CheckStatus::Warn,
+            "lock",
+            "held by another nvimTimeMachine process",
+            "wait for it to finish; the lock is released when that process exits",
+        ),
+        Err(e) => Check::problem(
+            CheckStatus::Fail,
+            "lock",
+            e.to_string(),
+            "make the store directory writable",
+        ),
+    }
+}
+
+/// Time Machine leaves the store out of its backups.
+fn check_time_machine(store: &CapsuleStore) -> Check {
+    if !store.exists() {
+        return Check::ok("time machine", "store not created yet");
+    }
+    let out = Command::new("tmutil")
+        .arg("isexcluded")
+        .arg(store.dir())
+        .output();
+    match out {
+        Ok(out) if String::from_utf8_lossy(&out.stdout).contains("[Excluded]") => {
+            Check::ok("time machine", "store excluded from backups")
+        }
+        Ok(_) => Check::problem(
+            CheckStatus::Warn,
+            "time machine",
+            "the store is backed up by Time Machine",
+            format!(
+                "exclude it with `tmutil addexclusion {}`",
+                store.dir().display()
+            ),
+        ),
+        Err(e) => Check::problem(
+            CheckStatus::Warn,
+            "time machine",
+            format!("`tmutil` failed: {}", e),
+            "check the exclusion in the Time Machine settings",
+        ),
+    }
+}
+
+/// Staging and scratch directories an interrupted restore left behind.
+fn check_leftovers(home: &Path) -> Check {
+    let mut found = Vec::new();
+    let dirs: Vec<_> = SourceKind::ALL
+        .iter()
+        .map(|k| k.dir(home, DEFAULT_APPNAME))
+        .collect();
+    // staging directories are named after the directory they replace
+    let prefixes: Vec<String> = dirs
+        .iter()
+        .filter_map(|d| d.file_name())
+        .map(|n| format!(".{}.tm-", n.to_string_lossy()))
+        .collect();
+    let mut parents: Vec<_> = dirs
+        .iter()
+        .filter_map(|d| d.parent().map(Path::to_path_buf))
+        .collect();
+    parents.push(home.to_path_buf());
+    parents.dedup();
+    for parent in parents {
+        for entry in fs::read_dir(&parent).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if prefixes.iter().any(|p| name.starts_with(p.as_str()))
+                || name.starts_with(".nvim-tm-restore-")
+            {
+                found.push(entry.path());
+            }
+        }
+    }
+    match found.first() {
+        None => Check::ok("leftovers", "no interrupted restores"),
+        Some(first) => Check::problem(
+            CheckStatus::Warn,
+            "leftovers",
+            format!(
+                "{} left over from an interrupted restore, e.g. {}",
+                found.len(),
+                first.display()
+            ),
+            "your live directories were left intact; remove these once you're sure",
+        ),
+    }
+}
+
+/// Every capsule's archive and manifest can be opened.
+fn check_capsules(store: &CapsuleStore) -> Vec<Check> {
+    let capsules = match store.list() {
+        Ok(capsules) => capsules,
+        Err(e) => {
+            return vec![Check::problem(
+                CheckStatus::Fail,
+                "capsules",
+                e.to_string(),
+                "make the store directory readable",
+            )]
+        }
+    };
+    let mut checks = Vec::new();
+    let mut readable = 0;
+    for capsule in &capsules {
+        match capsule.manifest() {
+            Ok(_) => readable += 1,
+            Err(e) => checks.push(Check::problem(
+                CheckStatus::Fail,
+                format!("capsule {}", capsule.name()),
+                e.to_string(),
+                "the archive is damaged; delete it, or restore it from another copy",
+            )),
+        }
+    }
+    checks.insert(
+        0,
+        Check::ok(
+            "capsules",
+            format!("{} of {} readable", readable, capsules.len()),
+        ),
+    );
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_us_in_a_crontab() {
+        assert!(runs_us("0 * * * * nvimTimeMachine create --prune\n"));
+        assert!(!runs_us("# 0 * * * * nvimTimeMachine create\n"));
+        assert!(!runs_us("0 * * * * restic backup\n"));
+        assert!(!runs_us(""));
+    }
+
+    #[test]
+    fn finds_a_timer_or_agent() {
+        let home = tempfile::tempdir().unwrap();
+        assert_eq!(scheduler_file(home.path()), None);
+        let agents = home.path().join("Library/LaunchAgents");
+        fs::create_dir_all(&agents).unwrap();
+        fs::write(agents.join("org.other.plist"), b"").unwrap();
+        assert_eq!(scheduler_file(home.path()), None);
+        fs::write(agents.join("nvimTimeMachine.plist"), b"").unwrap();
+        assert_eq!(
+            scheduler_file(home.path()),
+            Some(agents.join("nvimTimeMachine.plist"))
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::plugins::{LAZY_LOCK_FILE, PACKER_SNAPSHOT_DIR};
+use crate::sources::SourceKind;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The machine and Neovim a capsule was created with, recorded in its
+/// manifest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Environment {
+    /// Output of `nvim --version`, if Neovim could be run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nvim_version: Option<String>,
+    /// `std::env::consts::OS`, e.g. `linux` or `macos`.
+    pub os: String,
+    /// `std::env::consts::ARCH`, e.g. `x86_64` or `aarch64`.
+    pub arch: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Contents of the plugin manager lock files by archive path:
+    /// `lazy-lock.json` and packer.nvim's snapshots.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub lock_files: BTreeMap<String, String>,
+}
+
+impl Environment {
+    /// Look at this machine and the `appname` profile of `home`; everything
+    /// that can't be determined is left empty.
+    pub fn capture(home: &Path, appname: &str) -> Self {
+        let mut lock_files = BTreeMap::new();
+        let lazy_lock = SourceKind::Config.dir(home, appname).join(LAZY_LOCK_FILE);
+        if let Ok(text) = fs::read_to_string(lazy_lock) {
+            lock_files.insert(
+                format!("{}/{}", SourceKind::Config.archive_dir(), LAZY_LOCK_FILE),
+                text,
+            );
+        }
+        let snapshots = SourceKind::Cache
+            .dir(home, appname)
+            .join(PACKER_SNAPSHOT_DIR);
+        for entry in fs::read_dir(snapshots).into_iter().flatten().flatten() {
+            let Ok(text) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            lock_files.insert(
+                format!(
+                    "{}/{}/{}",
+                    SourceKind::Cache.archive_dir(),
+                    PACKER_SNAPSHOT_DIR,
+                    entry.file_name().to_string_lossy()
+                ),
+                text,
+            );
+        }
+        Environment {
+            nvim_version: command_output("nvim", &["--version"]),
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            hostname: hostname(),
+            lock_files,
+        }
+    }
+
+    /// The Neovim release, e.g. `v0.10.2`, from the first line of
+    /// `nvim --version`.
+    pub fn nvim_release(&self) -> Option<&str> {
+        self.nvim_version
+            .as_deref()?
+            .lines()
+            .next()?
+            .strip_prefix("NVIM ")
+            .map(str::trim)
+    }
+
+    /// How a capsule created in this environment may not work in `current`:
+    /// another OS or architecture (unless `portable`, which left out native
+    /// code), or another Neovim major or minor release.
+    pub fn mismatches(&self, current: &Environment, portable: bool) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if !portable && (self.os != current.os || self.arch != current.arch) {
+            mismatches.push(format!(
+                "created on {}/{}, this is {}/{}",
+                self.os, self.arch, current.os, current.arch
+            ));
+        }
+        if let (Some(then), Some(now)) = (self.nvim_release(), current.nvim_release()) {
+            if minor_release(then) != minor_release(now) {
+                mismatches.push(format!("created with Neovim {}, this is {}", then, now));
+            }
+        }
+        mismatches
+    }
+
+    /// Number of plugins pinned in `lazy-lock.json`, if there is one.
+    pub fn locked_plugins(&self) -> Option<usize> {
+        let key = format!("{}/{}", SourceKind::Config.archive_dir(), LAZY_LOCK_FILE);
+        let lock: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(self.lock_files.get(&key)?).ok()?;
+        Some(lock.len())
+    }
+}
+
+/// `0.10` of a release like `v0.10.2` or `v0.11.0-dev-1234+g5678`.
+fn minor_release(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.trim_start_matches('v').split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// This machine's name, if it can be found out.
+pub(crate) fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .or_else(|| command_output("hostname", &[]))
+}
+
+/// Trimmed stdout of a command that succeeded.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !stdout.is_empty()).then_some(stdout)
+}
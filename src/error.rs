@@ -0,0 +1,65 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+use zip::result::ZipError;
+
+/// Everything that can go wrong in this crate.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A capsule (or an archive being imported) can't be read as a zip.
+    #[error("{} is not a readable capsule: {source}", path.display())]
+    BadArchive { path: PathBuf, source: ZipError },
+
+    #[error("invalid archive entry: {0}")]
+    Zip(#[from] ZipError),
+
+    #[error("invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+
+    /// The capsule index database can't be read or written.
+    #[error("capsule index: {0}")]
+    Index(#[from] rusqlite::Error),
+
+    #[error("could not determine the home directory (is HOME set?)")]
+    NoHome,
+
+    #[error("no capsules found")]
+    NoCapsules,
+
+    #[error("no capsule named \"{0}\"")]
+    CapsuleNotFound(String),
+
+    /// No capsule was created at or before the given moment.
+    #[error("no capsule from {0} or earlier")]
+    NoneAsOf(String),
+
+    #[error("no capsule #{index} (there are {count})")]
+    NoSuchIndex { index: usize, count: usize },
+
+    #[error("\"{path}\" is not in {capsule}")]
+    NotInCapsule { capsule: String, path: String },
+
+    #[error("{} already exists", .0.display())]
+    AlreadyExists(PathBuf),
+
+    /// Another process holds the store lock.
+    #[error("another nvimTimeMachine is already working on {}", .0.display())]
+    LockHeld(PathBuf),
+
+    /// A capsule's contents don't match its manifest.
+    #[error("verification failed: {0}")]
+    VerificationFailed(String),
+
+    /// `ssh` or `scp` to another machine failed.
+    #[error("{host}: {message}")]
+    Remote { host: String, message: String },
+
+    /// Bad user-supplied input, such as an invalid glob.
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,201 @@
+use crate::capsule::{entry_mtime, Capsule};
+use crate::error::Result;
+use crate::manifest::MANIFEST_NAME;
+use crate::restorer::symlink;
+use crate::sources::{SourceKind, DEFAULT_APPNAME};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::info;
+
+/// Directory layout of a dotfiles manager, for [`export_dotfiles`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotfilesLayout {
+    /// chezmoi's source state: `dot_config/nvim/...`, with `executable_`,
+    /// `private_`, `symlink_` and `literal_` name prefixes.
+    Chezmoi,
+    /// A GNU stow package: `nvim/.config/nvim/...`, to be linked into HOME
+    /// with `stow nvim`.
+    Stow,
+}
+
+/// Write the files of `capsule` to a plain tar archive at `dest`.
+///
+/// Entries keep their HOME-relative paths; the manifest is left out so the
+/// tar can be unpacked straight into a HOME directory. Returns the number of
+/// files written.
+pub fn export_tar(capsule: &Capsule, dest: &Path) -> Result<u64> {
+    let mut archive = capsule.archive()?;
+    let mut tar = tar::Builder::new(fs::File::create(dest)?);
+
+    let mut count = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() || entry.name() == MANIFEST_NAME {
+            continue;
+        }
+        let Some(path) = entry.enclosed_name() else {
+            continue;
+        };
+
+        let mut header = tar::Header::new_gnu();
+        if entry.is_symlink() {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            header.set_mtime(unix_time(&entry));
+            tar.append_link(&mut header, path, target)?;
+            count += 1;
+            continue;
+        }
+        header.set_size(entry.size());
+        header.set_mode(entry.unix_mode().unwrap_or(0o644) & 0o7777);
+        header.set_mtime(unix_time(&entry));
+        header.set_entry_type(tar::EntryType::Regular);
+        tar.append_data(&mut header, path, &mut entry)?;
+        count += 1;
+    }
+
+    tar.into_inner()?;
+    Ok(count)
+}
+
+/// Unpack the config directory of `capsule` into the dotfiles directory
+/// `dest` in the given layout, replacing the config files already there (and
+/// nothing else). Returns the directory written and the number of files.
+pub fn export_dotfiles(
+    capsule: &Capsule,
+    dest: &Path,
+    layout: DotfilesLayout,
+) -> Result<(PathBuf, u64)> {
+    let manifest = capsule.manifest()?;
+    let appname = manifest
+        .as_ref()
+        .map_or_else(|| DEFAULT_APPNAME.to_string(), |m| m.appname.clone());
+    let file_metadata = manifest.is_some_and(|m| m.file_metadata);
+    let root = match layout {
+        DotfilesLayout::Chezmoi => dest
+            .join("dot_config")
+            .join(chezmoi_name(&appname, 0o755, true)),
+        DotfilesLayout::Stow => dest.join(&appname).join(".config").join(&appname),
+    };
+    match fs::remove_dir_all(&root) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    fs::create_dir_all(&root)?;
+
+    let prefix = format!("{}/", SourceKind::Config.archive_dir());
+    let mut archive = capsule.archive()?;
+    let mut count = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(rest) = entry.name().strip_prefix(prefix.as_str()) else {
+            continue;
+        };
+        let rest = rest.trim_end_matches('/').to_string();
+        if rest.is_empty() || entry.enclosed_name().is_none() {
+            continue;
+        }
+        // older capsules store 0o755 for everything
+        let mode = match entry.unix_mode() {
+            Some(mode) if file_metadata => mode & 0o7777,
+            _ => 0o644,
+        };
+        let components: Vec<&str> = rest.split('/').collect();
+        let path = match layout {
+            DotfilesLayout::Stow => root.join(&rest),
+            DotfilesLayout::Chezmoi => {
+                let (name, dirs) = components.split_last().unwrap_or((&"", &[]));
+                let mut path = root.clone();
+                for dir in dirs {
+                    path.push(chezmoi_name(dir, 0o755, true));
+                }
+                if entry.is_symlink() {
+                    path.join(format!("symlink_{}", chezmoi_name(name, 0o644, false)))
+                } else {
+                    // directory permissions aren't carried over, so every file finds its parent
+                    path.join(chezmoi_name(
+                        name,
+                        if entry.is_dir() { 0o755 } else { mode },
+                        entry.is_dir(),
+                    ))
+                }
+            }
+        };
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if entry.is_symlink() && layout == DotfilesLayout::Stow {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            symlink(&target, &path)?;
+        } else {
+            // chezmoi keeps a link as a file holding its target
+            let mut file = fs::File::create(&path)?;
+            io::copy(&mut entry, &mut file)?;
+            #[cfg(unix)]
+            if layout == DotfilesLayout::Stow {
+                use std::os::unix::fs::PermissionsExt;
+                file.set_permissions(fs::Permissions::from_mode(mode))?;
+            }
+            if let Some(mtime) = entry_mtime(&entry) {
+                file.set_modified(mtime)?;
+            }
+        }
+        count += 1;
+    }
+    info!(capsule = capsule.name(), dest = %root.display(), files = count, "exported dotfiles");
+    Ok((root, count))
+}
+
+/// The chezmoi source state name of a file (or, with `dir`, a directory)
+/// called `name` with unix permissions `mode`.
+fn chezmoi_name(name: &str, mode: u32, dir: bool) -> String {
+    // names that start like an attribute would be read as one
+    const ATTRIBUTES: [&str; 12] = [
+        "after_",
+        "before_",
+        "create_",
+        "dot_",
+        "empty_",
+        "encrypted_",
+        "executable_",
+        "literal_",
+        "modify_",
+        "once_",
+        "private_",
+        "symlink_",
+    ];
+    let mut out = String::new();
+    if mode & 0o077 == 0 {
+        out.push_str("private_");
+    }
+    if !dir && mode & 0o111 != 0 {
+        out.push_str("executable_");
+    }
+    match name.strip_prefix('.') {
+        Some(rest) => {
+            out.push_str("dot_");
+            out.push_str(rest);
+        }
+        None => {
+            if ATTRIBUTES.iter().any(|a| name.starts_with(a)) {
+                out.push_str("literal_");
+            }
+            out.push_str(name);
+        }
+    }
+    out
+}
+
+/// Seconds since the epoch of an entry's modification time.
+fn unix_time<R: Read>(entry: &zip::read::ZipFile<'_, R>) -> u64 {
+    entry_mtime(entry)
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
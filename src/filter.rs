@@ -0,0 +1,54 @@
+use crate::error::{Error, Result};
+use crate::sources::SourceKind;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Include/exclude glob patterns selecting archive entries.
+///
+/// Patterns match either the full archive path (`.config/nvim/init.lua`) or
+/// the path inside its Neovim directory (`init.lua`, `lua/plugins/lsp.lua`).
+/// `*` stays within one path component, `**` spans several.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl EntryFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(EntryFilter {
+            include: build(include)?,
+            exclude: build(exclude)?,
+        })
+    }
+
+    /// Whether any patterns were given at all.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none()
+    }
+
+    /// Whether the archive entry `name` passes the filter.
+    pub fn matches(&self, name: &str) -> bool {
+        let inner = SourceKind::of_archive_path(name).map(|(_, rest)| rest);
+        let hit = |set: &GlobSet| set.is_match(name) || inner.is_some_and(|r| set.is_match(r));
+
+        self.include.as_ref().is_none_or(hit) && !self.exclude.as_ref().is_some_and(hit)
+    }
+}
+
+fn build(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| Error::InvalidInput(e.to_string()))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| Error::InvalidInput(e.to_string()))
+}
@@ -0,0 +1,102 @@
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// Name of the bare repository inside the store.
+pub const GIT_HISTORY_DIR_NAME: &str = "config.git";
+
+/// Name and e-mail address the commits are made with.
+const COMMITTER: (&str, &str) = ("nvimTimeMachine", "nvimTimeMachine@localhost");
+
+/// A bare git repository next to the capsules, with one commit of a config
+/// directory per capsule, for `git log -p` of the Lua files. Every
+/// `NVIM_APPNAME` gets a branch of its own.
+#[derive(Debug, Clone)]
+pub struct GitHistory {
+    git_dir: PathBuf,
+}
+
+impl GitHistory {
+    pub fn new(git_dir: impl Into<PathBuf>) -> Self {
+        GitHistory {
+            git_dir: git_dir.into(),
+        }
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// Commit the contents of `work_tree` to the branch `branch`, with
+    /// `message`, unless nothing changed since its last commit. The
+    /// repository is created on first use.
+    ///
+    /// Returns the abbreviated hash of the new commit.
+    pub fn commit(&self, work_tree: &Path, branch: &str, message: &str) -> Result<Option<String>> {
+        if !self.git_dir.exists() {
+            fs::create_dir_all(&self.git_dir)?;
+            self.git(None, None, &["init", "--quiet", "--bare"])?;
+            // so a plain `git log` shows the first profile committed
+            let head = format!("refs/heads/{}", branch);
+            self.git(None, None, &["symbolic-ref", "HEAD", &head])?;
+        }
+        // an index per branch, so branches don't see each other's files
+        let index = self.git_dir.join(format!("index-{}", branch));
+        let reference = format!("refs/heads/{}", branch);
+        self.git(Some(work_tree), Some(&index), &["add", "--all", "."])?;
+        let tree = self.git(Some(work_tree), Some(&index), &["write-tree"])?;
+        let parent = self
+            .git(
+                None,
+                None,
+                &["rev-parse", "--verify", "--quiet", &reference],
+            )
+            .ok();
+        if let Some(parent) = &parent {
+            let parent_tree =
+                self.git(None, None, &["rev-parse", &format!("{}^{{tree}}", parent)])?;
+            if parent_tree == tree {
+                return Ok(None);
+            }
+        }
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message];
+        if let Some(parent) = &parent {
+            args.extend(["-p", parent.as_str()]);
+        }
+        let commit = self.git(None, None, &args)?;
+        self.git(None, None, &["update-ref", &reference, &commit])?;
+        info!(git_dir = %self.git_dir.display(), branch, commit = %commit, "committed config history");
+        Ok(Some(commit.chars().take(7).collect()))
+    }
+
+    /// Run git on the repository and return its trimmed output.
+    fn git(&self, work_tree: Option<&Path>, index: Option<&Path>, args: &[&str]) -> Result<String> {
+        let mut command = Command::new("git");
+        command.arg("--git-dir").arg(&self.git_dir);
+        if let Some(work_tree) = work_tree {
+            command
+                .arg("--work-tree")
+                .arg(work_tree)
+                .current_dir(work_tree);
+        }
+        if let Some(index) = index {
+            command.env("GIT_INDEX_FILE", index);
+        }
+        for var in ["GIT_AUTHOR", "GIT_COMMITTER"] {
+            command
+                .env(format!("{}_NAME", var), COMMITTER.0)
+                .env(format!("{}_EMAIL", var), COMMITTER.1);
+        }
+        let output = command.args(args).output()?;
+        if !output.status.success() {
+            return Err(Error::InvalidInput(format!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
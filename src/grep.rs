@@ -0,0 +1,46 @@
+use crate::capsule::Capsule;
+use crate::diff::is_text;
+use crate::error::Result;
+use crate::manifest::MANIFEST_NAME;
+use regex::Regex;
+use serde::Serialize;
+use std::io::Read;
+
+/// A matching line inside an archived file.
+#[derive(Debug, Clone, Serialize)]
+pub struct GrepMatch {
+    pub path: String,
+    /// 1-based line number.
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// Search the text files of `capsule` for `pattern`, reading entries in place.
+pub fn grep_capsule(capsule: &Capsule, pattern: &Regex) -> Result<Vec<GrepMatch>> {
+    let mut archive = capsule.archive()?;
+    let mut matches = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() || entry.is_symlink() || entry.name() == MANIFEST_NAME {
+            continue;
+        }
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+        if !is_text(&buf) {
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&buf);
+        for (n, line) in text.lines().enumerate() {
+            if pattern.is_match(line) {
+                matches.push(GrepMatch {
+                    path: entry.name().to_string(),
+                    line_number: n + 1,
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
+    Ok(matches)
+}
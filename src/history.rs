@@ -0,0 +1,59 @@
+use crate::capsule::Capsule;
+use crate::error::Result;
+use crate::index::CapsuleIndex;
+use crate::manifest::ManifestEntry;
+use crate::sources::SourceKind;
+
+/// One capsule's copy of a file.
+#[derive(Debug, Clone)]
+pub struct FileVersion {
+    pub capsule: Capsule,
+    pub entry: ManifestEntry,
+}
+
+/// Archive paths a user-supplied `query` may refer to, most specific first.
+///
+/// `query` can be the archive path itself (`.config/nvim/init.lua`, optionally
+/// prefixed with `~/`) or relative to one of the Neovim directories
+/// (`lua/plugins/lsp.lua`).
+pub fn candidate_paths(query: &str) -> Vec<String> {
+    let query = query.replace('\\', "/");
+    let query = query.trim_start_matches("~/").trim_start_matches("./");
+    let mut candidates = vec![query.to_string()];
+    candidates.extend(
+        SourceKind::ALL
+            .iter()
+            .map(|k| format!("{}/{}", k.archive_dir(), query)),
+    );
+    candidates
+}
+
+/// Find the archive path `query` refers to in a single file list.
+pub fn resolve_path<'a>(files: &'a [ManifestEntry], query: &str) -> Option<&'a ManifestEntry> {
+    candidate_paths(query)
+        .iter()
+        .find_map(|c| files.iter().find(|f| &f.path == c))
+}
+
+/// Every version of the file `query` across `capsules`, in capsule order,
+/// looked up in `index`.
+pub fn file_history(
+    index: &CapsuleIndex,
+    capsules: &[Capsule],
+    query: &str,
+) -> Result<Vec<FileVersion>> {
+    let candidates = candidate_paths(query);
+    let mut versions = Vec::new();
+    for capsule in capsules {
+        for path in &candidates {
+            if let Some(entry) = index.entry(capsule, path)? {
+                versions.push(FileVersion {
+                    capsule: capsule.clone(),
+                    entry,
+                });
+                break;
+            }
+        }
+    }
+    Ok(versions)
+}
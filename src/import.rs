@@ -0,0 +1,258 @@
+use crate::capsule::Capsule;
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::sources::SourceKind;
+use crate::store::CapsuleStore;
+use crate::writer::CapsuleWriter;
+use flate2::read::GzDecoder;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+use std::process::{self, Command};
+use tracing::info;
+use walkdir::WalkDir;
+use zip::read::ZipArchive;
+
+/// Called with the path and contents of each file to import.
+type OnFile<'a> = dyn FnMut(&str, &[u8]) -> Result<()> + 'a;
+
+/// Where the files of an imported archive end up.
+enum Layout {
+    /// Paths are already relative to HOME (`.config/nvim/...`).
+    Home,
+    /// The archive is a bare config directory, optionally wrapped in a single
+    /// top-level folder; it is placed under `.config/nvim`.
+    Config { strip: Option<String> },
+}
+
+/// Outcome of [`import_archive`].
+#[derive(Debug)]
+pub struct Imported {
+    pub capsule: Capsule,
+    pub manifest: Manifest,
+    /// Files that fell outside the Neovim directories and were left out.
+    pub skipped: u64,
+}
+
+/// Adopt a zip, tar or tar.gz archive of a Neovim setup into `store` as a
+/// new v2 capsule.
+///
+/// Archives whose paths are relative to HOME keep them (anything outside the
+/// Neovim directories is skipped); anything else is treated as the contents
+/// of `~/.config/nvim`.
+pub fn import_archive(store: &CapsuleStore, path: &Path) -> Result<Imported> {
+    import(store, &path.display().to_string(), &|f| {
+        for_each_file(path, f)
+    })
+}
+
+/// Adopt a git repository of a Neovim config, such as a dotfiles repo, as a
+/// new capsule: `source` (a URL or a local path) is cloned, and the files of
+/// its default branch are laid out as for [`import_archive`]. The capsule's
+/// note records where it came from.
+pub fn import_git(store: &CapsuleStore, source: &str) -> Result<Imported> {
+    let dir = env::temp_dir().join(format!("nvim-tm-git-{}", process::id()));
+    let imported = clone_and_import(store, source, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    imported
+}
+
+fn clone_and_import(store: &CapsuleStore, source: &str, dir: &Path) -> Result<Imported> {
+    let output = Command::new("git")
+        .args(["clone", "--quiet", "--depth", "1", "--", source])
+        .arg(dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::InvalidInput(format!(
+            "git clone {} failed: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let imported = import(store, source, &|f| for_each_dir_file(dir, f))?;
+    let commit = Command::new("git")
+        .args(["log", "-1", "--format=%h %s"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    let mut meta = imported.capsule.meta()?;
+    meta.set_note(&match commit {
+        Some(commit) => format!("Imported from {} at {}", source, commit),
+        None => format!("Imported from {}", source),
+    });
+    imported.capsule.set_meta(&meta)?;
+    Ok(imported)
+}
+
+/// Import the files `for_each` calls its argument with, `origin` being
+/// where they come from for the log.
+fn import(
+    store: &CapsuleStore,
+    origin: &str,
+    for_each: &dyn Fn(&mut OnFile) -> Result<()>,
+) -> Result<Imported> {
+    let mut names = Vec::new();
+    for_each(&mut |name, _| {
+        names.push(name.to_string());
+        Ok(())
+    })?;
+    let layout = detect_layout(&names);
+
+    let dest = store.new_capsule_path()?;
+    let sources = match layout {
+        Layout::Home => SourceKind::ALL
+            .iter()
+            .filter(|&&k| {
+                names
+                    .iter()
+                    .any(|n| SourceKind::of_archive_path(n).is_some_and(|(kind, _)| kind == k))
+            })
+            .map(|k| k.archive_dir().to_string())
+            .collect(),
+        Layout::Config { .. } => vec![SourceKind::Config.archive_dir().to_string()],
+    };
+    let mut writer = CapsuleWriter::create(&dest, sources)?;
+    let mut skipped = 0;
+    for_each(&mut |name, contents| {
+        match map_path(&layout, name) {
+            Some(target) => writer.add_file(&target, contents)?,
+            None => skipped += 1,
+        }
+        Ok(())
+    })?;
+    let manifest = writer.finish()?;
+    info!(
+        archive = origin,
+        capsule = %dest.display(),
+        files = manifest.file_count,
+        skipped,
+        "imported archive"
+    );
+    store.index_capsule(&Capsule::from_path(&dest));
+
+    Ok(Imported {
+        capsule: Capsule::from_path(dest),
+        manifest,
+        skipped,
+    })
+}
+
+fn detect_layout(names: &[String]) -> Layout {
+    let home_relative = names
+        .iter()
+        .any(|n| SourceKind::of_archive_path(n).is_some());
+    if home_relative {
+        return Layout::Home;
+    }
+
+    // a single wrapping folder, as in `nvim/init.lua` or `dotfiles-main/init.lua`
+    let first = names
+        .first()
+        .and_then(|n| n.split_once('/'))
+        .map(|(d, _)| d);
+    let strip = first
+        .filter(|d| names.iter().all(|n| n.starts_with(&format!("{}/", d))))
+        .map(|d| format!("{}/", d));
+    Layout::Config { strip }
+}
+
+fn map_path(layout: &Layout, name: &str) -> Option<String> {
+    match layout {
+        Layout::Home => SourceKind::of_archive_path(name).map(|_| name.to_string()),
+        Layout::Config { strip } => {
+            let rel = match strip {
+                Some(prefix) => name.strip_prefix(prefix.as_str())?,
+                None => name,
+            };
+            Some(format!("{}/{}", SourceKind::Config.archive_dir(), rel))
+        }
+    }
+}
+
+/// Call `f` with the normalized path and contents of every regular file.
+fn for_each_file(path: &Path, f: &mut OnFile) -> Result<()> {
+    let file_name = path.to_string_lossy().to_lowercase();
+    let file = fs::File::open(path)?;
+
+    if file_name.ends_with(".zip") {
+        let mut archive = ZipArchive::new(file).map_err(|source| Error::BadArchive {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if !entry.is_file() || entry.name() == MANIFEST_NAME {
+                continue;
+            }
+            let Some(name) = entry.enclosed_name().as_deref().and_then(normalize) else {
+                continue;
+            };
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf)?;
+            f(&name, &buf)?;
+        }
+        Ok(())
+    } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+        for_each_tar_file(tar::Archive::new(GzDecoder::new(file)), f)
+    } else if file_name.ends_with(".tar") {
+        for_each_tar_file(tar::Archive::new(file), f)
+    } else {
+        Err(Error::InvalidInput(format!(
+            "unsupported archive {} (expected .zip, .tar or .tar.gz)",
+            path.display()
+        )))
+    }
+}
+
+fn for_each_tar_file<R: Read>(mut archive: tar::Archive<R>, f: &mut OnFile) -> Result<()> {
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(name) = normalize(&entry.path()?) else {
+            continue;
+        };
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+        f(&name, &buf)?;
+    }
+    Ok(())
+}
+
+/// Call `f` with the path and contents of every file of a checked out
+/// repository, leaving out git's own files.
+fn for_each_dir_file(dir: &Path, f: &mut OnFile) -> Result<()> {
+    let entries = WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || e.file_name() != ".git");
+    for entry in entries {
+        let entry = entry.map_err(io::Error::from)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let Some(name) = normalize(rel) else {
+            continue;
+        };
+        f(&name, &fs::read(entry.path())?)?;
+    }
+    Ok(())
+}
+
+/// A `/`-separated relative path, or `None` if it escapes the archive root.
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(p) => parts.push(p.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
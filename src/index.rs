@@ -0,0 +1,179 @@
+use crate::capsule::Capsule;
+use crate::error::Result;
+use crate::manifest::ManifestEntry;
+use crate::store::CapsuleStore;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// SQLite database inside the store caching every capsule's file list.
+pub const INDEX_FILE_NAME: &str = ".index.sqlite";
+
+/// Bumped whenever the tables change; an older index is rebuilt from scratch.
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+    CREATE TABLE capsules (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        size INTEGER NOT NULL,
+        mtime INTEGER NOT NULL
+    );
+    CREATE TABLE files (
+        capsule INTEGER NOT NULL REFERENCES capsules (id) ON DELETE CASCADE,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        sha256 TEXT NOT NULL,
+        PRIMARY KEY (capsule, path)
+    ) WITHOUT ROWID;
+";
+
+/// File lists of the capsules in a store, so `history`, `diff` and
+/// `grep --name` don't have to open every archive.
+///
+/// The index is only a cache: a capsule whose archive changed size or mtime
+/// since it was indexed (or that was never indexed, e.g. because it was
+/// copied into the store by hand) is read again on first use.
+pub struct CapsuleIndex {
+    conn: Connection,
+}
+
+impl CapsuleIndex {
+    /// Open the index of `store`, creating it if needed. A store that doesn't
+    /// exist yet gets a throwaway in-memory index.
+    pub fn open(store: &CapsuleStore) -> Result<Self> {
+        let conn = if store.exists() {
+            Connection::open(store.dir().join(INDEX_FILE_NAME))?
+        } else {
+            Connection::open_in_memory()?
+        };
+        // other processes may be refreshing the index at the same time
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            conn.execute_batch("DROP TABLE IF EXISTS files; DROP TABLE IF EXISTS capsules;")?;
+            conn.execute_batch(SCHEMA)?;
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        Ok(CapsuleIndex { conn })
+    }
+
+    /// Size and checksum of every archived file, sorted by path, like
+    /// [`Capsule::files`].
+    pub fn files(&self, capsule: &Capsule) -> Result<Vec<ManifestEntry>> {
+        let id = self.refresh(capsule)?;
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT path, size, sha256 FROM files WHERE capsule = ? ORDER BY path",
+        )?;
+        let files = stmt
+            .query_map([id], |row| {
+                Ok(ManifestEntry {
+                    path: row.get(0)?,
+                    size: row.get::<_, i64>(1)? as u64,
+                    sha256: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(files)
+    }
+
+    /// The entry for archive path `path` in `capsule`, if it has one.
+    pub fn entry(&self, capsule: &Capsule, path: &str) -> Result<Option<ManifestEntry>> {
+        let id = self.refresh(capsule)?;
+        let entry = self
+            .conn
+            .prepare_cached("SELECT size, sha256 FROM files WHERE capsule = ? AND path = ?")?
+            .query_row(params![id, path], |row| {
+                Ok(ManifestEntry {
+                    path: path.to_string(),
+                    size: row.get::<_, i64>(0)? as u64,
+                    sha256: row.get(1)?,
+                })
+            })
+            .optional()?;
+        Ok(entry)
+    }
+
+    /// Read `capsule` again and store its file list.
+    pub fn update(&self, capsule: &Capsule) -> Result<()> {
+        let (size, mtime) = stamp(capsule)?;
+        self.insert(capsule, size, mtime).map(|_| ())
+    }
+
+    /// Forget the capsule named `name`.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM capsules WHERE name = ?", [name])?;
+        Ok(())
+    }
+
+    /// Keep the entry of a capsule that was renamed from `old_name`.
+    pub fn rename(&self, old_name: &str, new_name: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE capsules SET name = ?2 WHERE name = ?1",
+            [old_name, new_name],
+        )?;
+        Ok(())
+    }
+
+    /// Drop entries of capsules that are no longer in `capsules`.
+    pub fn retain(&self, capsules: &[Capsule]) -> Result<()> {
+        let names: Vec<String> = self
+            .conn
+            .prepare("SELECT name FROM capsules")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for name in names {
+            if !capsules.iter().any(|c| c.name() == name) {
+                self.remove(&name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Row id of `capsule`, indexing it first if its entry is missing or stale.
+    fn refresh(&self, capsule: &Capsule) -> Result<i64> {
+        let (size, mtime) = stamp(capsule)?;
+        let indexed: Option<(i64, i64, i64)> = self
+            .conn
+            .prepare_cached("SELECT id, size, mtime FROM capsules WHERE name = ?")?
+            .query_row([capsule.name()], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .optional()?;
+        match indexed {
+            Some((id, s, m)) if s == size && m == mtime => Ok(id),
+            _ => self.insert(capsule, size, mtime),
+        }
+    }
+
+    fn insert(&self, capsule: &Capsule, size: i64, mtime: i64) -> Result<i64> {
+        let files = capsule.files()?;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM capsules WHERE name = ?", [capsule.name()])?;
+        tx.execute(
+            "INSERT INTO capsules (name, size, mtime) VALUES (?, ?, ?)",
+            params![capsule.name(), size, mtime],
+        )?;
+        let id = tx.last_insert_rowid();
+        {
+            let mut stmt =
+                tx.prepare("INSERT INTO files (capsule, path, size, sha256) VALUES (?, ?, ?, ?)")?;
+            for file in &files {
+                stmt.execute(params![id, file.path, file.size as i64, file.sha256])?;
+            }
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+}
+
+/// Archive size and mtime (in nanoseconds), to tell whether an entry is stale.
+fn stamp(capsule: &Capsule) -> Result<(i64, i64)> {
+    let mtime = fs::metadata(capsule.path())?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i64);
+    Ok((capsule.size()? as i64, mtime))
+}
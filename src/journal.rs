@@ -0,0 +1,257 @@
+use crate::error::Result;
+use crate::progress::Progress;
+use crate::writer::CapsuleWriter;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use zip::read::read_zipfile_from_stream;
+
+/// Suffix of a capsule archive that is still being written.
+pub const PART_SUFFIX: &str = ".part";
+
+/// An entry written to a capsule, as recorded in its journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum JournalEntry {
+    File {
+        path: String,
+        sha256: String,
+        mode: u32,
+        mtime: Option<SystemTime>,
+    },
+    Dir {
+        path: String,
+        mode: u32,
+        /// Missing from journals of older versions.
+        #[serde(default)]
+        mtime: Option<SystemTime>,
+    },
+    Symlink {
+        path: String,
+        target: String,
+    },
+}
+
+/// The entries added to a capsule archive so far, one JSON line each, so an
+/// interrupted `create` can be resumed.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    file: fs::File,
+}
+
+/// Where a capsule is written before it is moved to `dest`.
+pub(crate) fn part_path(dest: &Path) -> PathBuf {
+    with_suffix(dest, PART_SUFFIX)
+}
+
+/// The journal of the archive at `archive`.
+pub(crate) fn journal_path(archive: &Path) -> PathBuf {
+    with_suffix(archive, ".journal")
+}
+
+/// `path` with `suffix` appended to its file name.
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+impl Journal {
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        Ok(Journal {
+            file: fs::File::create(path)?,
+        })
+    }
+
+    pub(crate) fn record(&mut self, entry: &JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// Add the entries of the interrupted archive `part` to `writer` again, in
+/// the order its journal lists them, stopping at the first one that didn't
+/// make it into the archive intact.
+///
+/// Returns the paths of the entries added.
+pub(crate) fn replay(
+    part: &Path,
+    writer: &mut CapsuleWriter,
+    progress: &dyn Progress,
+) -> Result<HashSet<String>> {
+    let journal = fs::read_to_string(journal_path(part))?;
+    // the archive has no central directory yet, so it's read entry by entry
+    let mut archive = io::BufReader::new(fs::File::open(part)?);
+    let mut done = HashSet::new();
+    for line in journal.lines() {
+        // the last line may be cut short
+        let Ok(entry) = serde_json::from_str::<JournalEntry>(line) else {
+            break;
+        };
+        let Ok(Some(mut file)) = read_zipfile_from_stream(&mut archive) else {
+            break;
+        };
+        let name = file.name().trim_end_matches('/').to_string();
+        let mut contents = Vec::new();
+        if file.read_to_end(&mut contents).is_err() {
+            break;
+        }
+        drop(file);
+        match entry {
+            JournalEntry::File {
+                path,
+                sha256,
+                mode,
+                mtime,
+            } if path == name && format!("{:x}", Sha256::digest(&contents)) == sha256 => {
+                match mtime {
+                    Some(mtime) => writer.add_file_with(&path, &contents, mode, mtime)?,
+                    None => writer.add_file(&path, &contents)?,
+                }
+                progress.inc(Path::new(&path), contents.len() as u64);
+                done.insert(path);
+            }
+            JournalEntry::Dir { path, mode, mtime } if path == name => {
+                match mtime {
+                    Some(mtime) => writer.add_dir_with(&path, mode, mtime)?,
+                    None => writer.add_dir(&path, mode)?,
+                }
+                done.insert(path);
+            }
+            JournalEntry::Symlink { path, target } if path == name => {
+                writer.add_symlink(&path, &target)?;
+                progress.inc(Path::new(&path), 0);
+                done.insert(path);
+            }
+            _ => break,
+        }
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capsule::Capsule;
+    use crate::progress::NoProgress;
+    use crate::testing;
+
+    /// A finished archive `capsule.zip.part` in a new directory, with a
+    /// journal of its entries.
+    fn journaled() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("capsule.zip.part");
+        testing::capsule(&part, &[".config/nvim"], |w| {
+            w.set_journal(Journal::create(&journal_path(&part)).unwrap());
+            w.add_file(".config/nvim/init.lua", b"vim.o.number = true\n")
+                .unwrap();
+            w.add_dir(".config/nvim/lua", 0o755).unwrap();
+            w.add_symlink(".config/nvim/alias.lua", "init.lua").unwrap();
+            // doesn't compress, so it takes up the end of the archive
+            let mut seed = 1u32;
+            let big: Vec<u8> = (0..64 << 10)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (seed >> 16) as u8
+                })
+                .collect();
+            w.add_file(".config/nvim/lua/big.lua", &big).unwrap();
+        });
+        (dir, part)
+    }
+
+    fn replayed(part: &Path) -> (HashSet<String>, Capsule) {
+        let mut done = HashSet::new();
+        let capsule = testing::capsule(
+            &part.with_file_name("resumed.zip"),
+            &[".config/nvim"],
+            |w| {
+                done = replay(part, w, &NoProgress).unwrap();
+            },
+        );
+        (done, capsule)
+    }
+
+    #[test]
+    fn paths() {
+        let dest = Path::new("/store/capsule.zip");
+        assert_eq!(part_path(dest), Path::new("/store/capsule.zip.part"));
+        assert_eq!(
+            journal_path(&part_path(dest)),
+            Path::new("/store/capsule.zip.part.journal")
+        );
+    }
+
+    #[test]
+    fn replays_every_entry() {
+        let (_dir, part) = journaled();
+        let (done, capsule) = replayed(&part);
+        assert_eq!(done.len(), 4);
+        assert_eq!(
+            capsule.read_file(".config/nvim/init.lua").unwrap(),
+            b"vim.o.number = true\n"
+        );
+        let manifest = capsule.manifest().unwrap().unwrap();
+        assert_eq!(manifest.file_count, 2);
+        assert_eq!(manifest.symlinks.len(), 1);
+    }
+
+    #[test]
+    fn stops_at_a_cut_off_entry() {
+        let (_dir, part) = journaled();
+        // cut the archive short in the middle of the last file
+        let archive = fs::read(&part).unwrap();
+        let end = archive
+            .windows(24)
+            .position(|w| w == b".config/nvim/lua/big.lua")
+            .unwrap();
+        fs::write(&part, &archive[..end + (32 << 10)]).unwrap();
+
+        let (done, capsule) = replayed(&part);
+        let mut done: Vec<_> = done.into_iter().collect();
+        done.sort();
+        assert_eq!(
+            done,
+            [
+                ".config/nvim/alias.lua",
+                ".config/nvim/init.lua",
+                ".config/nvim/lua"
+            ]
+        );
+        assert!(capsule.read_file(".config/nvim/lua/big.lua").is_err());
+    }
+
+    #[test]
+    fn stops_at_a_cut_off_journal_line() {
+        let (_dir, part) = journaled();
+        let journal = fs::read_to_string(journal_path(&part)).unwrap();
+        let second = journal.match_indices('\n').next().unwrap().0 + 1;
+        fs::write(journal_path(&part), &journal[..second + 10]).unwrap();
+
+        let (done, _) = replayed(&part);
+        assert_eq!(done.len(), 1);
+        assert!(done.contains(".config/nvim/init.lua"));
+    }
+
+    #[test]
+    fn stops_at_a_changed_file() {
+        let (_dir, part) = journaled();
+        let journal = fs::read_to_string(journal_path(&part)).unwrap();
+        let (first, rest) = journal.split_once('\n').unwrap();
+        let mut entry: JournalEntry = serde_json::from_str(first).unwrap();
+        if let JournalEntry::File { sha256, .. } = &mut entry {
+            *sha256 = "0".repeat(64);
+        }
+        let first = serde_json::to_string(&entry).unwrap();
+        fs::write(journal_path(&part), format!("{}\n{}", first, rest)).unwrap();
+
+        let (done, _) = replayed(&part);
+        assert!(done.is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+//! Core of `nvimTimeMachine`: creating, listing and restoring time capsules
+//! of a Neovim environment.
+//!
+//! Nothing in here reads stdin or writes stdout; interactive prompts and
+//! progress rendering are left to the front end.
+
+mod age;
+mod archiver;
+mod capsule;
+mod config;
+mod diff;
+mod doctor;
+mod environment;
+mod error;
+mod export;
+mod filter;
+mod git;
+mod grep;
+mod history;
+mod import;
+mod index;
+mod journal;
+mod manifest;
+mod merge;
+mod metadata;
+mod migrate;
+mod mount;
+mod naming;
+mod plugins;
+mod progress;
+mod remote;
+mod restorer;
+mod retention;
+mod section;
+mod size;
+mod snapshot;
+mod sources;
+mod stats;
+mod store;
+#[cfg(test)]
+mod testing;
+mod transaction;
+mod tree;
+mod verify;
+mod volume;
+mod writer;
+
+pub use age::{Age, Moment};
+pub use archiver::{Archiver, CreatePreview, Symlinks, MIN_VOLUME_SIZE, PORTABLE_EXCLUDES};
+pub use capsule::{Capsule, CapsuleInfo, CAPSULE_EXTENSION};
+pub use config::{Config, Profile, Remote};
+pub use diff::{diff_files, is_text, unified_diff, Change, DiffStat, FileChange};
+pub use doctor::{find_schedule, run_checks, Check, CheckStatus};
+pub use environment::Environment;
+pub use error::{Error, Result};
+pub use export::{export_dotfiles, export_tar, DotfilesLayout};
+pub use filter::EntryFilter;
+pub use git::{GitHistory, GIT_HISTORY_DIR_NAME};
+pub use grep::{grep_capsule, GrepMatch};
+pub use history::{candidate_paths, file_history, resolve_path, FileVersion};
+pub use import::{import_archive, import_git, Imported};
+pub use index::{CapsuleIndex, INDEX_FILE_NAME};
+pub use journal::PART_SUFFIX;
+pub use manifest::{Manifest, ManifestEntry, SymlinkEntry, FORMAT_VERSION, MANIFEST_NAME};
+pub use merge::{merge_capsules, MergeConflict};
+pub use metadata::{CapsuleMeta, META_EXTENSION};
+pub use migrate::migrate_capsule;
+pub use mount::{mount_capsule, mount_store, unmount};
+pub use naming::{CapsuleNaming, NameTemplate, DEFAULT_NAME_TEMPLATE};
+pub use plugins::{PluginManager, LAZY_LOCK_FILE, PACKER_SNAPSHOT_DIR};
+pub use progress::{NoProgress, Progress};
+pub use remote::{FetchedCapsule, RemoteCapsule, SshRemote, Upload};
+pub use restorer::{Action, ConflictStrategy, ExistingDirs, PlannedFile, RestorePlan, Restorer};
+pub use retention::{PruneItem, RetentionPolicy};
+pub use section::Section;
+pub use size::ByteSize;
+pub use snapshot::{Snapshot, SnapshotInfo, SnapshotStore, SNAPSHOT_DIR_NAME};
+pub use sources::{find_appnames, SourceKind, DEFAULT_APPNAME};
+pub use stats::{store_stats, CapsuleStats, PathSize, StoreStats};
+pub use store::{
+    capsule_prefix, CapsuleStore, Quarantined, StoreLock, CAPSULE_DIR_NAME, CAPSULE_PREFIX,
+    CORRUPT_DIR_NAME, LEGACY_PREFIX, LOG_DIR_NAME,
+};
+pub use tree::{build_tree, TreeNode};
+pub use verify::DamagedEntry;
+pub use writer::CapsuleWriter;
@@ -0,0 +1,59 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::{fmt, prelude::*};
+
+/// Number of daily log files kept around.
+const KEEP_LOG_FILES: usize = 14;
+
+/// Log to stderr at the level picked with `-v`/`-q`, and always at info
+/// level to a daily rotated file in `dir`.
+///
+/// The returned guard flushes the log file when dropped. If the file can't
+/// be opened only stderr logging is set up.
+pub fn init(verbose: u8, quiet: bool, dir: Option<&Path>) -> Option<WorkerGuard> {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    let stderr = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(console::colors_enabled_stderr())
+        .with_target(false)
+        .without_time()
+        .with_filter(level);
+
+    let appender = dir.and_then(|dir| {
+        fs::create_dir_all(dir).ok()?;
+        Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("nvimTimeMachine")
+            .filename_suffix("log")
+            .max_log_files(KEEP_LOG_FILES)
+            .build(dir)
+            .ok()
+    });
+    let (file, guard) = match appender {
+        Some(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(LevelFilter::INFO);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .init();
+    guard
+}
@@ -1,209 +1,2458 @@
-use chrono::Local;
-use clap::Parser;
-use dialoguer::{theme::ColorfulTheme, Confirm, Select};
+mod cli;
+mod logging;
+mod prompt;
+mod tui;
+
+use chrono::{Local, TimeDelta};
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use cli::{
+    Cli, ColorChoice, Command, CreateArgs, ExportFormat, IconSet, ListArgs, NoteCommand,
+    OutputFormat, ProgressMode, PruneArgs, RestoreArgs, SortKey, TagCommand,
+};
+use console::{style, Term};
 use dirs::home_dir;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::ffi::OsStr;
-use std::{
-    fs,
-    io::{self, Read, Write},
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use nvim_time_machine::{
+    build_tree, capsule_prefix, diff_files, export_tar, find_appnames, find_schedule, grep_capsule,
+    import_archive, import_git, merge_capsules, migrate_capsule, mount_capsule, mount_store,
+    resolve_path, run_checks, store_stats, unified_diff, Action, Age, Archiver, ByteSize, Capsule,
+    CapsuleMeta, CapsuleNaming, CapsuleStore, Change, CheckStatus, Config, ConflictStrategy,
+    CreatePreview, DamagedEntry, DiffStat, DotfilesLayout, EntryFilter, Environment, Error,
+    ExistingDirs, FileChange, ManifestEntry, Moment, PluginManager, Progress, PruneItem,
+    Quarantined, RemoteCapsule, RestorePlan, Restorer, Result, Snapshot, SnapshotStore, SourceKind,
+    SshRemote, TreeNode, CAPSULE_EXTENSION, DEFAULT_APPNAME,
 };
-use walkdir::WalkDir;
-use zip::{read::ZipArchive, write::FileOptions, CompressionMethod, ZipWriter};
+use prompt::Prompter;
+use regex::RegexBuilder;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use tracing::{error, info, warn};
+
+/// Renders library progress with an indicatif bar.
+struct Bar {
+    pb: ProgressBar,
+    done: &'static str,
+}
 
-#[derive(Parser)]
-#[command(name = "nvimTimeMachine", version)]
-#[command(about = "Manage Neovim time capsules", long_about = None)]
-struct Cli {
-    /// Create a new capsule
-    #[arg(short = 'c', long)]
-    create_capsule: bool,
+impl Bar {
+    fn new(done: &'static str) -> Self {
+        let pb = ProgressBar::new(0);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+                )
+                .expect("invalid progress bar template"),
+        );
+        Bar { pb, done }
+    }
+}
 
-    /// List existing capsules
-    #[arg(short = 'l', long)]
-    list_capsules: bool,
+impl Progress for Bar {
+    fn start(&self, total: u64, _total_bytes: u64) {
+        self.pb.set_length(total);
+    }
 
-    /// Restore from a capsule
-    #[arg(short = 'r', long)]
-    restore_capsule: bool,
+    fn inc(&self, _item: &Path, _bytes: u64) {
+        self.pb.inc(1);
+    }
+
+    fn finish(&self) {
+        self.pb.finish_with_message(self.done);
+    }
+}
+
+/// Writes library progress to stderr as newline-delimited JSON events.
+struct JsonProgress {
+    phase: &'static str,
+    counts: Mutex<Counts>,
 }
 
-fn main() -> io::Result<()> {
-    let args = Cli::parse();
+#[derive(Default)]
+struct Counts {
+    files_done: u64,
+    files_total: u64,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+impl JsonProgress {
+    fn new(phase: &'static str) -> Self {
+        JsonProgress {
+            phase,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    fn emit(&self, event: &str, path: Option<&Path>, counts: &Counts) {
+        let line = json!({
+            "phase": self.phase,
+            "event": event,
+            "path": path,
+            "files_done": counts.files_done,
+            "files_total": counts.files_total,
+            "bytes_done": counts.bytes_done,
+            "bytes_total": counts.bytes_total,
+        });
+        // one write per line, so events from several workers don't interleave
+        let _ = io::stderr()
+            .lock()
+            .write_all(format!("{}\n", line).as_bytes());
+    }
+}
+
+impl Progress for JsonProgress {
+    fn start(&self, total: u64, total_bytes: u64) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.files_total = total;
+        counts.bytes_total = total_bytes;
+        self.emit("start", None, &counts);
+    }
+
+    fn inc(&self, item: &Path, bytes: u64) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.files_done += 1;
+        counts.bytes_done += bytes;
+        self.emit("file", Some(item), &counts);
+    }
+
+    fn finish(&self) {
+        self.emit("finish", None, &self.counts.lock().unwrap());
+    }
+}
+
+/// Progress reporting for one phase of a command, as selected with `--progress`.
+fn progress(mode: ProgressMode, phase: &'static str, done: &'static str) -> Box<dyn Progress> {
+    match mode {
+        ProgressMode::Bar => Box::new(Bar::new(done)),
+        ProgressMode::Json => Box::new(JsonProgress::new(phase)),
+    }
+}
 
-    if args.create_capsule {
-        create_capsule()?;
-    } else if args.list_capsules {
-        list_capsules()?;
-    } else if args.restore_capsule {
-        restore_capsule()?;
+fn main() {
+    // answers the shell when completing a command line, then exits
+    CompleteEnv::with_factory(Cli::command).complete();
+    let cli = Cli::parse();
+    // Ctrl-C in the middle of a prompt would otherwise leave the cursor hidden
+    let _ = ctrlc::set_handler(|| {
+        let _ = Term::stderr().show_cursor();
+        process::exit(EXIT_CANCELLED);
+    });
+    set_colors(cli.color);
+    let store = home_dir()
+        .filter(|_| cli.uses_store())
+        .map(|home| CapsuleStore::in_home(&home));
+    // logging creates the store, so this is the first use
+    let new_store = store.as_ref().filter(|s| !s.exists()).cloned();
+    let log_dir = store.map(|s| s.log_dir());
+    let guard = logging::init(cli.verbose, cli.quiet, log_dir.as_deref());
+    info!(args = ?env::args().collect::<Vec<_>>(), "started");
+    if let Some(store) = new_store.filter(|_| !cli.no_tm_exclude) {
+        if let Err(e) = store.exclude_from_time_machine() {
+            warn!(error = %e, "could not exclude the store from Time Machine");
+        }
+    }
+
+    let code = match run(cli) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("{}", e);
+            exit_code(&e)
+        }
+    };
+    // a prompt cancelled with Ctrl-C leaves the cursor hidden
+    let term = Term::stderr();
+    if term.is_term() {
+        let _ = term.show_cursor();
+    }
+    // flush the log file before exiting
+    drop(guard);
+    process::exit(code);
+}
+
+/// Exit status after Ctrl-C, as for a shell killed by SIGINT.
+const EXIT_CANCELLED: i32 = 130;
+
+/// Exit status for `error`; see "Exit codes" in the README.
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::InvalidInput(_) => 2,
+        Error::NoCapsules => 3,
+        Error::CapsuleNotFound(_)
+        | Error::NoSuchIndex { .. }
+        | Error::NotInCapsule { .. }
+        | Error::NoneAsOf(_) => 4,
+        Error::LockHeld(_) => 5,
+        Error::VerificationFailed(_) => 6,
+        Error::BadArchive { .. } | Error::Zip(_) | Error::Manifest(_) => 7,
+        Error::Io(e) if e.kind() == io::ErrorKind::Interrupted => EXIT_CANCELLED,
+        _ => 1,
+    }
+}
+
+fn home() -> Result<PathBuf> {
+    home_dir().ok_or(Error::NoHome)
+}
+
+/// Apply `--color`; `auto` leaves the decision to `console`, which checks for
+/// a terminal, but also honours `NO_COLOR`.
+fn set_colors(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Auto if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => false,
+        ColorChoice::Auto => return,
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let prompter = Prompter::new(!cli.yes);
+    let mode = cli.progress;
+    let icons = cli.icons;
+    let verbose = cli.verbose > 0;
+    match cli.into_command() {
+        Some(Command::Create(args)) => create_capsule(args, &prompter, mode),
+        Some(Command::List(args)) => list_capsules(args, icons),
+        Some(Command::Restore(args)) => restore_capsule(args, &prompter, mode, verbose),
+        Some(Command::Diff {
+            a,
+            b,
+            live,
+            content,
+            only,
+            output,
+        }) => diff_capsules(&a, b.as_deref().filter(|_| !live), content, &only, output),
+        Some(Command::History {
+            path,
+            restore,
+            at,
+            output,
+        }) => match at.filter(|_| restore) {
+            Some(at) => restore_file(&at, &path, None),
+            None => file_history(&path, output),
+        },
+        Some(Command::Grep {
+            pattern,
+            capsule,
+            ignore_case,
+            fixed_strings,
+            name,
+            output,
+        }) => grep_capsules(
+            &pattern,
+            capsule.as_deref(),
+            ignore_case,
+            fixed_strings,
+            name,
+            output,
+        ),
+        Some(Command::Inspect {
+            capsule,
+            subpath,
+            depth,
+            output,
+        }) => inspect_capsule(&capsule, subpath.as_deref(), depth, output),
+        Some(Command::Show { capsule, path }) => show_file(&capsule, &path),
+        Some(Command::Export {
+            capsule,
+            chezmoi: Some(dir),
+            ..
+        }) => export_dotfiles(&capsule, &dir, DotfilesLayout::Chezmoi),
+        Some(Command::Export {
+            capsule,
+            stow: Some(dir),
+            ..
+        }) => export_dotfiles(&capsule, &dir, DotfilesLayout::Stow),
+        Some(Command::Export {
+            capsule,
+            format,
+            output,
+            ..
+        }) => export_capsule(&capsule, format, output),
+        Some(Command::Clone {
+            remote,
+            capsules,
+            only,
+            limit_rate,
+        }) => clone_capsules(&remote, &capsules, &only, limit_rate),
+        Some(Command::Import { path, from_git }) => {
+            import_capsule(path.as_deref(), from_git.as_deref())
+        }
+        Some(Command::RestoreFile { capsule, path, to }) => {
+            restore_file(&capsule, &path, to.as_deref())
+        }
+        Some(Command::Mount {
+            capsule,
+            mountpoint,
+            store,
+        }) => mount(capsule.as_deref(), mountpoint.as_deref(), store.as_deref()),
+        Some(Command::Unmount { mountpoint }) => {
+            let count = nvim_time_machine::unmount(&mountpoint)?;
+            println!(
+                "Unmounted {} file system(s) at {}",
+                count,
+                mountpoint.display()
+            );
+            Ok(())
+        }
+        Some(Command::Try {
+            capsule,
+            appname,
+            launch,
+        }) => try_capsule(&capsule, &appname, launch, mode),
+        Some(Command::Delete {
+            name,
+            index,
+            older_than,
+            force,
+        }) => delete_capsules(&name, &index, older_than, force, &prompter),
+        Some(Command::Rename { old, new }) => rename_capsule(&old, &new),
+        Some(Command::Merge { a, b, name }) => merge(&a, &b, &name),
+        Some(Command::Tag(command)) => tag_command(command),
+        Some(Command::Status { output }) => show_status(output),
+        Some(Command::Verify {
+            capsule,
+            all: _,
+            output,
+        }) => verify_capsules(capsule.as_deref(), output, mode),
+        Some(Command::Tui) => match tui::run(&home()?)? {
+            tui::Exit::Quit => Ok(()),
+            tui::Exit::Restore(name) => restore_capsule(
+                RestoreArgs {
+                    name: Some(name),
+                    ..RestoreArgs::default()
+                },
+                &prompter,
+                mode,
+                verbose,
+            ),
+        },
+        Some(Command::Doctor { output }) => doctor(output),
+        Some(Command::Stats { top, output }) => show_stats(top, output),
+        Some(Command::Prune(args)) => prune_capsules(args, &prompter),
+        Some(Command::Pin { capsules }) => pin_capsules(&capsules, true),
+        Some(Command::Unpin { capsules }) => pin_capsules(&capsules, false),
+        Some(Command::Note(command)) => note_command(command, &prompter),
+        Some(Command::Migrate) => migrate_capsules(),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "nvimTimeMachine",
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+        Some(Command::Mangen { out_dir }) => write_man_pages(&out_dir),
+        None => Ok(()),
+    }
+}
+
+fn create_capsule(args: CreateArgs, prompter: &Prompter, mode: ProgressMode) -> Result<()> {
+    let home = home()?;
+    let mut config = Config::load(&home)?;
+    if let Some(template) = &args.name {
+        config.name_template = Some(template.clone());
+    }
+    // what to back up, with how its capsules are named
+    let mut jobs: Vec<(String, Archiver, CapsuleNaming)> = if let Some(name) = &args.profile {
+        let profile = config.profile(name)?;
+        let archiver = Archiver::new(&home)
+            .symlinks(args.symlinks())
+            .reproducible(args.reproducible)
+            .profile(name, profile)?;
+        let naming = config.naming(&profile.prefix(name), name);
+        vec![(name.clone(), archiver, naming)]
+    } else {
+        let appnames = if args.all_appnames {
+            let appnames = find_appnames(&home);
+            if appnames.is_empty() {
+                return Err(Error::InvalidInput(
+                    "no Neovim configuration with an init.lua or init.vim found".to_string(),
+                ));
+            }
+            appnames
+        } else {
+            let appname = args.appname.as_deref().unwrap_or(DEFAULT_APPNAME);
+            check_appname(appname)?;
+            vec![appname.to_string()]
+        };
+        appnames
+            .into_iter()
+            .map(|a| {
+                let archiver = Archiver::new(&home)
+                    .symlinks(args.symlinks())
+                    .reproducible(args.reproducible)
+                    .appname(&a);
+                let naming = config.naming(&capsule_prefix(&a), &a);
+                (a, archiver, naming)
+            })
+            .collect()
+    };
+    for (_, archiver, naming) in &mut jobs {
+        *naming = naming.clone().note(args.message.as_deref());
+        if args.portable {
+            *archiver = archiver.clone().portable()?;
+        }
+        if args.plugins_only {
+            *archiver = archiver.clone().plugins_only()?;
+        }
+        if let Some(ByteSize(size)) = args.split_size {
+            *archiver = archiver.clone().split(size)?;
+        }
     }
 
+    let budget = args.max_size.or(config.max_size);
+    if args.dry_run {
+        for (name, archiver, _) in &jobs {
+            let preview = archiver.preview()?;
+            if jobs.len() > 1 {
+                println!("{}:", style(name).bold());
+            }
+            for (source, dir, files, bytes) in &preview.sources {
+                println!(
+                    "{:<6} {} files, {}  {}",
+                    source,
+                    files,
+                    HumanBytes(*bytes),
+                    dir.display()
+                );
+            }
+            for path in &preview.excluded {
+                println!("excluded  {} (not a regular file)", path.display());
+            }
+            println!(
+                "Would archive {} files ({}); nothing was written.",
+                preview.file_count(),
+                HumanBytes(preview.total_size())
+            );
+            if let Some(budget) = budget {
+                print_over_budget(name, &preview, budget);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(budget) = budget {
+        for (name, archiver, _) in &jobs {
+            let preview = archiver.preview()?;
+            if print_over_budget(name, &preview, budget)
+                && !prompter.confirm("Create it anyway?", false)?
+            {
+                return Err(Error::InvalidInput(format!(
+                    "the capsule of {} would be over the size budget of {}",
+                    name, budget
+                )));
+            }
+        }
+    }
+
+    let store = CapsuleStore::in_home(&home);
+    let lock = store.lock()?;
+    // only Neovim's config directories have a branch in the history
+    let git_history = args.profile.is_none() && config.git_history;
+    let mut created = Vec::new();
+    for (name, archiver, naming) in &jobs {
+        if args.snapshot {
+            let snapshots = store.snapshots();
+            let previous = snapshots.latest_named(naming)?;
+            let dest = snapshots.new_snapshot_path_named(naming)?;
+            let (manifest, linked) = archiver.snapshot(
+                &dest,
+                previous.as_ref(),
+                &*progress(mode, "create", "🕒 Snapshot created!"),
+            )?;
+            println!(
+                "Created snapshot \"{}\": {} files, {} unchanged and linked",
+                Snapshot::from_path(&dest).name(),
+                manifest.file_count,
+                linked
+            );
+            if let Some(previous) = &previous {
+                let old = previous.manifest()?;
+                print_diffstat(previous.name(), &old.files, &manifest.files);
+            }
+            if git_history {
+                commit_history(
+                    &store,
+                    &home,
+                    name,
+                    Snapshot::from_path(&dest).name(),
+                    &args,
+                );
+            }
+            created.push(dest);
+            continue;
+        }
+        let previous = store.latest_named(naming)?;
+        let interrupted = if args.resume {
+            store.interrupted(naming)?
+        } else {
+            None
+        };
+        let bar = progress(mode, "create", "🕒 Capsule created!");
+        let (zip_path, manifest) = match interrupted {
+            Some(zip_path) => {
+                println!(
+                    "Resuming \"{}\"",
+                    zip_path.file_name().unwrap_or_default().to_string_lossy()
+                );
+                let manifest = archiver.resume(&zip_path, &*bar)?;
+                (zip_path, manifest)
+            }
+            None => {
+                let zip_path = store.new_capsule_path_named(naming)?;
+                let manifest = archiver.create(&zip_path, &*bar)?;
+                (zip_path, manifest)
+            }
+        };
+        store.index_capsule(&Capsule::from_path(&zip_path));
+        if jobs.len() > 1 {
+            println!(
+                "Created \"{}\" for {}",
+                Capsule::from_path(&zip_path).name(),
+                name
+            );
+        }
+        // a legacy or damaged predecessor just goes without
+        if let Some((previous, old)) = previous
+            .as_ref()
+            .and_then(|c| Some((c, c.manifest().ok()??)))
+        {
+            print_diffstat(previous.name(), &old.files, &manifest.files);
+        }
+        if git_history {
+            commit_history(
+                &store,
+                &home,
+                name,
+                Capsule::from_path(&zip_path).name(),
+                &args,
+            );
+        }
+        let volumes = Capsule::from_path(&zip_path).volumes().len();
+        if volumes > 1 {
+            println!("Split into {} volumes", volumes);
+        }
+        if args.verify {
+            let damaged = Capsule::from_path(&zip_path).verify(&*progress(mode, "verify", ""))?;
+            for entry in &damaged {
+                println!(
+                    "{}  {}: {}",
+                    style("DAMAGED").red(),
+                    entry.path,
+                    entry.problem
+                );
+            }
+            if !damaged.is_empty() {
+                return Err(Error::VerificationFailed(format!(
+                    "{} doesn't match what was read ({} entries)",
+                    zip_path.display(),
+                    damaged.len()
+                )));
+            }
+            println!("Verified {}", zip_path.display());
+        }
+        if let Some(message) = &args.message {
+            let capsule = Capsule::from_path(&zip_path);
+            let mut meta = CapsuleMeta::default();
+            meta.set_note(message);
+            capsule.set_meta(&meta)?;
+        }
+        created.push(zip_path);
+    }
+
+    if args.prune {
+        let policy = args.retention.policy();
+        for (_, _, naming) in &jobs {
+            // never throw away what was just written
+            for capsule in store.prune_named(&lock, &policy, naming, &created)? {
+                println!("Pruned \"{}\"", capsule.name());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Commit the config directory of `appname` to the store's git history, as
+/// of the capsule `capsule`. The capsule is what counts, so a failure is only
+/// a warning.
+fn commit_history(
+    store: &CapsuleStore,
+    home: &Path,
+    appname: &str,
+    capsule: &str,
+    args: &CreateArgs,
+) {
+    let message = match &args.message {
+        Some(note) => format!("{}\n\n{}", capsule, note),
+        None => capsule.to_string(),
+    };
+    let history = store.git_history();
+    match history.commit(&SourceKind::Config.dir(home, appname), appname, &message) {
+        Ok(Some(commit)) => println!(
+            "Committed {} to {} (branch {})",
+            commit,
+            history.git_dir().display(),
+            appname
+        ),
+        Ok(None) => {}
+        Err(e) => warn!(error = %e, "could not commit the config history"),
+    }
+}
+
+/// One line of how many files were added, modified and removed since `previous`.
+fn print_diffstat(previous: &str, old: &[ManifestEntry], new: &[ManifestEntry]) {
+    let stat = DiffStat::of(&diff_files(old, new));
+    if stat.is_empty() {
+        println!("No changes since \"{}\"", previous);
+        return;
+    }
+    let sign = if stat.delta_bytes < 0 { "-" } else { "+" };
+    println!(
+        "{} added, {} modified, {} removed ({}{}) since \"{}\"",
+        style(stat.added).green(),
+        style(stat.modified).yellow(),
+        style(stat.removed).red(),
+        sign,
+        HumanBytes(stat.delta_bytes.unsigned_abs()),
+        previous
+    );
+}
+
+/// Warn, with the biggest directories, if `preview` comes to more than
+/// `budget`; returns whether it does.
+fn print_over_budget(name: &str, preview: &CreatePreview, budget: ByteSize) -> bool {
+    if preview.total_size() <= budget.0 {
+        return false;
+    }
+    println!(
+        "{} the capsule of {} would be {} before compression, over the budget of {}. Largest directories:",
+        style("warning:").yellow(),
+        name,
+        HumanBytes(preview.total_size()),
+        budget
+    );
+    for dir in &preview.largest_dirs {
+        println!("  {:>10}  {}", HumanBytes(dir.size).to_string(), dir.path);
+    }
+    true
+}
+
+/// Tell which capsules were moved to the `corrupt` directory, and why.
+fn print_quarantined(quarantined: &[Quarantined]) {
+    for capsule in quarantined {
+        println!(
+            "{} \"{}\" is unreadable ({}); moved it to {}",
+            style("warning:").yellow(),
+            capsule.name,
+            capsule.reason,
+            capsule.path.display()
+        );
+    }
+}
+
+/// Reject app names Neovim would not turn into a directory next to `~/.config/nvim`.
+fn check_appname(appname: &str) -> Result<()> {
+    if appname.is_empty() || appname.starts_with('.') || appname.contains(['/', '\\']) {
+        return Err(Error::InvalidInput(format!(
+            "invalid NVIM_APPNAME \"{}\"",
+            appname
+        )));
+    }
     Ok(())
 }
 
-fn create_capsule() -> io::Result<()> {
-    let home = home_dir().expect("Could not find HOME");
-    let sources = [
-        home.join(".local/share/nvim"),
-        home.join(".config/nvim"),
-        home.join(".cache/nvim"),
-    ];
-    let capsule_dir = home.join(".nvim_capsules");
-    fs::create_dir_all(&capsule_dir)?;
+fn list_capsules(args: ListArgs, icons: IconSet) -> Result<()> {
+    let home = home()?;
+    if let Some(remote) = &args.remote {
+        let remote = SshRemote::resolve(&Config::load(&home)?, remote);
+        return list_remote(&remote, &args, icons);
+    }
+    let store = CapsuleStore::in_home(&home);
+    if args.snapshots {
+        return list_snapshots(&store.snapshots(), &args, icons);
+    }
+    // only while no other process works on the store; until then a damaged
+    // capsule is listed as unreadable
+    if store.exists() {
+        if let Some(lock) = store.try_lock()? {
+            let quarantined = store.quarantine_unreadable(&lock)?;
+            if args.output != OutputFormat::Json {
+                print_quarantined(&quarantined);
+            }
+        }
+    }
+    let naming = match &args.profile {
+        Some(name) => Some(Config::load(&home)?.profile_naming(name)?),
+        None => None,
+    };
+    // keep the store-wide numbering, so it can be passed to --index
+    let mut capsules = Vec::new();
+    for (i, capsule) in store.list()?.into_iter().enumerate() {
+        if naming.as_ref().is_some_and(|n| !n.matches(capsule.name())) {
+            continue;
+        }
+        let meta = capsule.meta()?;
+        if args.tag.as_deref().is_none_or(|t| meta.has_tag(t)) {
+            let size = capsule.size()?;
+            capsules.push((i + 1, capsule, meta, size));
+        }
+    }
+    match args.sort {
+        // the store already lists capsules by creation time
+        SortKey::Date => {}
+        SortKey::Size => capsules.sort_by_key(|(_, _, _, size)| *size),
+        SortKey::Name => capsules.sort_by(|a, b| a.1.name().cmp(b.1.name())),
+    }
+    if args.reverse {
+        capsules.reverse();
+    }
+    if let Some(limit) = args.limit {
+        capsules.truncate(limit);
+    }
+
+    if args.output == OutputFormat::Json {
+        let infos = capsules
+            .iter()
+            .map(|(_, c, _, _)| c.info())
+            .collect::<Result<Vec<_>>>()?;
+        return print_json(&infos);
+    }
 
-    let timestamp = Local::now().format("%Y%m%d%H%M%S");
-    let zip_path = capsule_dir.join(format!("nvim_backup_{}.zip", timestamp));
+    if capsules.is_empty() {
+        println!("No capsules found.");
+        return Ok(());
+    }
+
+    for (idx, capsule, meta, size) in &capsules {
+        let created = capsule
+            .created()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        // a damaged archive is still listed, so it can be found and deleted
+        let files = match capsule.info() {
+            Ok(info) => format!("{} files", info.file_count),
+            Err(_) => style("unreadable").red().to_string(),
+        };
+        let tags = if meta.tags.is_empty() {
+            String::new()
+        } else {
+            let tags: Vec<&str> = meta.tags.iter().map(String::as_str).collect();
+            format!(" {}", style(format!("[{}]", tags.join(", "))).cyan())
+        };
+        let pinned = if meta.pinned {
+            format!(" {}", style(icons.pinned()).yellow())
+        } else {
+            String::new()
+        };
+        let note = meta
+            .summary()
+            .map(|n| format!(" {}", style(format!("- {}", n)).dim()))
+            .unwrap_or_default();
+        println!(
+            "[{}]:{}: {:<16}  {:>10}  {:>11}  \"{}\"{}{}{}",
+            style(icons.capsule()).yellow(),
+            style(format!("({})", idx)).green(),
+            created,
+            HumanBytes(*size).to_string(),
+            files,
+            capsule.name(),
+            pinned,
+            tags,
+            note
+        );
+        if args.long {
+            let environment = capsule
+                .manifest()
+                .ok()
+                .flatten()
+                .and_then(|m| m.environment);
+            print_environment(environment.as_ref(), "      ");
+            for section in capsule.sections().unwrap_or_default() {
+                println!(
+                    "      {:<20} {:>6} files  {:>10}  ({} packed)",
+                    section.source,
+                    section.file_count,
+                    HumanBytes(section.size).to_string(),
+                    HumanBytes(section.compressed_size)
+                );
+            }
+        }
+    }
 
-    // count files
-    let total = sources
+    Ok(())
+}
+
+fn list_snapshots(snapshots: &SnapshotStore, args: &ListArgs, icons: IconSet) -> Result<()> {
+    let mut infos = snapshots
+        .list()?
         .iter()
-        .flat_map(|d| WalkDir::new(d).into_iter().filter_map(Result::ok))
-        .filter(|e| e.file_type().is_file())
-        .count() as u64;
+        .map(Snapshot::info)
+        .collect::<Result<Vec<_>>>()?;
+    match args.sort {
+        SortKey::Date => {}
+        SortKey::Size => infos.sort_by_key(|i| i.total_size),
+        SortKey::Name => infos.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+    if args.reverse {
+        infos.reverse();
+    }
+    if let Some(limit) = args.limit {
+        infos.truncate(limit);
+    }
+    if args.output == OutputFormat::Json {
+        return print_json(&infos);
+    }
+    if infos.is_empty() {
+        println!("No snapshots found.");
+        return Ok(());
+    }
+    for info in &infos {
+        println!(
+            "[{}]: {:<16}  {:>10}  {:>11}  {:>10} own  \"{}\"",
+            style(icons.capsule()).yellow(),
+            info.created.format("%Y-%m-%d %H:%M"),
+            HumanBytes(info.total_size).to_string(),
+            format!("{} files", info.file_count),
+            HumanBytes(info.unique_size).to_string(),
+            info.name
+        );
+    }
+    Ok(())
+}
 
-    let pb = ProgressBar::new(total);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
-            )
-            .expect("invalid progress bar template"),
+fn list_remote(remote: &SshRemote, args: &ListArgs, icons: IconSet) -> Result<()> {
+    let mut capsules: Vec<(usize, RemoteCapsule)> =
+        remote.list()?.into_iter().enumerate().collect();
+    match args.sort {
+        SortKey::Date => {}
+        SortKey::Size => capsules.sort_by_key(|(_, c)| c.size),
+        SortKey::Name => capsules.sort_by(|a, b| a.1.name.cmp(&b.1.name)),
+    }
+    if args.reverse {
+        capsules.reverse();
+    }
+    if let Some(limit) = args.limit {
+        capsules.truncate(limit);
+    }
+
+    if args.output == OutputFormat::Json {
+        let capsules: Vec<&RemoteCapsule> = capsules.iter().map(|(_, c)| c).collect();
+        return print_json(&capsules);
+    }
+    if capsules.is_empty() {
+        println!("No capsules found on {}.", remote.host());
+        return Ok(());
+    }
+    for (i, capsule) in &capsules {
+        let created = capsule
+            .created
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let volumes = if capsule.volumes > 1 {
+            format!(" ({} volumes)", capsule.volumes)
+        } else {
+            String::new()
+        };
+        println!(
+            "[{}]:{}: {:<16}  {:>10}  \"{}\"{}",
+            style(icons.capsule()).yellow(),
+            style(format!("({})", i + 1)).green(),
+            created,
+            HumanBytes(capsule.size).to_string(),
+            capsule.name,
+            volumes
+        );
+    }
+    Ok(())
+}
+
+/// Where a capsule was created, on one or two lines starting with `indent`.
+fn print_environment(environment: Option<&Environment>, indent: &str) {
+    let Some(environment) = environment else {
+        println!("{}{}", indent, style("no environment recorded").dim());
+        return;
+    };
+    println!(
+        "{}{} on {}/{}{}",
+        indent,
+        environment
+            .nvim_release()
+            .map_or_else(|| "unknown Neovim".to_string(), |r| format!("Neovim {}", r)),
+        environment.os,
+        environment.arch,
+        environment
+            .hostname
+            .as_ref()
+            .map(|h| format!(", host {}", h))
+            .unwrap_or_default()
+    );
+    if !environment.lock_files.is_empty() {
+        let names: Vec<&str> = environment
+            .lock_files
+            .keys()
+            .map(|k| k.rsplit('/').next().unwrap_or(k))
+            .collect();
+        let plugins = environment
+            .locked_plugins()
+            .map(|n| format!(" ({} plugins locked)", n))
+            .unwrap_or_default();
+        println!("{}lock files: {}{}", indent, names.join(", "), plugins);
+    }
+}
+
+/// One line describing a capsule in a picker: date, size, name, tags and note.
+fn capsule_row(capsule: &Capsule) -> Result<String> {
+    let meta = capsule.meta()?;
+    let mut row = format!(
+        "{:<16}  {:>10}  {}",
+        capsule
+            .created()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default(),
+        HumanBytes(capsule.size()?).to_string(),
+        capsule.name()
     );
-    let file = fs::File::create(&zip_path)?;
-    let mut zip = ZipWriter::new(file);
-    let options: FileOptions<'_, ()> = FileOptions::default()
-        .compression_method(CompressionMethod::Deflated)
-        .unix_permissions(0o755);
-    for dir in &sources {
-        for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
-            let path = entry.path();
-            if path.is_file() {
-                pb.inc(1);
-                // build the path inside the zip so it’s relative
-                let rel = path.strip_prefix(&home).unwrap();
-                zip.start_file_from_path(rel, options)?;
-                let mut f = fs::File::open(path)?;
-                let mut buf = Vec::new();
-                f.read_to_end(&mut buf)?;
-                zip.write_all(&buf)?;
+    if !meta.tags.is_empty() {
+        let tags: Vec<&str> = meta.tags.iter().map(String::as_str).collect();
+        row.push_str(&format!("  [{}]", tags.join(", ")));
+    }
+    if let Some(note) = meta.summary() {
+        row.push_str(&format!("  - {}", note));
+    }
+    Ok(row)
+}
+
+/// Restore a capsule picked by `args` or interactively. With `verbose`, every
+/// file the restore touches is listed before asking for confirmation.
+fn restore_capsule(
+    args: RestoreArgs,
+    prompter: &Prompter,
+    mode: ProgressMode,
+    verbose: bool,
+) -> Result<()> {
+    let home = home()?;
+    let store = CapsuleStore::in_home(&home);
+    let lock = store.lock()?;
+    if args.remote.is_none() && args.snapshot.is_none() {
+        // so they aren't offered below
+        print_quarantined(&store.quarantine_unreadable(&lock)?);
+        if store.list()?.is_empty() {
+            return Err(Error::NoCapsules);
+        }
+    }
+
+    // kept until the restore is done, then removed
+    let fetched;
+    let capsule = if let Some(remote) = &args.remote {
+        let mut remote = SshRemote::resolve(&Config::load(&home)?, remote);
+        if let Some(ByteSize(rate)) = args.limit_rate {
+            remote = remote.limit_rate(rate);
+        }
+        let chosen = pick_remote_capsule(&remote, &args, &home, prompter)?;
+        println!(
+            "Downloading \"{}\" ({}) from {}",
+            chosen.name,
+            HumanBytes(chosen.size),
+            remote.host()
+        );
+        fetched = remote.fetch(&chosen)?;
+        fetched.capsule().clone()
+    } else if let Some(name) = &args.snapshot {
+        fetched = store.snapshots().find(name)?.pack()?;
+        fetched.capsule().clone()
+    } else if let Some(name) = &args.name {
+        store.find(name)?
+    } else if let Some(index) = args.index {
+        store.nth(index)?
+    } else if let Some(Moment(moment)) = args.at {
+        let capsule = store.as_of(moment)?;
+        let created = capsule.created().map(|t| t.format("%Y-%m-%d %H:%M"));
+        println!(
+            "Using \"{}\" from {}",
+            capsule.name(),
+            created.map(|t| t.to_string()).unwrap_or_default()
+        );
+        capsule
+    } else {
+        let mut capsules = store.list()?;
+        if let Some(tag) = &args.tag {
+            let mut tagged = Vec::new();
+            for capsule in capsules {
+                if capsule.meta()?.has_tag(tag) {
+                    tagged.push(capsule);
+                }
+            }
+            if tagged.is_empty() {
+                return Err(Error::InvalidInput(format!(
+                    "no capsules tagged \"{}\"",
+                    tag
+                )));
+            }
+            capsules = tagged;
+        }
+        if let Some(name) = &args.profile {
+            let naming = Config::load(&home)?.profile_naming(name)?;
+            capsules.retain(|c| naming.matches(c.name()));
+            if capsules.is_empty() {
+                return Err(Error::InvalidInput(format!(
+                    "no capsules of profile \"{}\"",
+                    name
+                )));
             }
         }
+        if args.latest {
+            capsules.pop().ok_or(Error::NoCapsules)?
+        } else {
+            // newest first, which is what you usually want
+            let rows = capsules
+                .iter()
+                .rev()
+                .map(capsule_row)
+                .collect::<Result<Vec<_>>>()?;
+            let selection = prompter.choose(
+                "Select a capsule to restore (type to search)",
+                &rows,
+                "no capsule selected (pass --latest, --name, --index or --at)",
+            )?;
+            capsules.swap_remove(capsules.len() - 1 - selection)
+        }
+    };
+
+    check_compatibility(&capsule, &home, &args, prompter)?;
+
+    let filter = EntryFilter::new(&args.include, &args.exclude)?;
+    let root = args.to_dir.clone().unwrap_or_else(|| home.clone());
+    let appname = match &args.appname {
+        Some(appname) => appname.clone(),
+        None => capsule.appname()?,
+    };
+    check_appname(&appname)?;
+    let mut base = Restorer::for_capsule(&root, &capsule)?.appname(&appname);
+    if args.to_dir.is_some() {
+        base = base.plain_layout();
+    }
+    // a profile capsule brings its own directories
+    let profile_dirs = capsule.profile_dirs()?;
+    let sources = if !profile_dirs.is_empty() {
+        if !args.only.is_empty() {
+            return Err(Error::InvalidInput(
+                "--only selects Neovim directories; this is a profile capsule".to_string(),
+            ));
+        }
+        Vec::new()
+    } else if args.only.is_empty() {
+        let restorer = base.clone();
+        let items: Vec<String> = restorer
+            .sources()
+            .iter()
+            .map(|&kind| format!("{:<6} {}", kind, restorer.target_dir(kind).display()))
+            .collect();
+        let chosen =
+            prompter.multi_select("Directories to restore", &items, &vec![true; items.len()])?;
+        chosen.into_iter().map(|i| restorer.sources()[i]).collect()
+    } else {
+        args.only
+    };
+
+    // an alternate target, a filtered or a layered restore only touches the extracted files
+    let layered = !filter.is_empty() || args.strategy.is_some();
+    let (existing, auto_capsule) = if args.to_dir.is_some() {
+        (ExistingDirs::Keep, false)
+    } else if args.dry_run {
+        // backing up and deleting displace the same files
+        let existing = if layered {
+            ExistingDirs::Keep
+        } else {
+            ExistingDirs::Delete
+        };
+        (existing, false)
+    } else if layered {
+        let auto_capsule = prompter.confirm(
+            "Create a capsule of the current state first (to undo the restore)?",
+            true,
+        )?;
+        (ExistingDirs::Keep, auto_capsule)
+    } else {
+        let prompt = if profile_dirs.is_empty() {
+            "What to do with the current Neovim directories?"
+        } else {
+            "What to do with the current directories of the profile?"
+        };
+        let choice = prompter.select(
+            prompt,
+            &[
+                "Save them as a new capsule, then replace them (undo = restore that capsule)",
+                "Back up (rename with timestamp)",
+                "Delete",
+            ],
+            0,
+        )?;
+        match choice {
+            0 => (ExistingDirs::Delete, true),
+            1 => (ExistingDirs::Backup, false),
+            _ => (ExistingDirs::Delete, false),
+        }
+    };
+
+    let strategy = args.strategy.unwrap_or_default();
+    let mut restorer = base.only(&sources).filter(filter).strategy(strategy);
+    if let Some(jobs) = args.jobs {
+        restorer = restorer.jobs(jobs);
+    }
+    let mut plan = restorer.plan(&capsule, existing)?;
+
+    if args.dry_run {
+        let [created, overwritten, skipped, removed] =
+            summarize_plan(&restorer, &plan, existing, true);
+        println!(
+            "Would create {}, overwrite {}, skip {} and remove {} files; nothing was changed.",
+            created, overwritten, skipped, removed
+        );
+        return Ok(());
+    }
+
+    if strategy == ConflictStrategy::Prompt {
+        for file in plan
+            .files
+            .iter_mut()
+            .filter(|f| f.action == Action::Overwrite && !f.is_dir)
+        {
+            let overwrite =
+                prompter.confirm(&format!("Overwrite \"{}\"?", file.target.display()), false)?;
+            if !overwrite {
+                file.action = Action::Skip;
+            }
+        }
+    }
+
+    // nothing has been touched yet, so this is the last chance to back out
+    let [created, overwritten, skipped, removed] =
+        summarize_plan(&restorer, &plan, existing, verbose);
+    println!(
+        "This will create {}, overwrite {}, skip {} and remove {} files.",
+        created, overwritten, skipped, removed
+    );
+    // without a terminal to ask, -y is the confirmation
+    if !args.force && prompter.is_interactive() && !prompter.confirm("Restore?", false)? {
+        println!("Nothing restored.");
+        return Ok(());
+    }
+
+    if auto_capsule {
+        let (archiver, naming) = restorer.current_state(&Config::load(&home)?, &capsule)?;
+        let path = store.new_capsule_path_named(&naming)?;
+        archiver.create(
+            &path,
+            &*progress(mode, "snapshot", "🕒 Current state saved!"),
+        )?;
+        store.index_capsule(&Capsule::from_path(&path));
+        println!(
+            "Saved the current state as \"{}\"; restore it to undo.",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
     }
 
-    zip.finish()?;
-    pb.finish_with_message("🕒 Capsule created!");
+    restorer.execute(
+        &capsule,
+        &plan,
+        existing,
+        &*progress(mode, "restore", "🕒 Restoration complete!"),
+    )?;
+    if capsule.manifest()?.is_some_and(|m| m.plugins_only) {
+        restorer.restore_snapshots(&capsule)?;
+        if args.to_dir.is_none() && !args.no_sync {
+            sync_plugins(&home, &appname)?;
+        }
+    }
+    if let Some(dir) = &args.to_dir {
+        println!("Restored into \"{}\"", dir.display());
+    }
     Ok(())
 }
 
-fn list_capsules() -> io::Result<()> {
-    let capsule_dir = home_dir().expect("HOME not set").join(".nvim_capsules");
+/// The remote capsule `--name`, `--index`, `--at` or `--latest` select, or
+/// else the one picked from a list.
+fn pick_remote_capsule(
+    remote: &SshRemote,
+    args: &RestoreArgs,
+    home: &Path,
+    prompter: &Prompter,
+) -> Result<RemoteCapsule> {
+    let mut capsules = remote.list()?;
+    if let Some(name) = &args.profile {
+        let naming = Config::load(home)?.profile_naming(name)?;
+        capsules.retain(|c| naming.matches(&c.name));
+    }
+    if capsules.is_empty() {
+        return Err(Error::NoCapsules);
+    }
+    let suffix = format!(".{}", CAPSULE_EXTENSION);
+    if let Some(name) = &args.name {
+        let i = capsules
+            .iter()
+            .position(|c| c.name == *name || c.name.strip_suffix(&suffix) == Some(name))
+            .ok_or_else(|| Error::CapsuleNotFound(name.clone()))?;
+        Ok(capsules.swap_remove(i))
+    } else if let Some(index) = args.index {
+        let count = capsules.len();
+        if index == 0 || index > count {
+            return Err(Error::NoSuchIndex { index, count });
+        }
+        Ok(capsules.swap_remove(index - 1))
+    } else if let Some(Moment(moment)) = args.at {
+        capsules
+            .into_iter()
+            .filter(|c| c.created.is_some_and(|t| t <= moment))
+            .max_by_key(|c| c.created)
+            .ok_or_else(|| Error::NoneAsOf(moment.format("%Y-%m-%d %H:%M").to_string()))
+    } else if args.latest {
+        capsules.pop().ok_or(Error::NoCapsules)
+    } else {
+        let rows: Vec<String> = capsules
+            .iter()
+            .rev()
+            .map(|c| {
+                format!(
+                    "{:<16}  {:>10}  {}",
+                    c.created
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default(),
+                    HumanBytes(c.size).to_string(),
+                    c.name
+                )
+            })
+            .collect();
+        let selection = prompter.choose(
+            &format!("Select a capsule on {} to restore", remote.host()),
+            &rows,
+            "no capsule selected (pass --latest, --name, --index or --at)",
+        )?;
+        Ok(capsules.swap_remove(capsules.len() - 1 - selection))
+    }
+}
 
-    if !capsule_dir.exists() {
-        println!("No capsules found.");
+/// Refuse to restore a capsule from another OS, architecture or Neovim
+/// release into the live directories, where its compiled parsers and plugin
+/// builds would break, unless confirmed or forced.
+fn check_compatibility(
+    capsule: &Capsule,
+    home: &Path,
+    args: &RestoreArgs,
+    prompter: &Prompter,
+) -> Result<()> {
+    let Some(manifest) = capsule.manifest()? else {
+        return Ok(());
+    };
+    let Some(environment) = &manifest.environment else {
+        return Ok(());
+    };
+    let current = Environment::capture(home, &manifest.appname);
+    let mismatches = environment.mismatches(&current, manifest.portable);
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    for mismatch in &mismatches {
+        warn!(capsule = capsule.name(), "{}", mismatch);
+    }
+    // a separate directory or a dry run can't break the live setup
+    if args.force || args.to_dir.is_some() || args.dry_run {
         return Ok(());
     }
+    if prompter.confirm(
+        "The capsule may not work here (native plugins, parsers). Restore anyway?",
+        false,
+    )? {
+        return Ok(());
+    }
+    Err(Error::InvalidInput(format!(
+        "\"{}\" was {}; pass --force to restore it anyway",
+        capsule.name(),
+        mismatches.join(" and ")
+    )))
+}
 
-    // gather all .zip files
-    let mut entries: Vec<_> = fs::read_dir(&capsule_dir)?
-        .filter_map(Result::ok)
-        .filter(|e| e.path().extension() == Some(OsStr::new("zip")))
-        .collect();
+/// Have the plugin manager of `appname` install the plugins its lock file names.
+fn sync_plugins(home: &Path, appname: &str) -> Result<()> {
+    let Some(manager) = PluginManager::detect(home, appname) else {
+        println!("No plugin manager found; start Neovim to install the plugins.");
+        return Ok(());
+    };
+    println!("Installing plugins with {}...", manager);
+    let status = process::Command::new("nvim")
+        .args(manager.sync_args())
+        .env("NVIM_APPNAME", appname)
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!(%status, "plugin sync failed; run it yourself in Neovim"),
+        Err(e) => warn!(error = %e, "could not run nvim; install the plugins yourself"),
+    }
+    Ok(())
+}
 
-    // sort by modified time (oldest first)
-    entries.sort_by_key(|e| e.metadata().and_then(|m| m.modified()).ok());
+/// Count the files `plan` creates, overwrites, skips and removes, listing
+/// each of them if `list`.
+fn summarize_plan(
+    restorer: &Restorer,
+    plan: &RestorePlan,
+    existing: ExistingDirs,
+    list: bool,
+) -> [usize; 4] {
+    let (mut created, mut overwritten, mut skipped) = (0, 0, 0);
+    for file in plan.files.iter().filter(|f| !f.is_dir) {
+        // with the directories replaced wholesale everything is a create,
+        // but files that exist now still get overwritten
+        let action = match file.action {
+            Action::Create if file.target.exists() => Action::Overwrite,
+            action => action,
+        };
+        let mark = match action {
+            Action::Create => {
+                created += 1;
+                "create   "
+            }
+            Action::Overwrite => {
+                overwritten += 1;
+                "overwrite"
+            }
+            Action::Skip => {
+                skipped += 1;
+                "skip     "
+            }
+        };
+        if list {
+            println!("{}  {}", mark, file.target.display());
+        }
+    }
+    let displaced = restorer.displaced(plan, existing);
+    if list {
+        for path in &displaced {
+            println!("remove     {}", path.display());
+        }
+    }
+    [created, overwritten, skipped, displaced.len()]
+}
 
-    // now enumerate them
-    for (i, entry) in entries.iter().enumerate() {
-        let idx = i + 1;
+/// Diff capsule `a` against capsule `b`, or against the live directories.
+fn diff_capsules(
+    a: &str,
+    b: Option<&str>,
+    content: bool,
+    only: &[SourceKind],
+    output: OutputFormat,
+) -> Result<()> {
+    let home = home()?;
+    let store = CapsuleStore::in_home(&home);
+    let a = store.find(a)?;
+    let b = b.map(|b| store.find(b)).transpose()?;
 
-        // pull the OsString into a local so .to_string_lossy()
-        // doesn't borrow from a temporary
-        let os_name = entry.file_name();
-        let name = os_name.to_string_lossy();
+    let appname = a.appname()?;
+    let index = store.index()?;
+    let new_files = match &b {
+        Some(b) => index.files(b)?,
+        None => Archiver::for_capsule(&home, &a, &Config::load(&home)?)?.scan()?,
+    };
+    let mut old_files = index.files(&a)?;
+    let mut new_files = new_files;
+    if !only.is_empty() {
+        let wanted = |path: &str| {
+            SourceKind::of_archive_path(path).is_some_and(|(kind, _)| only.contains(&kind))
+        };
+        old_files.retain(|f| wanted(&f.path));
+        new_files.retain(|f| wanted(&f.path));
+    }
+    let changes = diff_files(&old_files, &new_files);
+    let unified = |change: &FileChange| -> Result<Option<String>> {
+        let old = a.read_file(&change.path)?;
+        let old_label = format!("{}/{}", a.name(), change.path);
+        let (new, new_label) = match &b {
+            Some(b) => (
+                b.read_file(&change.path)?,
+                format!("{}/{}", b.name(), change.path),
+            ),
+            None => {
+                let live = SourceKind::live_path(&home, &appname, &change.path);
+                let label = match live.strip_prefix(&home) {
+                    Ok(rest) => format!("~/{}", rest.to_string_lossy().replace('\\', "/")),
+                    Err(_) => live.display().to_string(),
+                };
+                (fs::read(&live)?, label)
+            }
+        };
+        Ok(unified_diff(&old_label, &new_label, &old, &new))
+    };
 
-        println!("[\x1b[33m\x1b[0m ]:\x1b[32m({})\x1b[0m: \"{}\"", idx, name);
+    if output == OutputFormat::Json {
+        let mut values = Vec::new();
+        for change in &changes {
+            let mut value = serde_json::to_value(change)?;
+            if content && change.change == Change::Modified {
+                value["diff"] = serde_json::to_value(unified(change)?)?;
+            }
+            values.push(value);
+        }
+        return print_json(&values);
     }
 
+    let (mut added, mut removed, mut modified) = (0, 0, 0);
+    for change in &changes {
+        let mark = match change.change {
+            Change::Added => {
+                added += 1;
+                style("A").green()
+            }
+            Change::Removed => {
+                removed += 1;
+                style("D").red()
+            }
+            Change::Modified => {
+                modified += 1;
+                style("M").yellow()
+            }
+        };
+        println!("{}  {}", mark, change.path);
+
+        if content && change.change == Change::Modified {
+            match unified(change)? {
+                Some(diff) => print!("{}", diff),
+                None => println!("   (binary files differ)"),
+            }
+        }
+    }
+
+    println!(
+        "{} added, {} removed, {} modified",
+        added, removed, modified
+    );
     Ok(())
 }
-fn restore_capsule() -> io::Result<()> {
-    let home = home_dir().expect("HOME not set");
-    let capsule_dir = home.join(".nvim_capsules");
-    if !capsule_dir.exists() {
-        println!("No capsules found.");
+
+fn file_history(query: &str, output: OutputFormat) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let capsules = store.list()?;
+    let index = store.index()?;
+    index.retain(&capsules)?;
+    let versions = nvim_time_machine::file_history(&index, &capsules, query)?;
+
+    if output == OutputFormat::Json {
+        let values: Vec<_> = versions
+            .iter()
+            .map(|v| {
+                json!({
+                    "capsule": v.capsule.name(),
+                    "created": v.capsule.created(),
+                    "path": v.entry.path,
+                    "size": v.entry.size,
+                    "sha256": v.entry.sha256,
+                })
+            })
+            .collect();
+        return print_json(&values);
+    }
+
+    if versions.is_empty() {
+        println!("\"{}\" is not in any capsule.", query);
         return Ok(());
     }
 
-    let mut entries: Vec<_> = fs::read_dir(&capsule_dir)?
-        .filter_map(Result::ok)
-        .filter(|e| e.path().extension() == Some(OsStr::new("zip")))
-        .collect();
-    entries.sort_by_key(|e| e.metadata().and_then(|m| m.modified()).ok());
+    println!("{}", versions[0].entry.path);
+    let mut previous: Option<&str> = None;
+    for version in &versions {
+        // mark the capsules where the file actually changed
+        let changed = previous != Some(version.entry.sha256.as_str());
+        previous = Some(&version.entry.sha256);
+        let date = version
+            .capsule
+            .created()
+            .map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        println!(
+            "{} {}  {}  {:>10}  {}",
+            if changed { "*" } else { " " },
+            version.capsule.name(),
+            date,
+            version.entry.size,
+            &version.entry.sha256[..12]
+        );
+    }
+    Ok(())
+}
 
-    let names: Vec<String> = entries
-        .iter()
-        .map(|e| e.file_name().to_string_lossy().into_owned())
-        .collect();
-
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select a capsule to restore")
-        .items(&names)
-        .default(0)
-        .interact()
-        .unwrap();
-    let selected_path = entries[selection].path();
-
-    let backup = Confirm::new()
-        .with_prompt("Backup existing Neovim directories (rename with timestamp)?")
-        .default(true)
-        .interact()
-        .unwrap();
-
-    let ts = Local::now().format("%Y%m%d%H%M%S");
-    let targets = [
-        home.join(".local/share/nvim"),
-        home.join(".config/nvim"),
-        home.join(".cache/nvim"),
-    ];
-    for dir in &targets {
-        if dir.exists() {
-            if backup {
-                let backup_path = dir.with_file_name(format!("nvim{}", ts));
-                fs::rename(dir, backup_path)?;
-            } else {
-                fs::remove_dir_all(dir)?;
+fn clone_capsules(
+    remote: &str,
+    names: &[String],
+    only: &[SourceKind],
+    limit_rate: Option<ByteSize>,
+) -> Result<()> {
+    let home = home()?;
+    let store = CapsuleStore::in_home(&home);
+    let mut remote = SshRemote::resolve(&Config::load(&home)?, remote);
+    if let Some(ByteSize(rate)) = limit_rate {
+        remote = remote.limit_rate(rate);
+    }
+    let capsules = if names.is_empty() {
+        store.list()?
+    } else {
+        names
+            .iter()
+            .map(|n| store.find(n))
+            .collect::<Result<Vec<_>>>()?
+    };
+    if capsules.is_empty() {
+        return Err(Error::NoCapsules);
+    }
+    let (mut total, mut sent) = (0, 0);
+    for capsule in &capsules {
+        let (upload, name) = if only.is_empty() {
+            (remote.upload(capsule)?, capsule.name().to_string())
+        } else {
+            let extracted = capsule.extract_sections(only)?;
+            let name = extracted.capsule().name().to_string();
+            (remote.upload(extracted.capsule())?, name)
+        };
+        total += upload.size;
+        sent += upload.sent;
+        let delta = if upload.sent < upload.size {
+            format!(", sent {}", HumanBytes(upload.sent))
+        } else {
+            String::new()
+        };
+        println!("Copied \"{}\" ({}{})", name, HumanBytes(upload.size), delta);
+    }
+    println!(
+        "Cloned {} capsules ({}, sent {}) to {}; checksums match.",
+        capsules.len(),
+        HumanBytes(total),
+        HumanBytes(sent),
+        remote.host()
+    );
+    Ok(())
+}
+
+fn restore_file(name: &str, query: &str, to: Option<&Path>) -> Result<()> {
+    let home = home()?;
+    let capsule = CapsuleStore::in_home(&home).find(name)?;
+    // back into the directories of the capsule's own appname or profile
+    let dest = Restorer::for_capsule(&home, &capsule)?.restore_file(&capsule, query, to)?;
+    println!(
+        "Restored \"{}\" from \"{}\"",
+        dest.display(),
+        capsule.name()
+    );
+    Ok(())
+}
+
+fn grep_capsules(
+    pattern: &str,
+    capsule: Option<&str>,
+    ignore_case: bool,
+    fixed_strings: bool,
+    name: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let capsules = match capsule {
+        Some(name) => vec![store.find(name)?],
+        None => store.list()?,
+    };
+
+    let pattern = if fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| Error::InvalidInput(e.to_string()))?;
+
+    if name {
+        // answered from the index, without opening any archive
+        let index = store.index()?;
+        let mut matches = Vec::new();
+        for capsule in &capsules {
+            for file in index.files(capsule)? {
+                if regex.is_match(&file.path) {
+                    matches.push((capsule, file));
+                }
+            }
+        }
+        if output == OutputFormat::Json {
+            let values: Vec<_> = matches
+                .iter()
+                .map(|(capsule, file)| {
+                    json!({
+                        "capsule": capsule.name(),
+                        "path": file.path,
+                        "size": file.size,
+                        "sha256": file.sha256,
+                    })
+                })
+                .collect();
+            return print_json(&values);
+        }
+        for (capsule, file) in &matches {
+            println!(
+                "{}:{}",
+                style(capsule.name()).magenta(),
+                style(&file.path).cyan()
+            );
+        }
+        return Ok(());
+    }
+
+    if output == OutputFormat::Json {
+        let mut values = Vec::new();
+        for capsule in &capsules {
+            for m in grep_capsule(capsule, &regex)? {
+                let mut value = serde_json::to_value(m)?;
+                value["capsule"] = capsule.name().into();
+                values.push(value);
             }
         }
+        return print_json(&values);
+    }
+
+    for capsule in &capsules {
+        for m in grep_capsule(capsule, &regex)? {
+            println!(
+                "{}:{}:{}:{}",
+                style(capsule.name()).magenta(),
+                style(&m.path).cyan(),
+                style(m.line_number).green(),
+                m.line
+            );
+        }
+    }
+    Ok(())
+}
+
+fn inspect_capsule(
+    name: &str,
+    subpath: Option<&str>,
+    depth: Option<usize>,
+    output: OutputFormat,
+) -> Result<()> {
+    let capsule = CapsuleStore::in_home(&home()?).find(name)?;
+    let tree = build_tree(&capsule.files()?, subpath).ok_or_else(|| Error::NotInCapsule {
+        capsule: capsule.name().to_string(),
+        path: subpath.unwrap_or_default().to_string(),
+    })?;
+
+    if output == OutputFormat::Json {
+        let mut tree = tree;
+        if let Some(depth) = depth {
+            prune_tree(&mut tree, depth);
+        }
+        return print_json(&tree);
     }
 
-    let file = fs::File::open(&selected_path)?;
-    let mut archive = ZipArchive::new(file)?;
-    let total = archive.len() as u64;
-    let pb = ProgressBar::new(total);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
-            )
-            .expect("Invalid progress template"),
+    if subpath.is_none() {
+        let environment = capsule.manifest()?.and_then(|m| m.environment);
+        print_environment(environment.as_ref(), "");
+    }
+    println!(
+        "{} ({}, {} files)",
+        style(&tree.name).blue(),
+        HumanBytes(tree.size),
+        tree.files
     );
+    print_tree(&tree, "", depth.unwrap_or(usize::MAX));
+    Ok(())
+}
 
-    for i in 0..archive.len() {
-        let mut zip_file = archive.by_index(i)?;
-        let outpath = home.join(zip_file.mangled_name());
-        if zip_file.is_dir() {
-            fs::create_dir_all(&outpath)?;
+/// Drop everything more than `depth` levels below `node`.
+fn prune_tree(node: &mut TreeNode, depth: usize) {
+    if depth == 0 {
+        node.children.clear();
+    }
+    for child in node.children.values_mut() {
+        prune_tree(child, depth - 1);
+    }
+}
+
+fn print_tree(node: &TreeNode, indent: &str, depth: usize) {
+    if depth == 0 {
+        return;
+    }
+    let count = node.children.len();
+    for (i, child) in node.children.values().enumerate() {
+        let last = i + 1 == count;
+        let branch = if last { "└── " } else { "├── " };
+        if child.is_dir() {
+            println!(
+                "{}{}{} ({}, {} files)",
+                indent,
+                branch,
+                style(format!("{}/", child.name)).blue(),
+                HumanBytes(child.size),
+                child.files
+            );
+            let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+            print_tree(child, &indent, depth - 1);
         } else {
-            if let Some(parent) = outpath.parent() {
-                fs::create_dir_all(parent)?;
+            println!(
+                "{}{}{} ({})",
+                indent,
+                branch,
+                child.name,
+                HumanBytes(child.size)
+            );
+        }
+    }
+}
+
+/// Pretty-print `value` as JSON on stdout.
+fn print_json(value: &impl Serialize) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let written = serde_json::to_writer_pretty(&mut stdout, value)
+        .map_err(io::Error::from)
+        .and_then(|_| writeln!(stdout));
+    match written {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+fn show_file(name: &str, query: &str) -> Result<()> {
+    let capsule = CapsuleStore::in_home(&home()?).find(name)?;
+    let files = capsule.files()?;
+    let entry = resolve_path(&files, query).ok_or_else(|| Error::NotInCapsule {
+        capsule: capsule.name().to_string(),
+        path: query.to_string(),
+    })?;
+
+    match capsule.copy_file(&entry.path, &mut io::stdout().lock()) {
+        // the reader (e.g. a pager) went away early
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map(|_| ()),
+    }
+}
+
+fn export_capsule(name: &str, format: ExportFormat, output: Option<PathBuf>) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let capsule = store.find(name)?;
+
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(capsule.name()).with_extension(format.extension());
+        PathBuf::from(stem.file_name().unwrap_or_default())
+    });
+    match format {
+        ExportFormat::Tar => {
+            let count = export_tar(&capsule, &output)?;
+            println!("Exported {} files to \"{}\"", count, output.display());
+        }
+        ExportFormat::Zip => {
+            capsule.write_archive(&mut fs::File::create(&output)?)?;
+            println!(
+                "Exported \"{}\" to \"{}\"",
+                capsule.name(),
+                output.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn export_dotfiles(name: &str, dir: &Path, layout: DotfilesLayout) -> Result<()> {
+    let capsule = CapsuleStore::in_home(&home()?).find(name)?;
+    let (root, count) = nvim_time_machine::export_dotfiles(&capsule, dir, layout)?;
+    println!("Exported {} files to \"{}\"", count, root.display());
+    match layout {
+        DotfilesLayout::Chezmoi => {
+            println!("Apply them with: chezmoi --source {} apply", dir.display())
+        }
+        DotfilesLayout::Stow => println!(
+            "Link them into HOME with: stow -d {} {}",
+            dir.display(),
+            root.parent()
+                .and_then(Path::parent)
+                .and_then(Path::file_name)
+                .unwrap_or_default()
+                .to_string_lossy()
+        ),
+    }
+    Ok(())
+}
+
+fn import_capsule(path: Option<&Path>, repo: Option<&str>) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+    let imported = match (path, repo) {
+        (_, Some(repo)) => import_git(&store, repo)?,
+        (Some(path), None) => import_archive(&store, path)?,
+        (None, None) => unreachable!("clap requires a path or --from-git"),
+    };
+
+    println!(
+        "Imported {} files as \"{}\"",
+        imported.manifest.file_count,
+        imported.capsule.name()
+    );
+    if imported.skipped > 0 {
+        println!(
+            "Skipped {} files outside the Neovim directories",
+            imported.skipped
+        );
+    }
+    Ok(())
+}
+
+fn mount(capsule: Option<&str>, mountpoint: Option<&Path>, all: Option<&Path>) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let (mountpoint, count) = match (capsule, mountpoint, all) {
+        (_, _, Some(dir)) => (dir, mount_store(&store, dir)?.len()),
+        (Some(name), Some(dir), None) => {
+            mount_capsule(&store.find(name)?, dir)?;
+            (dir, 1)
+        }
+        // clap requires either
+        _ => unreachable!(),
+    };
+    println!(
+        "Mounted {} capsule(s) read-only at {}; unmount with: nvimTimeMachine unmount {}",
+        count,
+        mountpoint.display(),
+        mountpoint.display()
+    );
+    Ok(())
+}
+
+fn try_capsule(name: &str, appname: &str, launch: bool, mode: ProgressMode) -> Result<()> {
+    check_appname(appname)?;
+    let home = home()?;
+    let capsule = CapsuleStore::in_home(&home).find(name)?;
+    let restorer = Restorer::preview(&home, &capsule, appname)?;
+    // the preview profile is ours, so replace whatever was tried before
+    restorer.restore(
+        &capsule,
+        ExistingDirs::Delete,
+        &*progress(mode, "restore", "🕒 Preview ready!"),
+    )?;
+
+    if launch {
+        process::Command::new("nvim")
+            .env("NVIM_APPNAME", appname)
+            .status()?;
+    } else {
+        println!(
+            "Try it with: NVIM_APPNAME={} nvim (config in \"{}\")",
+            appname,
+            SourceKind::Config.dir(&home, appname).display()
+        );
+    }
+    Ok(())
+}
+
+fn delete_capsules(
+    names: &[String],
+    indices: &[usize],
+    older_than: Option<Age>,
+    force: bool,
+    prompter: &Prompter,
+) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+    let mut capsules = store.list()?;
+    if capsules.is_empty() {
+        return Err(Error::NoCapsules);
+    }
+
+    let mut selected: Vec<usize> = if names.is_empty() && indices.is_empty() && older_than.is_none()
+    {
+        let rows = capsules
+            .iter()
+            .map(capsule_row)
+            .collect::<Result<Vec<_>>>()?;
+        prompter.choose_many(
+            "Capsules to delete (space to select)",
+            &rows,
+            "no capsules selected (pass --name, --index or --older-than)",
+        )?
+    } else {
+        let mut selected = Vec::new();
+        for name in names {
+            let capsule = store.find(name)?;
+            selected.extend(capsules.iter().position(|c| c.path() == capsule.path()));
+        }
+        for &index in indices {
+            store.nth(index)?;
+            selected.push(index - 1);
+        }
+        if let Some(age) = older_than {
+            let now = Local::now();
+            for (i, capsule) in capsules.iter().enumerate() {
+                if !capsule.created().is_some_and(|t| age.exceeded_by(t, now)) {
+                    continue;
+                }
+                if capsule.meta()?.pinned {
+                    println!("Keeping pinned \"{}\"", capsule.name());
+                } else {
+                    selected.push(i);
+                }
             }
-            let mut outfile = fs::File::create(&outpath)?;
-            io::copy(&mut zip_file, &mut outfile)?;
         }
-        pb.inc(1);
+        selected
+    };
+    selected.sort_unstable();
+    selected.dedup();
+    if selected.is_empty() {
+        println!("Nothing to delete.");
+        return Ok(());
+    }
+
+    for &i in &selected {
+        println!("  {}", capsules[i].name());
     }
-    pb.finish_with_message("🕒 Restoration complete!");
+    let prompt = match selected.len() {
+        1 => "Delete this capsule?".to_string(),
+        n => format!("Delete these {} capsules?", n),
+    };
+    if !force && !prompter.confirm(&prompt, false)? {
+        println!("Nothing deleted.");
+        return Ok(());
+    }
+    // remove back to front so the indices stay valid
+    for i in selected.into_iter().rev() {
+        let capsule = capsules.remove(i);
+        store.delete(&capsule)?;
+        println!("Deleted \"{}\"", capsule.name());
+    }
+    Ok(())
+}
+
+fn rename_capsule(old: &str, new: &str) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+    let capsule = store.find(old)?;
+    let renamed = store.rename(&capsule, new)?;
+    println!("\"{}\" -> \"{}\"", capsule.name(), renamed.name());
+    Ok(())
+}
 
+fn merge(a: &str, b: &str, name: &str) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+    let a = store.find(a)?;
+    let b = store.find(b)?;
+    let dest = store.named_path(name)?;
+    let (manifest, conflicts) = merge_capsules(&a, &b, &dest)?;
+    let merged = Capsule::from_path(&dest);
+    store.index_capsule(&merged);
+    println!(
+        "Merged \"{}\" and \"{}\" into \"{}\" ({} files)",
+        a.name(),
+        b.name(),
+        merged.name(),
+        manifest.file_count
+    );
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "{} {} in both with different contents; kept the newer version:",
+        conflicts.len(),
+        if conflicts.len() == 1 {
+            "file was"
+        } else {
+            "files were"
+        }
+    );
+    for conflict in &conflicts {
+        println!(
+            "  {}  {}",
+            conflict.path,
+            style(format!("(from {})", conflict.kept)).dim()
+        );
+    }
+    Ok(())
+}
+
+fn tag_command(command: TagCommand) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let add = matches!(command, TagCommand::Add { .. });
+    match command {
+        TagCommand::Add { capsule, tags } | TagCommand::Remove { capsule, tags } => {
+            let _lock = store.lock()?;
+            let capsule = store.find(&capsule)?;
+            let mut meta = capsule.meta()?;
+            for tag in &tags {
+                if add {
+                    meta.add_tag(tag)?;
+                } else {
+                    meta.tags.remove(tag);
+                }
+            }
+            capsule.set_meta(&meta)?;
+            print_tags(&capsule, &meta);
+        }
+        TagCommand::List {
+            capsule: Some(capsule),
+        } => {
+            let capsule = store.find(&capsule)?;
+            print_tags(&capsule, &capsule.meta()?);
+        }
+        TagCommand::List { capsule: None } => {
+            let mut tags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for capsule in store.list()? {
+                for tag in capsule.meta()?.tags {
+                    tags.entry(tag)
+                        .or_default()
+                        .push(capsule.name().to_string());
+                }
+            }
+            for (tag, capsules) in tags {
+                println!("{}: {}", style(tag).cyan(), capsules.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn show_status(output: OutputFormat) -> Result<()> {
+    let home = home()?;
+    let store = CapsuleStore::in_home(&home);
+    let capsules = store.list()?;
+    let total_size = capsules.iter().map(|c| c.size().unwrap_or(0)).sum::<u64>();
+    let config = Config::load(&home)?;
+    let naming = config.naming(&capsule_prefix(DEFAULT_APPNAME), DEFAULT_APPNAME);
+    let latest = capsules.iter().rfind(|c| naming.matches(c.name()));
+    // a damaged capsule is reported, not a reason to fail
+    let (changes, damaged) = match latest.map(|c| (c, c.files())) {
+        Some((capsule, Ok(files))) => {
+            let live = Archiver::for_capsule(&home, capsule, &config)?.scan()?;
+            (Some(diff_files(&files, &live)), None)
+        }
+        Some((_, Err(e))) => (None, Some(e.to_string())),
+        None => (None, None),
+    };
+    let count = |kind| {
+        changes
+            .iter()
+            .flatten()
+            .filter(|c| c.change == kind)
+            .count()
+    };
+    let schedule = find_schedule(&home);
+
+    if output == OutputFormat::Json {
+        return print_json(&json!({
+            "last_capsule": latest.map(|c| json!({ "name": c.name(), "created": c.created() })),
+            "changes": changes.as_ref().map(|_| json!({
+                "added": count(Change::Added),
+                "modified": count(Change::Modified),
+                "removed": count(Change::Removed),
+            })),
+            "damaged": damaged,
+            "schedule": schedule,
+            "capsules": capsules.len(),
+            "total_size": total_size,
+            "store": store.dir(),
+        }));
+    }
+
+    match latest {
+        Some(capsule) => {
+            let created = capsule.created();
+            println!(
+                "Last capsule:  {} ({})",
+                capsule.name(),
+                created.map_or("unknown date".to_string(), |t| format!(
+                    "{}, {}",
+                    ago(Local::now() - t),
+                    t.format("%Y-%m-%d %H:%M")
+                ))
+            );
+            let (added, modified, removed) = (
+                count(Change::Added),
+                count(Change::Modified),
+                count(Change::Removed),
+            );
+            if let Some(reason) = &damaged {
+                println!(
+                    "Changes:       {} ({}; see `verify {}`)",
+                    style("unknown, the capsule is damaged").red(),
+                    reason,
+                    capsule.name()
+                );
+            } else if added + modified + removed == 0 {
+                println!("Changes:       {}", style("none since then").green());
+            } else {
+                println!(
+                    "Changes:       {} added, {} modified, {} removed since then (see `diff --live {}`)",
+                    style(added).green(),
+                    style(modified).yellow(),
+                    style(removed).red(),
+                    capsule.name()
+                );
+            }
+        }
+        None => println!(
+            "Last capsule:  {}",
+            style("none yet (run `create`)").yellow()
+        ),
+    }
+    match &schedule {
+        Some(schedule) => println!("Schedule:      {}", schedule),
+        None => println!("Schedule:      {}", style("none found").yellow()),
+    }
+    println!(
+        "Capsules:      {}, {} in {}",
+        capsules.len(),
+        HumanBytes(total_size),
+        store.dir().display()
+    );
+    Ok(())
+}
+
+/// Rough age for humans, like `3 hours ago`.
+fn ago(delta: TimeDelta) -> String {
+    let (amount, unit) = match delta.num_seconds() {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s => (s / 86400, "day"),
+    };
+    format!(
+        "{} {}{} ago",
+        amount,
+        unit,
+        if amount == 1 { "" } else { "s" }
+    )
+}
+
+fn verify_capsules(name: Option<&str>, output: OutputFormat, mode: ProgressMode) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let capsules = match name {
+        Some(name) => vec![store.find(name)?],
+        None => store.list()?,
+    };
+    if capsules.is_empty() {
+        return Err(Error::NoCapsules);
+    }
+
+    let mut results = Vec::new();
+    for capsule in &capsules {
+        let mut quarantined = None;
+        // whether another process worked on the store, so it wasn't moved
+        let mut locked_out = false;
+        let damaged = match capsule.verify(&*progress(mode, "verify", "")) {
+            Ok(damaged) => damaged,
+            // unreadable as a whole, so out of the way it goes
+            Err(Error::BadArchive { source, .. }) => {
+                let problem = source.to_string();
+                match store.try_lock()? {
+                    Some(lock) => quarantined = Some(store.quarantine(&lock, capsule, &problem)?),
+                    None => locked_out = true,
+                }
+                vec![DamagedEntry {
+                    path: String::new(),
+                    problem,
+                }]
+            }
+            Err(e) => return Err(e),
+        };
+        results.push((capsule, damaged, quarantined, locked_out));
+    }
+    let bad = results.iter().filter(|(_, d, _, _)| !d.is_empty()).count();
+
+    if output == OutputFormat::Json {
+        let values: Vec<_> = results
+            .iter()
+            .map(|(c, damaged, quarantined, _)| {
+                json!({
+                    "name": c.name(),
+                    "ok": damaged.is_empty(),
+                    "damaged": damaged,
+                    "quarantined": quarantined.as_ref().map(|q| &q.path),
+                })
+            })
+            .collect();
+        print_json(&values)?;
+    } else {
+        for (capsule, damaged, quarantined, locked_out) in &results {
+            if damaged.is_empty() {
+                println!("{}  {}", style("ok     ").green(), capsule.name());
+                continue;
+            }
+            println!("{}  {}", style("DAMAGED").red(), capsule.name());
+            for entry in damaged {
+                if entry.path.is_empty() {
+                    println!("         {}", entry.problem);
+                } else {
+                    println!("         {}: {}", entry.path, entry.problem);
+                }
+            }
+            if let Some(quarantined) = quarantined {
+                println!("         moved to {}", quarantined.path.display());
+            } else if *locked_out {
+                println!("         left in place while another nvimTimeMachine works on the store");
+            }
+        }
+    }
+
+    if bad > 0 {
+        return Err(Error::VerificationFailed(format!(
+            "{} of {} capsules are damaged",
+            bad,
+            results.len()
+        )));
+    }
+    Ok(())
+}
+
+fn doctor(output: OutputFormat) -> Result<()> {
+    let home = home()?;
+    let checks = run_checks(&home, &CapsuleStore::in_home(&home));
+    let failed = checks
+        .iter()
+        .filter(|c| c.status == CheckStatus::Fail)
+        .count();
+
+    if output == OutputFormat::Json {
+        print_json(&checks)?;
+    } else {
+        for check in &checks {
+            let status = match check.status {
+                CheckStatus::Ok => style("ok  ").green(),
+                CheckStatus::Warn => style("warn").yellow(),
+                CheckStatus::Fail => style("FAIL").red(),
+            };
+            println!("{}  {:<12} {}", status, check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                println!("      {:<12} {} {}", "", style("fix:").bold(), fix);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::VerificationFailed(format!(
+            "{} of {} checks failed",
+            failed,
+            checks.len()
+        )));
+    }
+    Ok(())
+}
+
+fn show_stats(top: usize, output: OutputFormat) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let capsules = store.list()?;
+    if capsules.is_empty() {
+        return Err(Error::NoCapsules);
+    }
+    let stats = store_stats(&capsules, top)?;
+    if output == OutputFormat::Json {
+        return print_json(&stats);
+    }
+
+    println!(
+        "{} capsules, {} on disk ({} of files)",
+        stats.capsules.len(),
+        style(HumanBytes(stats.total_size)).bold(),
+        HumanBytes(stats.content_size)
+    );
+    println!();
+    for capsule in &stats.capsules {
+        let growth = match capsule.growth {
+            Some(g) if g > 0 => {
+                style(format!("{:>11}", format!("+{}", HumanBytes(g as u64)))).red()
+            }
+            Some(g) if g < 0 => style(format!(
+                "{:>11}",
+                format!("-{}", HumanBytes(g.unsigned_abs()))
+            ))
+            .green(),
+            Some(_) => style(format!("{:>11}", "±0")).dim(),
+            None => style(format!("{:11}", "")),
+        };
+        let created = capsule
+            .created
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        println!(
+            "{:<16}  {:>10}  {:>6} files  {}  {}",
+            created,
+            HumanBytes(capsule.size).to_string(),
+            capsule.file_count,
+            growth,
+            capsule.name
+        );
+    }
+
+    let latest = &stats.capsules[stats.capsules.len() - 1].name;
+    println!();
+    println!("{} in {}:", style("Largest directories").bold(), latest);
+    for dir in &stats.largest_dirs {
+        println!("  {:>10}  {}/", HumanBytes(dir.size).to_string(), dir.path);
+    }
+    println!("{} in {}:", style("Largest files").bold(), latest);
+    for file in &stats.largest_files {
+        println!("  {:>10}  {}", HumanBytes(file.size).to_string(), file.path);
+    }
+
+    println!();
+    println!(
+        "{} of the file contents are unique; storing each file once would save {}.",
+        HumanBytes(stats.unique_content_size),
+        style(HumanBytes(stats.dedup_potential())).bold()
+    );
+    Ok(())
+}
+
+fn prune_capsules(args: PruneArgs, prompter: &Prompter) -> Result<()> {
+    let home = home()?;
+    let store = CapsuleStore::in_home(&home);
+    let lock = store.lock()?;
+    // so every capsule's timeline can be told
+    print_quarantined(&store.quarantine_unreadable(&lock)?);
+    let timelines = args.retention.policy().plan_timelines(
+        store.list()?,
+        &Config::load(&home)?,
+        Local::now(),
+    )?;
+
+    for (name, items) in &timelines {
+        if timelines.len() > 1 {
+            println!("{}:", style(name).bold());
+        }
+        for item in items {
+            if item.keep() {
+                println!(
+                    "{}  {}  {}",
+                    style("keep ").green(),
+                    item.capsule.name(),
+                    style(format!("({})", item.kept_by.join(", "))).dim()
+                );
+            } else {
+                println!("{}  {}", style("prune").red(), item.capsule.name());
+            }
+        }
+    }
+    let plan: Vec<&PruneItem> = timelines.iter().flat_map(|(_, items)| items).collect();
+    let pruned: Vec<&PruneItem> = plan.iter().copied().filter(|i| !i.keep()).collect();
+    let size = HumanBytes(pruned.iter().map(|i| i.size).sum());
+    if pruned.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+    if args.dry_run {
+        println!(
+            "Would prune {} of {} capsules ({}); nothing was deleted.",
+            pruned.len(),
+            plan.len(),
+            size
+        );
+        return Ok(());
+    }
+
+    let prompt = format!("Prune {} capsules ({})?", pruned.len(), size);
+    if !args.force && !prompter.confirm(&prompt, false)? {
+        println!("Nothing deleted.");
+        return Ok(());
+    }
+    for item in &pruned {
+        store.delete(&item.capsule)?;
+    }
+    println!("Pruned {} capsules, freeing {}.", pruned.len(), size);
+    Ok(())
+}
+
+fn pin_capsules(names: &[String], pinned: bool) -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+    for name in names {
+        let capsule = store.find(name)?;
+        let mut meta = capsule.meta()?;
+        meta.pinned = pinned;
+        capsule.set_meta(&meta)?;
+        let verb = if pinned { "Pinned" } else { "Unpinned" };
+        println!("{} \"{}\"", verb, capsule.name());
+    }
+    Ok(())
+}
+
+fn note_command(command: NoteCommand, prompter: &Prompter) -> Result<()> {
+    let NoteCommand::Edit { capsule, message } = command;
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+    let capsule = store.find(&capsule)?;
+    let mut meta = capsule.meta()?;
+    let note = match message {
+        Some(message) => message,
+        None => {
+            let current = meta.note.clone().unwrap_or_default();
+            match prompter.edit(&current, "pass the note with --message when not prompting")? {
+                Some(note) => note,
+                None => {
+                    println!("Note left unchanged.");
+                    return Ok(());
+                }
+            }
+        }
+    };
+    meta.set_note(&note);
+    capsule.set_meta(&meta)?;
+    match &meta.note {
+        Some(note) => println!("\"{}\": {}", capsule.name(), note),
+        None => println!("Removed the note of \"{}\"", capsule.name()),
+    }
+    Ok(())
+}
+
+fn print_tags(capsule: &Capsule, meta: &CapsuleMeta) {
+    let tags: Vec<&str> = meta.tags.iter().map(String::as_str).collect();
+    println!("\"{}\": {}", capsule.name(), style(tags.join(", ")).cyan());
+}
+
+fn migrate_capsules() -> Result<()> {
+    let store = CapsuleStore::in_home(&home()?);
+    let _lock = store.lock()?;
+
+    let mut migrated = 0;
+    for capsule in store.list()? {
+        if let Some(new) = migrate_capsule(&capsule)? {
+            store.index_capsule(&new);
+            println!("\"{}\" -> \"{}\"", capsule.name(), new.name());
+            migrated += 1;
+        }
+    }
+
+    if migrated == 0 {
+        println!("All capsules are up to date.");
+    }
+    Ok(())
+}
+
+fn write_man_pages(out_dir: &Path) -> Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let mut cmd = Cli::command();
+    cmd.build();
+    write_man_page(&cmd, out_dir)
+}
+
+/// Render `cmd` and, recursively, its visible subcommands as `<name>.1` pages.
+fn write_man_page(cmd: &clap::Command, out_dir: &Path) -> Result<()> {
+    let name = cmd.get_display_name().unwrap_or_else(|| cmd.get_name());
+    let mut file = fs::File::create(out_dir.join(format!("{}.1", name)))?;
+    clap_mangen::Man::new(cmd.clone()).render(&mut file)?;
+
+    for sub in cmd
+        .get_subcommands()
+        .filter(|s| !s.is_hide_set() && s.get_name() != "help")
+    {
+        write_man_page(sub, out_dir)?;
+    }
     Ok(())
 }
@@ -0,0 +1,127 @@
+use crate::environment::Environment;
+use crate::error::Result;
+use crate::sources::DEFAULT_APPNAME;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use zip::{read::ZipArchive, result::ZipError};
+
+/// Name of the manifest entry inside a v2 capsule.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Current capsule format. Capsules without a manifest are format 1.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Metadata embedded in every v2 capsule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created: DateTime<Local>,
+    pub tool_version: String,
+    /// Source directories, relative to HOME.
+    pub sources: Vec<String>,
+    /// `NVIM_APPNAME` the directories were taken from.
+    #[serde(default = "default_appname")]
+    pub appname: String,
+    /// Config file profile the sources were taken from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Machine and Neovim the capsule was created with; missing in older capsules.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Environment>,
+    pub file_count: u64,
+    /// Sum of the uncompressed file sizes in bytes.
+    pub total_size: u64,
+    pub files: Vec<ManifestEntry>,
+    /// Whether the archived files carry their real unix permissions and
+    /// modification times; older capsules store 0o755 for everything.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub file_metadata: bool,
+    /// Whether machine-specific files were left out (`create --portable`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub portable: bool,
+    /// Whether only the configuration and plugin lock files were archived
+    /// (`create --plugins-only`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub plugins_only: bool,
+    /// Whether the capsule was made with `create --reproducible`; `created` is
+    /// then the newest file's time (or `SOURCE_DATE_EPOCH`), not the clock's.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reproducible: bool,
+    /// Symbolic links archived as links rather than the files they point to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symlinks: Vec<SymlinkEntry>,
+}
+
+fn default_appname() -> String {
+    DEFAULT_APPNAME.to_string()
+}
+
+/// A single archived file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path inside the archive, relative to HOME, `/`-separated.
+    pub path: String,
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+/// A symbolic link stored as such.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymlinkEntry {
+    /// Path inside the archive of the link itself.
+    pub path: String,
+    /// Where the link points, as read from the file system.
+    pub target: String,
+}
+
+impl Manifest {
+    /// An empty manifest for a capsule created now.
+    pub fn new(sources: Vec<String>) -> Self {
+        Manifest {
+            format_version: FORMAT_VERSION,
+            created: Local::now(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            sources,
+            appname: DEFAULT_APPNAME.to_string(),
+            profile: None,
+            environment: None,
+            file_count: 0,
+            total_size: 0,
+            files: Vec::new(),
+            file_metadata: false,
+            portable: false,
+            plugins_only: false,
+            reproducible: false,
+            symlinks: Vec::new(),
+        }
+    }
+
+    /// Record an archived file.
+    pub fn push(&mut self, entry: ManifestEntry) {
+        self.file_count += 1;
+        self.total_size += entry.size;
+        self.files.push(entry);
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    /// Read the manifest from an open archive; `None` for legacy capsules.
+    pub fn read_from<R: io::Read + io::Seek>(archive: &mut ZipArchive<R>) -> Result<Option<Self>> {
+        let mut entry = match archive.by_name(MANIFEST_NAME) {
+            Ok(entry) => entry,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+        Self::from_json(&buf).map(Some)
+    }
+}
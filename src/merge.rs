@@ -0,0 +1,162 @@
+use crate::capsule::{entry_mtime, Capsule};
+use crate::error::{Error, Result};
+use crate::journal::part_path;
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::writer::CapsuleWriter;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+use tracing::info;
+
+/// A path both capsules of a [`merge_capsules`] have, with different contents.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeConflict {
+    pub path: String,
+    /// Name of the capsule whose version went into the merged one.
+    pub kept: String,
+}
+
+/// What [`merge_capsules`] needs to know about an archive entry to pick a side.
+struct Entry {
+    /// 0 for the first capsule, 1 for the second.
+    side: usize,
+    index: usize,
+    is_dir: bool,
+    size: u64,
+    crc32: u32,
+    mtime: Option<SystemTime>,
+}
+
+/// Combine the capsules `a` and `b` into a new capsule at `dest`, such as
+/// the capsules of one config made on two machines.
+///
+/// Every file, directory and link of either capsule is kept. Where both have
+/// a path with different contents, the version modified last wins, or the
+/// one from the capsule created last if that can't be told; each of those is
+/// reported as a [`MergeConflict`]. Both capsules must have a manifest and
+/// be of the same `NVIM_APPNAME` and profile.
+pub fn merge_capsules(
+    a: &Capsule,
+    b: &Capsule,
+    dest: &Path,
+) -> Result<(Manifest, Vec<MergeConflict>)> {
+    if dest.exists() {
+        return Err(Error::AlreadyExists(dest.to_path_buf()));
+    }
+    let capsules = [a, b];
+    let mut archives = [a.archive()?, b.archive()?];
+    let mut manifests = Vec::new();
+    for (capsule, archive) in capsules.iter().zip(&mut archives) {
+        manifests.push(Manifest::read_from(archive)?.ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "{} has no manifest; run `nvimTimeMachine migrate` first",
+                capsule.name()
+            ))
+        })?);
+    }
+    let (first, second) = (&manifests[0], &manifests[1]);
+    if first.appname != second.appname || first.profile != second.profile {
+        return Err(Error::InvalidInput(format!(
+            "{} and {} are capsules of different directories",
+            a.name(),
+            b.name()
+        )));
+    }
+    // the capsule created last breaks ties
+    let newer = usize::from(b.created() >= a.created());
+
+    let mut chosen: BTreeMap<String, Entry> = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for (side, archive) in archives.iter_mut().enumerate() {
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index)?;
+            let path = entry.name().trim_end_matches('/').to_string();
+            if path == MANIFEST_NAME {
+                continue;
+            }
+            let entry = Entry {
+                side,
+                index,
+                is_dir: entry.is_dir(),
+                size: entry.size(),
+                crc32: entry.crc32(),
+                mtime: entry_mtime(&entry),
+            };
+            let Some(other) = chosen.get(&path) else {
+                chosen.insert(path, entry);
+                continue;
+            };
+            if (other.is_dir, other.size, other.crc32) == (entry.is_dir, entry.size, entry.crc32) {
+                continue;
+            }
+            let wins = match (entry.mtime, other.mtime) {
+                (Some(mine), Some(theirs)) if mine != theirs => mine > theirs,
+                _ => side == newer,
+            };
+            conflicts.push(MergeConflict {
+                path: path.clone(),
+                kept: capsules[if wins { side } else { other.side }]
+                    .name()
+                    .to_string(),
+            });
+            if wins {
+                chosen.insert(path, entry);
+            }
+        }
+    }
+
+    let mut sources = first.sources.clone();
+    for source in &second.sources {
+        if !sources.contains(source) {
+            sources.push(source.clone());
+        }
+    }
+    let part = part_path(dest);
+    let mut writer = CapsuleWriter::create(&part, sources)?;
+    let latest = &manifests[newer];
+    let manifest = writer.manifest_mut();
+    manifest.appname = latest.appname.clone();
+    manifest.profile = latest.profile.clone();
+    manifest.environment = latest.environment.clone();
+    manifest.file_metadata = first.file_metadata && second.file_metadata;
+    // only left out if left out of both
+    manifest.portable = first.portable && second.portable;
+    manifest.plugins_only = first.plugins_only && second.plugins_only;
+
+    for (path, chosen) in &chosen {
+        let mut entry = archives[chosen.side].by_index(chosen.index)?;
+        let mode = entry.unix_mode().map(|m| m & 0o7777);
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        if chosen.is_dir {
+            match (mode, chosen.mtime) {
+                (Some(mode), Some(mtime)) if manifests[chosen.side].file_metadata => {
+                    writer.add_dir_with(path, mode, mtime)?
+                }
+                _ => writer.add_dir(path, mode.unwrap_or(0o755))?,
+            }
+        } else if entry.is_symlink() {
+            writer.add_symlink(path, &String::from_utf8_lossy(&contents))?;
+        } else {
+            match (mode, chosen.mtime) {
+                (Some(mode), Some(mtime)) if manifests[chosen.side].file_metadata => {
+                    writer.add_file_with(path, &contents, mode, mtime)?
+                }
+                _ => writer.add_file(path, &contents)?,
+            }
+        }
+    }
+    let manifest = writer.finish()?;
+    fs::rename(&part, dest)?;
+    info!(
+        a = a.name(),
+        b = b.name(),
+        dest = %dest.display(),
+        conflicts = conflicts.len(),
+        "merged capsules"
+    );
+    Ok((manifest, conflicts))
+}
@@ -0,0 +1,95 @@
+use crate::capsule::Capsule;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension of the metadata file kept next to each capsule archive, e.g.
+/// `pre-lazy.meta.json` for `pre-lazy.zip`.
+pub const META_EXTENSION: &str = "meta.json";
+
+/// User-editable data about a capsule, stored beside it rather than in the
+/// archive so it can change without rewriting the zip.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapsuleMeta {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Free-form description of why the capsule exists.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Protected from age-based deletion and pruning.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+}
+
+impl CapsuleMeta {
+    /// Add `tag`, returning whether it was new.
+    pub fn add_tag(&mut self, tag: &str) -> Result<bool> {
+        if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+            return Err(Error::InvalidInput(format!(
+                "invalid tag \"{}\" (no spaces or commas)",
+                tag
+            )));
+        }
+        Ok(self.tags.insert(tag.to_string()))
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// Replace the note; blank text removes it.
+    pub fn set_note(&mut self, note: &str) {
+        let note = note.trim();
+        self.note = (!note.is_empty()).then(|| note.to_string());
+    }
+
+    /// First line of the note, for one-line listings.
+    pub fn summary(&self) -> Option<&str> {
+        self.note.as_deref().and_then(|n| n.lines().next())
+    }
+}
+
+impl Capsule {
+    /// Where this capsule's [`CapsuleMeta`] is kept.
+    pub fn meta_path(&self) -> PathBuf {
+        meta_path(self.path())
+    }
+
+    /// The capsule's metadata; empty if none was ever saved.
+    pub fn meta(&self) -> Result<CapsuleMeta> {
+        match fs::read(self.meta_path()) {
+            Ok(buf) => Ok(serde_json::from_slice(&buf)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CapsuleMeta::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save `meta`, removing the file again once it is empty.
+    pub fn set_meta(&self, meta: &CapsuleMeta) -> Result<()> {
+        let path = self.meta_path();
+        if *meta == CapsuleMeta::default() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        fs::write(&path, serde_json::to_vec_pretty(meta)?)?;
+        Ok(())
+    }
+}
+
+/// Metadata path for the capsule archive at `path`.
+pub(crate) fn meta_path(path: &Path) -> PathBuf {
+    path.with_extension(META_EXTENSION)
+}
+
+/// Carry the metadata of a capsule being renamed over to `new_path`.
+pub(crate) fn move_meta(capsule: &Capsule, new_path: &Path) -> Result<()> {
+    match fs::rename(capsule.meta_path(), meta_path(new_path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
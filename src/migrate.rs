@@ -0,0 +1,93 @@
+use crate::capsule::{Capsule, CAPSULE_EXTENSION};
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+use crate::metadata::move_meta;
+use crate::sources::SourceKind;
+use crate::store::CAPSULE_PREFIX;
+use chrono::{DateTime, Local};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use tracing::info;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// Upgrade a legacy v1 capsule in place to the current format.
+///
+/// A manifest is generated from the archive contents and appended, then the
+/// file is renamed to the `nvim_capsule_<timestamp>.zip` scheme. Returns the
+/// migrated capsule, or `None` if it already had a manifest.
+pub fn migrate_capsule(capsule: &Capsule) -> Result<Option<Capsule>> {
+    let mut archive = capsule.archive()?;
+    if Manifest::read_from(&mut archive)?.is_some() {
+        return Ok(None);
+    }
+
+    let created = capsule
+        .timestamp_from_name()
+        .or_else(|| capsule.modified().map(DateTime::<Local>::from))
+        .unwrap_or_else(Local::now);
+
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+        files.push(ManifestEntry {
+            path: entry.name().to_string(),
+            size: buf.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(&buf)),
+        });
+    }
+    drop(archive);
+
+    // only record the sources that actually made it into the archive
+    let sources = SourceKind::ALL
+        .iter()
+        .filter(|&&k| {
+            files
+                .iter()
+                .any(|f| SourceKind::of_archive_path(&f.path).is_some_and(|(kind, _)| kind == k))
+        })
+        .map(|k| k.archive_dir().to_string())
+        .collect();
+    let mut manifest = Manifest::new(sources);
+    manifest.created = created;
+    for entry in files {
+        manifest.push(entry);
+    }
+
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(capsule.path())?;
+    let mut zip = ZipWriter::new_append(file)?;
+    let options: FileOptions<'_, ()> = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+    zip.start_file(MANIFEST_NAME, options)?;
+    zip.write_all(&manifest.to_json()?)?;
+    let file = zip.finish()?;
+    // keep the original mtime so the capsule doesn't jump to the end of the list
+    if let Some(modified) = capsule.modified() {
+        file.set_modified(modified)?;
+    }
+    drop(file);
+
+    let new_path = capsule.path().with_file_name(format!(
+        "{}{}.{}",
+        CAPSULE_PREFIX,
+        created.format("%Y%m%d%H%M%S"),
+        CAPSULE_EXTENSION
+    ));
+    if new_path.exists() {
+        return Err(Error::AlreadyExists(new_path));
+    }
+    fs::rename(capsule.path(), &new_path)?;
+    move_meta(capsule, &new_path)?;
+    info!(from = capsule.name(), to = %new_path.display(), "migrated capsule");
+
+    Ok(Some(Capsule::from_path(new_path)))
+}
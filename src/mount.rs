@@ -0,0 +1,133 @@
+use crate::capsule::{Capsule, CAPSULE_EXTENSION};
+use crate::error::{Error, Result};
+use crate::store::CapsuleStore;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// FUSE file systems that serve a zip file read-only, tried in this order,
+/// with the options that go before the archive and the mount point.
+const MOUNTERS: [(&str, &[&str]); 2] =
+    [("fuse-zip", &["-r"]), ("archivemount", &["-o", "readonly"])];
+
+/// Mount `capsule` read-only at `mountpoint`, created if missing, with
+/// `fuse-zip` or `archivemount`. The file system keeps running in the
+/// background until [`unmount`] is called.
+pub fn mount_capsule(capsule: &Capsule, mountpoint: &Path) -> Result<()> {
+    if capsule.volumes().len() > 1 {
+        return Err(Error::InvalidInput(format!(
+            "{} is split into volumes; join them with `cat` to mount it",
+            capsule.name()
+        )));
+    }
+    fs::create_dir_all(mountpoint)?;
+    for (program, options) in MOUNTERS {
+        let output = match Command::new(program)
+            .args(options)
+            .arg(capsule.path())
+            .arg(mountpoint)
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if !output.status.success() {
+            return Err(Error::InvalidInput(format!(
+                "{} {} failed: {}",
+                program,
+                capsule.name(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        info!(
+            capsule = capsule.name(),
+            mountpoint = %mountpoint.display(),
+            program,
+            "mounted capsule"
+        );
+        return Ok(());
+    }
+    Err(Error::InvalidInput(
+        "mounting needs fuse-zip or archivemount (and FUSE, or macFUSE on macOS)".to_string(),
+    ))
+}
+
+/// Mount every capsule of `store` in a directory of its own below
+/// `mountpoint`, named after the capsule without the extension. Capsules
+/// that can't be mounted are skipped with a warning.
+///
+/// Returns the directories mounted.
+pub fn mount_store(store: &CapsuleStore, mountpoint: &Path) -> Result<Vec<PathBuf>> {
+    let suffix = format!(".{}", CAPSULE_EXTENSION);
+    let mut mounted = Vec::new();
+    for capsule in store.list()? {
+        let name = capsule.name();
+        let dir = mountpoint.join(name.strip_suffix(&suffix).unwrap_or(name));
+        match mount_capsule(&capsule, &dir) {
+            Ok(()) => mounted.push(dir),
+            // without a FUSE program none of them would work
+            Err(e) if mounted.is_empty() && capsule.volumes().len() == 1 => {
+                let _ = fs::remove_dir(&dir);
+                return Err(e);
+            }
+            Err(e) => {
+                let _ = fs::remove_dir(&dir);
+                warn!(capsule = name, error = %e, "could not mount capsule");
+            }
+        }
+    }
+    Ok(mounted)
+}
+
+/// Unmount what [`mount_capsule`] or [`mount_store`] mounted at
+/// `mountpoint`, removing the per-capsule directories of a store mount.
+///
+/// Returns the number of file systems unmounted.
+pub fn unmount(mountpoint: &Path) -> Result<usize> {
+    if unmount_one(mountpoint).is_ok() {
+        return Ok(1);
+    }
+    let mut count = 0;
+    let mut last_error = None;
+    for entry in fs::read_dir(mountpoint)? {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        match unmount_one(&dir) {
+            Ok(()) => {
+                count += 1;
+                let _ = fs::remove_dir(&dir);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) if count == 0 => Err(e),
+        _ => Ok(count),
+    }
+}
+
+fn unmount_one(mountpoint: &Path) -> Result<()> {
+    // fusermount lets a user unmount without root on Linux
+    let mut command = if cfg!(target_os = "linux") {
+        let mut command = Command::new("fusermount");
+        command.arg("-u");
+        command
+    } else {
+        Command::new("umount")
+    };
+    let output = command.arg(mountpoint).output()?;
+    if !output.status.success() {
+        return Err(Error::InvalidInput(format!(
+            "could not unmount {}: {}",
+            mountpoint.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    info!(mountpoint = %mountpoint.display(), "unmounted");
+    Ok(())
+}
@@ -0,0 +1,447 @@
+use crate::capsule::CAPSULE_EXTENSION;
+use crate::environment::hostname;
+use crate::sources::SourceKind;
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+/// The file name scheme of capsules: the file name prefix, then the time.
+pub const DEFAULT_NAME_TEMPLATE: &str = "{prefix}{date}";
+
+/// Longest `{note-slug}`, in characters.
+const MAX_SLUG_LEN: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Prefix,
+    Profile,
+    Hostname,
+    Date,
+    /// With the separators around it that go away when there's no note.
+    NoteSlug {
+        before: Option<char>,
+        after: Option<char>,
+    },
+}
+
+/// A capsule file name pattern like `{hostname}-{profile}-{date}-{note-slug}`,
+/// from `create --name` or `name_template` in the config file.
+///
+/// `{prefix}` is the file name prefix of the profile or `NVIM_APPNAME`
+/// (`nvim_capsule_`), `{profile}` its name, `{hostname}` this machine's
+/// name, `{date}` the time as `YYYYmmddHHMMSS` and `{note-slug}` the note
+/// of `create -m` in lowercase words joined by dashes. `{date}` is required,
+/// as it is what orders the capsules, and so is `{prefix}` or `{profile}`,
+/// which tell the timelines of a store apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+/// The names of the capsules of one profile or `NVIM_APPNAME`: how the next
+/// one is called and which of the names in a store are theirs.
+#[derive(Debug, Clone)]
+pub struct CapsuleNaming {
+    template: NameTemplate,
+    prefix: String,
+    profile: String,
+    /// `None` matches the capsules of every machine.
+    hostname: Option<String>,
+    note: Option<String>,
+    /// What [`matches`](Self::matches) compares names with.
+    patterns: Vec<Regex>,
+}
+
+impl NameTemplate {
+    fn uses(&self, part: &Part) -> bool {
+        self.parts.contains(part)
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        DEFAULT_NAME_TEMPLATE
+            .parse()
+            .expect("the default name template is valid")
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed \"{{\" in name template \"{}\"", s))?;
+            parts.push(match &rest[start + 1..start + end] {
+                "prefix" => Part::Prefix,
+                "profile" => Part::Profile,
+                "hostname" => Part::Hostname,
+                "date" => Part::Date,
+                "note-slug" => Part::NoteSlug {
+                    before: None,
+                    after: None,
+                },
+                other => {
+                    return Err(format!(
+                        "unknown placeholder {{{}}} in name template \"{}\" (expected prefix, profile, hostname, date or note-slug)",
+                        other, s
+                    ))
+                }
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if !parts.contains(&Part::Date) {
+            return Err(format!("name template \"{}\" has no {{date}}", s));
+        }
+        // without either, the capsules of every profile would look alike
+        if !parts.contains(&Part::Prefix) && !parts.contains(&Part::Profile) {
+            return Err(format!(
+                "name template \"{}\" has neither {{prefix}} nor {{profile}}",
+                s
+            ));
+        }
+        if parts
+            .iter()
+            .any(|p| matches!(p, Part::Literal(l) if l.contains(['/', '\\'])))
+        {
+            return Err(format!("invalid name template \"{}\"", s));
+        }
+        // an empty note takes one of its separators along
+        for i in 0..parts.len() {
+            if !matches!(parts[i], Part::NoteSlug { .. }) {
+                continue;
+            }
+            let mut separator = None;
+            if let Some(Part::Literal(literal)) = i.checked_sub(1).map(|j| &mut parts[j]) {
+                if literal.ends_with(is_separator) {
+                    separator = literal.pop().map(|c| (c, true));
+                }
+            }
+            if separator.is_none() {
+                if let Some(Part::Literal(literal)) = parts.get_mut(i + 1) {
+                    if literal.starts_with(is_separator) {
+                        separator = Some((literal.remove(0), false));
+                    }
+                }
+            }
+            if let Some((c, before)) = separator {
+                parts[i] = Part::NoteSlug {
+                    before: before.then_some(c),
+                    after: (!before).then_some(c),
+                };
+            }
+        }
+        parts.retain(|p| !matches!(p, Part::Literal(l) if l.is_empty()));
+        Ok(NameTemplate { parts })
+    }
+}
+
+impl<'de> Deserialize<'de> for NameTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl CapsuleNaming {
+    /// Names from `template` for the capsules with the file name prefix
+    /// `prefix` of the profile or `NVIM_APPNAME` called `profile`, made on
+    /// this machine.
+    pub fn new(template: NameTemplate, prefix: &str, profile: &str) -> Self {
+        // only asked for when needed, as it may run `hostname`
+        let hostname = template
+            .uses(&Part::Hostname)
+            .then(|| hostname().unwrap_or_else(|| "localhost".to_string()));
+        CapsuleNaming {
+            template,
+            prefix: prefix.to_string(),
+            profile: profile.to_string(),
+            hostname,
+            note: None,
+            patterns: Vec::new(),
+        }
+        .compile()
+    }
+
+    /// Names of the default scheme, `<prefix><date>`.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self::new(NameTemplate::default(), prefix, "")
+    }
+
+    /// The file name prefix of the capsules, which sets their timeline apart.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Name of the profile or `NVIM_APPNAME` the capsules are of.
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// The note `{note-slug}` is made from.
+    pub fn note(mut self, note: Option<&str>) -> Self {
+        self.note = note.map(str::to_string);
+        self
+    }
+
+    /// Match the capsules of every machine rather than just this one's.
+    pub fn any_host(mut self) -> Self {
+        self.hostname = None;
+        self.compile()
+    }
+
+    /// The file name of a capsule created at `time`, without the extension.
+    pub fn stem(&self, time: DateTime<Local>) -> String {
+        let mut stem = String::new();
+        for part in &self.template.parts {
+            match part {
+                Part::Literal(literal) => stem.push_str(literal),
+                Part::Prefix => stem.push_str(&self.prefix),
+                Part::Profile => stem.push_str(&sanitize(&self.profile)),
+                Part::Hostname => {
+                    stem.push_str(&sanitize(self.hostname.as_deref().unwrap_or("localhost")))
+                }
+                Part::Date => stem.push_str(&time.format("%Y%m%d%H%M%S").to_string()),
+                Part::NoteSlug { before, after } => {
+                    let slug = slug(self.note.as_deref().unwrap_or_default());
+                    if !slug.is_empty() {
+                        stem.extend(*before);
+                        stem.push_str(&slug);
+                        stem.extend(*after);
+                    }
+                }
+            }
+        }
+        stem
+    }
+
+    /// Whether `name`, of a capsule or snapshot, is one of these names, going
+    /// by the template or by the default scheme, which covers the capsules
+    /// from before a template was set.
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| p.is_match(name))
+    }
+
+    fn compile(mut self) -> Self {
+        self.patterns = vec![self.pattern(&self.template)];
+        let default = NameTemplate::default();
+        if self.template != default {
+            self.patterns.push(self.pattern(&default));
+        }
+        self
+    }
+
+    fn pattern(&self, template: &NameTemplate) -> Regex {
+        let mut pattern = String::from("^");
+        for part in &template.parts {
+            match part {
+                Part::Literal(literal) => pattern.push_str(&regex::escape(literal)),
+                Part::Prefix => pattern.push_str(&regex::escape(&self.prefix)),
+                Part::Profile => pattern.push_str(&regex::escape(&sanitize(&self.profile))),
+                Part::Hostname => match &self.hostname {
+                    Some(hostname) => pattern.push_str(&regex::escape(&sanitize(hostname))),
+                    None => pattern.push_str("[^/]+?"),
+                },
+                Part::Date => pattern.push_str(r"\d{14}"),
+                Part::NoteSlug { before, after } => {
+                    let separator = |c: &Option<char>| {
+                        c.map(|c| regex::escape(&c.to_string())).unwrap_or_default()
+                    };
+                    pattern.push_str(&format!(
+                        "(?:{}[a-z0-9]+(?:-[a-z0-9]+)*{})?",
+                        separator(before),
+                        separator(after)
+                    ));
+                }
+            }
+        }
+        // the number that tells apart capsules of the same second, then the
+        // directories a capsule cloned with `--only` was cut down to
+        let kinds: Vec<&str> = SourceKind::ALL.iter().map(|k| k.name()).collect();
+        pattern.push_str(&format!(
+            r"(?:-\d+)?(?:-(?:{}))*(?:\.{})?$",
+            kinds.join("|"),
+            CAPSULE_EXTENSION
+        ));
+        Regex::new(&pattern).expect("the pattern is escaped")
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '-' | '_' | '.')
+}
+
+/// `value` with anything that doesn't belong in a file name replaced by `-`.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || is_separator(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// The first line of `note` as lowercase ASCII words joined by dashes, e.g.
+/// `before-lazy-migration` for "Before lazy migration!".
+fn slug(note: &str) -> String {
+    let line = note.lines().next().unwrap_or_default().to_ascii_lowercase();
+    let words: Vec<&str> = line
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut slug = String::new();
+    for word in words {
+        if slug.len() + word.len() + 1 > MAX_SLUG_LEN && !slug.is_empty() {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(word);
+    }
+    slug.truncate(MAX_SLUG_LEN);
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn time() -> DateTime<Local> {
+        NaiveDateTime::parse_from_str("20250513120000", "%Y%m%d%H%M%S")
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+    }
+
+    fn naming(template: &str, prefix: &str, profile: &str) -> CapsuleNaming {
+        CapsuleNaming::new(template.parse().unwrap(), prefix, profile)
+    }
+
+    fn error(template: &str) -> String {
+        template.parse::<NameTemplate>().unwrap_err()
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        assert!(error("{prefix}{when}").contains("unknown placeholder {when}"));
+        assert!(error("{prefix}{date").contains("unclosed"));
+        assert!(error("{prefix}{date}-{note").contains("unclosed"));
+        assert!(error("{prefix}-{profile}").contains("has no {date}"));
+        assert!(error("{hostname}-{date}").contains("neither {prefix} nor {profile}"));
+        assert!(error("backups/{prefix}{date}").contains("invalid name template"));
+    }
+
+    #[test]
+    fn the_default_template_is_the_default_scheme() {
+        let stem = CapsuleNaming::with_prefix("nvim_capsule_").stem(time());
+        assert_eq!(stem, "nvim_capsule_20250513120000");
+    }
+
+    #[test]
+    fn stem_without_a_note_drops_its_separator() {
+        let naming = naming("{profile}-{date}-{note-slug}", "tmux_capsule_", "tmux");
+        assert_eq!(naming.stem(time()), "tmux-20250513120000");
+        let naming = naming.note(Some("   "));
+        assert_eq!(naming.stem(time()), "tmux-20250513120000");
+
+        let naming = self::naming("{note-slug}_{prefix}{date}", "nvim_capsule_", "nvim");
+        assert_eq!(naming.stem(time()), "nvim_capsule_20250513120000");
+    }
+
+    #[test]
+    fn stem_with_a_note() {
+        let naming = naming("{profile}-{date}-{note-slug}", "tmux_capsule_", "tmux")
+            .note(Some("Before lazy migration!\nand more"));
+        assert_eq!(
+            naming.stem(time()),
+            "tmux-20250513120000-before-lazy-migration"
+        );
+
+        let naming = self::naming("{note-slug}_{prefix}{date}", "nvim_capsule_", "nvim")
+            .note(Some("Pre-0.11"));
+        assert_eq!(naming.stem(time()), "pre-0-11_nvim_capsule_20250513120000");
+    }
+
+    #[test]
+    fn stem_sanitizes_and_shortens() {
+        let naming = naming("{profile}-{date}-{note-slug}", "p_", "my tmux/conf")
+            .note(Some(&"word ".repeat(20)));
+        let stem = naming.stem(time());
+        assert!(stem.starts_with("my-tmux-conf-20250513120000-word-word"));
+        let slug = stem.rsplit_once("20250513120000-").unwrap().1;
+        assert!(slug.len() <= MAX_SLUG_LEN && !slug.ends_with('-'));
+    }
+
+    #[test]
+    fn matches_template_and_default_names() {
+        let naming = naming("{profile}-{date}-{note-slug}", "tmux_capsule_", "tmux");
+        for name in [
+            "tmux-20250513120000.zip",
+            "tmux-20250513120000-before-lazy.zip",
+            // a second capsule within the same second
+            "tmux-20250513120000-1.zip",
+            "tmux-20250513120000-before-lazy-2.zip",
+            // a snapshot or a .part path, without the extension
+            "tmux-20250513120000",
+            // from before the template was set
+            "tmux_capsule_20250513120000.zip",
+            "tmux_capsule_20250513120000-1.zip",
+        ] {
+            assert!(naming.matches(name), "{} didn't match", name);
+        }
+        for name in [
+            "tmux-min-20250513120000.zip",
+            "nvim-20250513120000.zip",
+            "nvim_capsule_20250513120000.zip",
+            "tmux-2025051312.zip",
+            "tmux-20250513120000-Before.zip",
+            "tmux-20250513120000.zip.002",
+            "pre-lazy.zip",
+        ] {
+            assert!(!naming.matches(name), "{} matched", name);
+        }
+    }
+
+    #[test]
+    fn the_default_scheme_keeps_appnames_apart() {
+        let naming = CapsuleNaming::with_prefix("nvim_capsule_");
+        assert!(naming.matches("nvim_capsule_20250513120000.zip"));
+        assert!(naming.matches("nvim_capsule_20250513120000-3.zip"));
+        assert!(naming.matches("nvim_capsule_20250513120000-config-data.zip"));
+        assert!(!naming.matches("nvim_capsule_nvim-minimal_20250513120000.zip"));
+        assert!(!naming.matches("nvim_backup_20250513120000.zip"));
+    }
+
+    #[test]
+    fn matches_other_hosts_with_any_host() {
+        let naming = naming("{hostname}-{prefix}{date}", "nvim_capsule_", "nvim");
+        let own = format!("{}.zip", naming.stem(time()));
+        let other = "some-other-machine-nvim_capsule_20250513120000.zip";
+        assert!(naming.matches(&own));
+        assert!(!naming.matches(other) || own == other);
+
+        let naming = naming.any_host();
+        assert!(naming.matches(&own));
+        assert!(naming.matches(other));
+        assert!(!naming.matches("nvim_capsule_nvim-minimal_20250513120000.zip"));
+    }
+}
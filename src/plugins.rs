@@ -0,0 +1,57 @@
+use crate::sources::SourceKind;
+use std::fmt;
+use std::path::Path;
+
+/// Lock file lazy.nvim keeps next to `init.lua`.
+pub const LAZY_LOCK_FILE: &str = "lazy-lock.json";
+
+/// Where packer.nvim writes its snapshots by default, inside the cache dir.
+pub const PACKER_SNAPSHOT_DIR: &str = "packer.nvim";
+
+/// A plugin manager whose lock files a plugin capsule carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginManager {
+    Lazy,
+    Packer,
+}
+
+impl PluginManager {
+    /// The plugin manager used by the `appname` profile of `home`, going by
+    /// its lock file or its install directory.
+    pub fn detect(home: &Path, appname: &str) -> Option<Self> {
+        let config = SourceKind::Config.dir(home, appname);
+        let data = SourceKind::Data.dir(home, appname);
+        if config.join(LAZY_LOCK_FILE).is_file() || data.join("lazy/lazy.nvim").is_dir() {
+            Some(PluginManager::Lazy)
+        } else if data.join("site/pack/packer").is_dir() {
+            Some(PluginManager::Packer)
+        } else {
+            None
+        }
+    }
+
+    /// Arguments for a headless `nvim` that installs the locked plugin
+    /// versions and exits.
+    pub fn sync_args(self) -> &'static [&'static str] {
+        match self {
+            // restore, unlike sync, installs what the lock file says
+            PluginManager::Lazy => &["--headless", "+Lazy! restore", "+qa"],
+            PluginManager::Packer => &[
+                "--headless",
+                "-c",
+                "autocmd User PackerComplete quitall",
+                "-c",
+                "PackerSync",
+            ],
+        }
+    }
+}
+
+impl fmt::Display for PluginManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PluginManager::Lazy => "lazy.nvim",
+            PluginManager::Packer => "packer.nvim",
+        })
+    }
+}
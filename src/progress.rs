@@ -0,0 +1,23 @@
+use std::path::Path;
+
+/// Receives progress updates from long-running operations.
+///
+/// The library never writes to stdout itself; front ends implement this
+/// to render a progress bar (or nothing at all). Updates may arrive from
+/// several worker threads.
+pub trait Progress: Sync {
+    /// Called once the number of items and their total size are known.
+    fn start(&self, _total: u64, _total_bytes: u64) {}
+
+    /// Called after each item has been processed, with its size.
+    fn inc(&self, _item: &Path, _bytes: u64) {}
+
+    /// Called when the operation completed successfully.
+    fn finish(&self) {}
+}
+
+/// A [`Progress`] that ignores all updates.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
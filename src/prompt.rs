@@ -0,0 +1,115 @@
+use dialoguer::{theme::ColorfulTheme, Confirm, Editor, FuzzySelect, MultiSelect, Select};
+use std::io;
+
+/// Asks the user, or answers with the defaults when running non-interactively.
+pub struct Prompter {
+    interactive: bool,
+}
+
+impl Prompter {
+    pub fn new(interactive: bool) -> Self {
+        Prompter { interactive }
+    }
+
+    /// Whether the user is asked, rather than the defaults taken.
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn confirm(&self, prompt: &str, default: bool) -> io::Result<bool> {
+        if !self.interactive {
+            return Ok(default);
+        }
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()
+            .map_err(into_io)
+    }
+
+    pub fn select<T: ToString>(
+        &self,
+        prompt: &str,
+        items: &[T],
+        default: usize,
+    ) -> io::Result<usize> {
+        if !self.interactive {
+            return Ok(default);
+        }
+        Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(items)
+            .default(default)
+            .interact()
+            .map_err(into_io)
+    }
+
+    pub fn multi_select<T: ToString>(
+        &self,
+        prompt: &str,
+        items: &[T],
+        defaults: &[bool],
+    ) -> io::Result<Vec<usize>> {
+        if !self.interactive {
+            return Ok((0..items.len()).filter(|&i| defaults[i]).collect());
+        }
+        MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(items)
+            .defaults(defaults)
+            .interact()
+            .map_err(into_io)
+    }
+
+    /// Like [`choose`](Self::choose), picking any number of items.
+    pub fn choose_many<T: ToString>(
+        &self,
+        prompt: &str,
+        items: &[T],
+        missing: &str,
+    ) -> io::Result<Vec<usize>> {
+        if !self.interactive {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, missing));
+        }
+        MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(items)
+            .interact()
+            .map_err(into_io)
+    }
+
+    /// A choice without a sensible default, narrowed down by typing to
+    /// fuzzy-search the items; non-interactively this fails with `missing`,
+    /// which should say how to make the choice instead.
+    pub fn choose<T: ToString>(
+        &self,
+        prompt: &str,
+        items: &[T],
+        missing: &str,
+    ) -> io::Result<usize> {
+        if !self.interactive {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, missing));
+        }
+        FuzzySelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(items)
+            .default(0)
+            .max_length(15)
+            .interact()
+            .map_err(into_io)
+    }
+
+    /// Open `text` in `$VISUAL`/`$EDITOR`; `None` if it was closed without saving.
+    pub fn edit(&self, text: &str, missing: &str) -> io::Result<Option<String>> {
+        if !self.interactive {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, missing));
+        }
+        Editor::new().extension(".md").edit(text).map_err(into_io)
+    }
+}
+
+fn into_io(e: dialoguer::Error) -> io::Error {
+    match e {
+        dialoguer::Error::IO(e) => e,
+    }
+}
@@ -0,0 +1,375 @@
+use crate::capsule::{Capsule, CAPSULE_EXTENSION};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::metadata::META_EXTENSION;
+use crate::store::CAPSULE_DIR_NAME;
+use crate::volume::volume_path;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use tracing::info;
+
+/// The capsule store of another machine, reached with `ssh` and `scp`, so
+/// the keys, agents and host aliases of `~/.ssh/config` all apply.
+#[derive(Debug, Clone)]
+pub struct SshRemote {
+    host: String,
+    /// Store directory, relative to the remote HOME unless absolute.
+    dir: String,
+    /// Most bytes per second to transfer.
+    limit_rate: Option<u64>,
+}
+
+/// A capsule in the store of an [`SshRemote`], known by its file listing only.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteCapsule {
+    pub name: String,
+    /// Going by the timestamp in the name.
+    pub created: Option<DateTime<Local>>,
+    /// Size of the archive, with all of its volumes.
+    pub size: u64,
+    /// Number of volumes the archive is split into.
+    pub volumes: usize,
+}
+
+/// Outcome of [`SshRemote::upload`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Upload {
+    /// Size of the capsule's archive, with all of its volumes.
+    pub size: u64,
+    /// Bytes sent over the network for it, less than `size` after a delta transfer.
+    pub sent: u64,
+}
+
+/// A capsule downloaded from an [`SshRemote`] (or packed from a
+/// [`Snapshot`](crate::Snapshot)) into a scratch directory, which is removed
+/// again when this is dropped.
+#[derive(Debug)]
+pub struct FetchedCapsule {
+    pub(crate) dir: PathBuf,
+    pub(crate) capsule: Capsule,
+}
+
+impl FetchedCapsule {
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+}
+
+impl Drop for FetchedCapsule {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl SshRemote {
+    /// The store in `~/.nvim_capsules` of `host`, e.g. `user@laptop` or an
+    /// alias from `~/.ssh/config`.
+    pub fn new(host: &str) -> Self {
+        SshRemote {
+            host: host.to_string(),
+            dir: CAPSULE_DIR_NAME.to_string(),
+            limit_rate: None,
+        }
+    }
+
+    /// The remote called `name` in the config file, or else the host `name`.
+    pub fn resolve(config: &Config, name: &str) -> Self {
+        match config.remotes.get(name) {
+            Some(remote) => {
+                let ssh = SshRemote::new(&remote.host);
+                match &remote.dir {
+                    Some(dir) => ssh.dir(dir),
+                    None => ssh,
+                }
+            }
+            None => SshRemote::new(name),
+        }
+    }
+
+    /// Use another store directory on the remote machine.
+    pub fn dir(mut self, dir: &str) -> Self {
+        // the remote shell starts in HOME, so `~/` is implied
+        self.dir = dir
+            .trim_start_matches("~/")
+            .trim_end_matches('/')
+            .to_string();
+        self
+    }
+
+    /// Transfer at most `bytes_per_sec`, to leave the rest of the line free.
+    pub fn limit_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.limit_rate = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Copy `capsule`, its volumes and its metadata into the remote store,
+    /// then compare checksums on both sides.
+    ///
+    /// With `rsync` on both machines only what changed is sent: the newest
+    /// capsule already there is copied to the new name first and used as
+    /// the basis of the transfer. Files in a zip are compressed one by one,
+    /// so unchanged files look the same in both archives. Otherwise the
+    /// whole capsule goes over with `scp`.
+    pub fn upload(&self, capsule: &Capsule) -> Result<Upload> {
+        self.run(&format!("mkdir -p {}", quote(&self.dir)))?;
+        let delta = self.has_rsync();
+        let basis = if delta {
+            self.previous(capsule)?
+        } else {
+            info!(host = %self.host, "no rsync on both machines, copying whole capsules");
+            None
+        };
+        let mut upload = Upload::default();
+        for (i, path) in capsule.volumes().iter().enumerate() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let remote = self.path(&name);
+            if !delta {
+                self.scp(path.as_os_str(), self.remote_arg(&remote).as_ref())?;
+                upload.sent += fs::metadata(path)?.len();
+            } else {
+                if let Some(basis) = basis.as_ref().filter(|b| i < b.volumes) {
+                    let basis = self.path(&volume_name(&basis.name, i + 1));
+                    self.run(&format!(
+                        "[ -e {remote} ] || cp -p {} {remote}",
+                        quote(&basis),
+                        remote = quote(&remote)
+                    ))?;
+                }
+                upload.sent += self.rsync(path, &remote)?;
+            }
+            self.compare(path, &remote)?;
+            upload.size += fs::metadata(path)?.len();
+        }
+        let meta = capsule.meta_path();
+        if meta.exists() {
+            let remote = self.path(&meta.file_name().unwrap_or_default().to_string_lossy());
+            self.scp(meta.as_os_str(), self.remote_arg(&remote).as_ref())?;
+            self.compare(&meta, &remote)?;
+        }
+        info!(
+            capsule = capsule.name(),
+            host = %self.host,
+            bytes = upload.size,
+            sent = upload.sent,
+            basis = basis.as_ref().map(|b| b.name.as_str()),
+            "uploaded capsule"
+        );
+        Ok(upload)
+    }
+
+    /// The newest remote capsule from before `capsule`, as the basis of a
+    /// delta transfer.
+    fn previous(&self, capsule: &Capsule) -> Result<Option<RemoteCapsule>> {
+        let created = capsule.created();
+        Ok(self
+            .list()?
+            .into_iter()
+            .rfind(|c| c.name != capsule.name() && c.created <= created))
+    }
+
+    /// Whether `rsync` runs here and on the remote machine.
+    fn has_rsync(&self) -> bool {
+        let local = Command::new("rsync")
+            .arg("--version")
+            .output()
+            .is_ok_and(|o| o.status.success());
+        local && self.run("command -v rsync").is_ok()
+    }
+
+    /// Send `local` to `remote` with `rsync`, which reuses the blocks of a
+    /// file already at `remote`. Returns the bytes sent.
+    fn rsync(&self, local: &Path, remote: &str) -> Result<u64> {
+        let mut rsync = Command::new("rsync");
+        // protect-args keeps the remote shell from splitting the path
+        rsync.args(["--times", "--protect-args", "--stats"]);
+        if let Some(rate) = self.limit_rate {
+            // rsync counts in KiB/s
+            rsync.arg(format!("--bwlimit={}", (rate / 1024).max(1)));
+        }
+        let output = rsync.arg(local).arg(self.remote_arg(remote)).output()?;
+        let stdout = self.check(output)?;
+        let sent = stdout
+            .lines()
+            .find_map(|l| l.strip_prefix("Total bytes sent:"))
+            .map(|n| n.chars().filter(char::is_ascii_digit).collect::<String>())
+            .and_then(|n| n.parse().ok());
+        Ok(sent.unwrap_or(0))
+    }
+
+    /// `host:path` for scp and rsync.
+    fn remote_arg(&self, remote: &str) -> String {
+        format!("{}:{}", self.host, remote)
+    }
+
+    /// The capsules in the remote store, oldest first, as for
+    /// [`CapsuleStore::list`](crate::CapsuleStore::list). A missing store is empty.
+    pub fn list(&self) -> Result<Vec<RemoteCapsule>> {
+        let stdout = self.run(&format!(
+            "cd {} 2>/dev/null || exit 0; for f in *; do \
+             [ -f \"$f\" ] && printf '%s\\t%s\\n' \"$(wc -c < \"$f\")\" \"$f\"; done; true",
+            quote(&self.dir)
+        ))?;
+        let suffix = format!(".{}", CAPSULE_EXTENSION);
+        let mut capsules: Vec<RemoteCapsule> = Vec::new();
+        let mut volumes = Vec::new();
+        for line in stdout.lines() {
+            let Some((size, name)) = line.split_once('\t') else {
+                continue;
+            };
+            let size: u64 = size.trim().parse().unwrap_or(0);
+            if name.ends_with(&suffix) {
+                capsules.push(RemoteCapsule {
+                    name: name.to_string(),
+                    created: Capsule::from_path(name).timestamp_from_name(),
+                    size,
+                    volumes: 1,
+                });
+            } else if let Some((archive, n)) = name.rsplit_once('.') {
+                if archive.ends_with(&suffix) && n.len() == 3 && n.parse::<u32>().is_ok() {
+                    volumes.push((archive.to_string(), size));
+                }
+            }
+        }
+        for (archive, size) in volumes {
+            if let Some(capsule) = capsules.iter_mut().find(|c| c.name == archive) {
+                capsule.size += size;
+                capsule.volumes += 1;
+            }
+        }
+        capsules.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+        Ok(capsules)
+    }
+
+    /// Download `capsule` with its volumes and metadata into a scratch
+    /// directory, checking the checksums, to restore or inspect it.
+    pub fn fetch(&self, capsule: &RemoteCapsule) -> Result<FetchedCapsule> {
+        let dir = env::temp_dir().join(format!("nvim-tm-remote-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        // removes the directory again if the download fails
+        let mut fetched = FetchedCapsule {
+            capsule: Capsule::from_path(dir.join(&capsule.name)),
+            dir,
+        };
+        let mut names: Vec<String> = (1..=capsule.volumes)
+            .map(|n| volume_name(&capsule.name, n))
+            .collect();
+        let meta = Path::new(&capsule.name).with_extension(META_EXTENSION);
+        names.push(meta.to_string_lossy().into_owned());
+        for (i, name) in names.iter().enumerate() {
+            let remote = self.path(name);
+            let local = fetched.dir.join(name);
+            let copied = self.scp(self.remote_arg(&remote).as_ref(), local.as_os_str());
+            match copied {
+                // a capsule without tags or a note has no metadata file
+                Err(_) if i == names.len() - 1 => continue,
+                result => result?,
+            }
+            self.compare(&local, &remote)?;
+        }
+        // read the mtime scp kept, which dates capsules without a timestamp in the name
+        fetched.capsule = Capsule::from_path(fetched.capsule.path().to_path_buf());
+        info!(capsule = %capsule.name, host = %self.host, "downloaded capsule");
+        Ok(fetched)
+    }
+
+    /// Copy `from` to `to` with `scp`, keeping the mtime, one of them being
+    /// `host:path`.
+    fn scp(&self, from: &OsStr, to: &OsStr) -> Result<()> {
+        // scp hands remote paths to SFTP, not to a shell, so they aren't quoted
+        let mut scp = Command::new("scp");
+        scp.arg("-q").arg("-p");
+        if let Some(rate) = self.limit_rate {
+            // scp counts in Kbit/s
+            scp.arg("-l").arg((rate * 8 / 1000).max(1).to_string());
+        }
+        let output = scp.arg(from).arg(to).output()?;
+        self.check(output).map(|_| ())
+    }
+
+    /// Fail unless the local file at `local` and `remote` have the same checksum.
+    fn compare(&self, local: &Path, remote: &str) -> Result<()> {
+        if sha256_file(local)? != self.sha256(remote)? {
+            return Err(Error::VerificationFailed(format!(
+                "{} on {} doesn't match the copy on this machine",
+                remote, self.host
+            )));
+        }
+        Ok(())
+    }
+
+    /// Path of the file `name` in the remote store.
+    fn path(&self, name: &str) -> String {
+        if self.dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.dir, name)
+        }
+    }
+
+    /// SHA-256 of a remote file, with whichever tool the remote has.
+    fn sha256(&self, path: &str) -> Result<String> {
+        let path = quote(path);
+        let stdout = self.run(&format!(
+            "sha256sum {} 2>/dev/null || shasum -a 256 {}",
+            path, path
+        ))?;
+        stdout
+            .split_whitespace()
+            .next()
+            .map(str::to_lowercase)
+            .ok_or_else(|| self.error("no checksum from sha256sum or shasum".to_string()))
+    }
+
+    /// Run `command` in the remote shell and return its stdout.
+    fn run(&self, command: &str) -> Result<String> {
+        let output = Command::new("ssh").arg(&self.host).arg(command).output()?;
+        self.check(output)
+    }
+
+    fn check(&self, output: Output) -> Result<String> {
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(self.error(match stderr.trim() {
+            "" => output.status.to_string(),
+            message => message.to_string(),
+        }))
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Remote {
+            host: self.host.clone(),
+            message,
+        }
+    }
+}
+
+/// File name of volume `n` of the capsule `name`.
+fn volume_name(name: &str, n: usize) -> String {
+    volume_path(Path::new(name), n)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `s` as a single word for a POSIX shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
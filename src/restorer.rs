@@ -0,0 +1,905 @@
+use crate::archiver::{Archiver, Symlinks};
+use crate::capsule::{entry_mtime, Capsule};
+use crate::config::{Config, Profile};
+use crate::error::{Error, Result};
+use crate::filter::EntryFilter;
+use crate::history::resolve_path;
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::naming::CapsuleNaming;
+use crate::plugins::PACKER_SNAPSHOT_DIR;
+use crate::progress::Progress;
+use crate::snapshot::set_mode;
+use crate::sources::{SourceKind, DEFAULT_APPNAME};
+use crate::store::capsule_prefix;
+use crate::transaction::Transaction;
+use chrono::Local;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::SystemTime;
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+/// What to do with the current Neovim directories before restoring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingDirs {
+    /// Rename them with a timestamp suffix.
+    Backup,
+    /// Remove them entirely.
+    Delete,
+    /// Leave them in place; extracted files overwrite existing ones.
+    Keep,
+}
+
+/// How to handle files that already exist when layering a capsule over the
+/// current tree (with [`ExistingDirs::Keep`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictStrategy {
+    /// Replace existing files with the archived copy.
+    #[default]
+    Overwrite,
+    /// Leave existing files alone; only create missing ones.
+    SkipExisting,
+    /// Keep files changed on disk after the capsule was created, replace the rest.
+    Merge,
+    /// Planned like `Overwrite`; the front end asks per file and marks
+    /// declined ones as [`Action::Skip`].
+    Prompt,
+}
+
+impl ConflictStrategy {
+    pub const ALL: [ConflictStrategy; 4] = [
+        ConflictStrategy::Overwrite,
+        ConflictStrategy::SkipExisting,
+        ConflictStrategy::Merge,
+        ConflictStrategy::Prompt,
+    ];
+
+    /// Name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            ConflictStrategy::Overwrite => "overwrite",
+            ConflictStrategy::SkipExisting => "skip-existing",
+            ConflictStrategy::Merge => "merge",
+            ConflictStrategy::Prompt => "prompt",
+        }
+    }
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|k| k.name()).collect();
+                format!(
+                    "unknown strategy \"{}\" (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// What happens to a single archive entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    Overwrite,
+    Skip,
+}
+
+/// An archive entry and where it goes.
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    /// Index of the entry in the archive.
+    pub index: usize,
+    pub name: String,
+    pub target: PathBuf,
+    pub action: Action,
+    /// Uncompressed size of the entry.
+    pub size: u64,
+    /// Permissions and modification time to give the file, for capsules that
+    /// recorded them.
+    pub mode: Option<u32>,
+    pub mtime: Option<SystemTime>,
+    /// A directory entry, created even if nothing is extracted into it.
+    pub is_dir: bool,
+    /// A symbolic link, made once everything else is extracted.
+    pub is_symlink: bool,
+}
+
+/// What a restore is going to do, file by file.
+#[derive(Debug, Clone, Default)]
+pub struct RestorePlan {
+    pub files: Vec<PlannedFile>,
+}
+
+impl RestorePlan {
+    /// Number of files that will get `action`.
+    pub fn count(&self, action: Action) -> usize {
+        self.files
+            .iter()
+            .filter(|f| f.action == action && !f.is_dir)
+            .count()
+    }
+}
+
+/// Unpacks a capsule over the Neovim directories.
+#[derive(Debug, Clone)]
+pub struct Restorer {
+    home: PathBuf,
+    appname: String,
+    /// Use the capsule's layout below `home` rather than Neovim's directories.
+    plain: bool,
+    sources: Vec<SourceKind>,
+    /// HOME-relative directories of a profile capsule, restored instead of
+    /// `sources`.
+    dirs: Vec<String>,
+    filter: EntryFilter,
+    strategy: ConflictStrategy,
+    jobs: usize,
+}
+
+impl Restorer {
+    /// Restore into the Neovim directories of `home`, wherever the XDG
+    /// variables and the platform put them.
+    pub fn new(home: impl Into<PathBuf>) -> Self {
+        Restorer {
+            home: home.into(),
+            appname: DEFAULT_APPNAME.to_string(),
+            plain: false,
+            sources: SourceKind::ALL.to_vec(),
+            dirs: Vec::new(),
+            filter: EntryFilter::default(),
+            strategy: ConflictStrategy::default(),
+            jobs: thread::available_parallelism().map_or(1, usize::from),
+        }
+    }
+
+    /// Restore below `home` into the directories `capsule` was made of: those
+    /// of its profile, or the Neovim directories of its `NVIM_APPNAME` that
+    /// it covers. Directories the capsule doesn't cover, like state in older
+    /// capsules, are left alone.
+    pub fn for_capsule(home: impl Into<PathBuf>, capsule: &Capsule) -> Result<Self> {
+        let restorer = Self::new(home)
+            .appname(&capsule.appname()?)
+            .only(&capsule.sources()?);
+        let dirs = capsule.profile_dirs()?;
+        if dirs.is_empty() {
+            Ok(restorer)
+        } else {
+            Ok(restorer.dirs(&dirs))
+        }
+    }
+
+    /// Restore `capsule` into the directories of the `NVIM_APPNAME`
+    /// `appname`, to try it out without touching the real configuration.
+    ///
+    /// Only capsules of Neovim directories can be tried: those of a profile
+    /// have nowhere else to go than the live directories.
+    pub fn preview(home: impl Into<PathBuf>, capsule: &Capsule, appname: &str) -> Result<Self> {
+        if appname == DEFAULT_APPNAME {
+            return Err(Error::InvalidInput(format!(
+                "\"{}\" can't be used as a preview profile",
+                appname
+            )));
+        }
+        if !capsule.profile_dirs()?.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "\"{}\" is a profile capsule, which can't be tried; restore it with --to-dir to look at it",
+                capsule.name()
+            )));
+        }
+        Ok(Self::new(home).appname(appname).only(&capsule.sources()?))
+    }
+
+    /// An archiver of what restoring `capsule` replaces, and the names of the
+    /// timeline its capsule goes into, to save the current state first so the
+    /// restore can be undone.
+    pub fn current_state(
+        &self,
+        config: &Config,
+        capsule: &Capsule,
+    ) -> Result<(Archiver, CapsuleNaming)> {
+        let archiver = Archiver::new(&self.home);
+        let (archiver, naming) = match config.capsule_profile(capsule)? {
+            Some((name, profile)) => {
+                // exactly the directories about to be replaced, exclusions and all
+                let profile = Profile {
+                    sources: self.dirs.clone(),
+                    exclude: Vec::new(),
+                    ..profile
+                };
+                let naming = config.naming(&profile.prefix(&name), &name);
+                (archiver.profile(&name, &profile)?, naming)
+            }
+            None => (
+                archiver.appname(&self.appname),
+                config.naming(&capsule_prefix(&self.appname), &self.appname),
+            ),
+        };
+        // links are kept as links so restoring the capsule gives back the same tree
+        Ok((archiver.symlinks(Symlinks::Preserve), naming))
+    }
+
+    /// Number of extraction workers [default: one per CPU].
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// How to treat existing files with [`ExistingDirs::Keep`].
+    pub fn strategy(mut self, strategy: ConflictStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Only extract the archive entries passing `filter`.
+    pub fn filter(mut self, filter: EntryFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Restore into the directories of another `NVIM_APPNAME`, e.g.
+    /// `~/.config/<appname>` instead of `~/.config/nvim`.
+    pub fn appname(mut self, appname: &str) -> Self {
+        self.appname = appname.to_string();
+        self
+    }
+
+    /// Extract into the capsule's own `.config/nvim`, `.local/share/nvim`, ...
+    /// layout below the directory given to [`new`](Self::new), e.g. to look at
+    /// a capsule somewhere other than HOME.
+    pub fn plain_layout(mut self) -> Self {
+        self.plain = true;
+        self
+    }
+
+    /// Restore the directories of a profile capsule, such as `.config/tmux`,
+    /// instead of the Neovim ones.
+    pub fn dirs(mut self, dirs: &[String]) -> Self {
+        self.dirs = dirs.to_vec();
+        self
+    }
+
+    /// Only restore (and clear) the given directories.
+    pub fn only(mut self, sources: &[SourceKind]) -> Self {
+        self.sources.retain(|k| sources.contains(k));
+        self
+    }
+
+    pub fn sources(&self) -> &[SourceKind] {
+        &self.sources
+    }
+
+    /// The directories that will be cleared and restored.
+    pub fn targets(&self) -> Vec<PathBuf> {
+        if !self.dirs.is_empty() {
+            return self.dirs.iter().map(|d| self.home.join(d)).collect();
+        }
+        let mut targets = Vec::new();
+        for &kind in &self.sources {
+            let dir = self.target_dir(kind);
+            // Windows keeps data and state in the same directory
+            if !targets.contains(&dir) {
+                targets.push(dir);
+            }
+        }
+        targets
+    }
+
+    /// Where the files of `kind` go.
+    pub fn target_dir(&self, kind: SourceKind) -> PathBuf {
+        if self.plain {
+            kind.plain_dir(&self.home, &self.appname)
+        } else {
+            kind.dir(&self.home, &self.appname)
+        }
+    }
+
+    /// Where the archive entry `name` is extracted to, or `None` to skip it.
+    fn target_for(&self, name: &str) -> Option<PathBuf> {
+        if !self.filter.matches(name) {
+            return None;
+        }
+        if !self.dirs.is_empty() {
+            let inside = |d: &String| {
+                name.strip_prefix(d.as_str())
+                    .is_some_and(|r| r.starts_with('/'))
+            };
+            return self.dirs.iter().any(inside).then(|| self.home.join(name));
+        }
+        match SourceKind::of_archive_path(name) {
+            Some((kind, rest)) => self
+                .sources
+                .contains(&kind)
+                .then(|| self.target_dir(kind).join(rest)),
+            // entries outside the known directories only come along on a full restore
+            None => (self.sources.len() == SourceKind::ALL.len()).then(|| self.home.join(name)),
+        }
+    }
+
+    /// Decide what happens to every archive entry of `capsule`.
+    ///
+    /// With [`ExistingDirs::Backup`] or [`ExistingDirs::Delete`] the targets
+    /// are cleared first, so every file is created; with [`ExistingDirs::Keep`]
+    /// files that already exist are handled by the [`ConflictStrategy`].
+    pub fn plan(&self, capsule: &Capsule, existing: ExistingDirs) -> Result<RestorePlan> {
+        let mut archive = capsule.archive()?;
+        let manifest = Manifest::read_from(&mut archive)?;
+        // anything changed on disk after the capsule was made counts as newer
+        let capsule_time = match &manifest {
+            Some(manifest) => Some(SystemTime::from(manifest.created)),
+            None => capsule.created().map(SystemTime::from),
+        };
+        let file_metadata = manifest.is_some_and(|m| m.file_metadata);
+
+        let mut files = Vec::new();
+        let mut links = HashSet::new();
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index)?;
+            let Some(name) = entry.enclosed_name() else {
+                continue;
+            };
+            let name = name.to_string_lossy().replace('\\', "/");
+            if name == MANIFEST_NAME {
+                continue;
+            }
+            let is_symlink = entry.is_symlink();
+            if is_symlink {
+                links.insert(name.clone());
+            }
+            let Some(target) = self.target_for(&name) else {
+                continue;
+            };
+            let is_dir = entry.is_dir();
+            // directories are only ever created, never replaced
+            if is_dir && existing == ExistingDirs::Keep && target.is_dir() {
+                continue;
+            }
+
+            let current = match existing {
+                ExistingDirs::Keep => fs::symlink_metadata(&target).ok(),
+                ExistingDirs::Backup | ExistingDirs::Delete => None,
+            };
+            let action = match (current, self.strategy) {
+                (None, _) => Action::Create,
+                (Some(_), ConflictStrategy::SkipExisting) => Action::Skip,
+                (Some(meta), ConflictStrategy::Merge) => {
+                    let newer = meta
+                        .modified()
+                        .ok()
+                        .zip(capsule_time)
+                        .is_some_and(|(disk, capsule)| disk > capsule);
+                    if newer {
+                        Action::Skip
+                    } else {
+                        Action::Overwrite
+                    }
+                }
+                (Some(_), ConflictStrategy::Overwrite | ConflictStrategy::Prompt) => {
+                    Action::Overwrite
+                }
+            };
+            files.push(PlannedFile {
+                index,
+                name,
+                target,
+                action,
+                size: entry.size(),
+                mode: entry
+                    .unix_mode()
+                    .filter(|_| file_metadata)
+                    .map(|m| m & 0o7777),
+                mtime: entry_mtime(&entry).filter(|_| file_metadata),
+                is_dir,
+                is_symlink,
+            });
+        }
+        // an entry below an archived link would be written wherever it points
+        for file in &files {
+            if let Some(link) = linked_ancestor(&file.name, &links) {
+                return Err(Error::InvalidInput(format!(
+                    "{}: \"{}\" is inside the archived link \"{}\"; refusing to restore it",
+                    capsule.name(),
+                    file.name,
+                    link
+                )));
+            }
+        }
+        Ok(RestorePlan { files })
+    }
+
+    /// Files currently in the target directories that a restore with
+    /// `existing` would delete or move aside, because `plan` does not put
+    /// anything back in their place.
+    pub fn displaced(&self, plan: &RestorePlan, existing: ExistingDirs) -> Vec<PathBuf> {
+        if existing == ExistingDirs::Keep {
+            return Vec::new();
+        }
+        let restored: HashSet<&Path> = plan.files.iter().map(|f| f.target.as_path()).collect();
+        let mut displaced: Vec<PathBuf> = self
+            .targets()
+            .iter()
+            .flat_map(|dir| WalkDir::new(dir).into_iter().filter_map(Result::ok))
+            .filter(|e| !e.file_type().is_dir() && !restored.contains(e.path()))
+            .map(|e| e.into_path())
+            .collect();
+        displaced.sort();
+        displaced
+    }
+
+    /// Clear the target directories and extract `capsule` into them.
+    pub fn restore(
+        &self,
+        capsule: &Capsule,
+        existing: ExistingDirs,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        let plan = self.plan(capsule, existing)?;
+        self.execute(capsule, &plan, existing, progress)
+    }
+
+    /// Carry out a plan made by [`plan`](Self::plan) with the same `existing`.
+    ///
+    /// Files are extracted to staging locations first and only swapped in
+    /// once all of them were written; on any error the previous state is
+    /// restored.
+    pub fn execute(
+        &self,
+        capsule: &Capsule,
+        plan: &RestorePlan,
+        existing: ExistingDirs,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        info!(
+            capsule = capsule.name(),
+            into = %self.home.display(),
+            ?existing,
+            strategy = self.strategy.name(),
+            "restoring"
+        );
+        let ts = Local::now().format("%Y%m%d%H%M%S").to_string();
+        let mut tx = Transaction::new(&self.home, &ts);
+        for dir in self.targets() {
+            match existing {
+                ExistingDirs::Backup => {
+                    // named after each directory, as `nvim` and `nvim-data`
+                    // share a parent on Windows
+                    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+                    let backup_path = dir.with_file_name(format!("{}{}", name, ts));
+                    tx.replace_dir(&dir, Some(backup_path));
+                }
+                ExistingDirs::Delete => tx.replace_dir(&dir, None),
+                ExistingDirs::Keep => {}
+            }
+        }
+
+        // extract everything into staging first; the live tree is untouched on failure
+        let files: Vec<&PlannedFile> = plan
+            .files
+            .iter()
+            .filter(|f| f.action != Action::Skip)
+            .collect();
+        progress.start(files.len() as u64, files.iter().map(|f| f.size).sum());
+        let extracted = files
+            .into_iter()
+            .map(|f| Ok((f, tx.stage(&f.target)?)))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|staged| self.extract(capsule, staged, progress));
+        if let Err(e) = extracted {
+            warn!(error = %e, "extraction failed, discarding staged files");
+            tx.abort();
+            return Err(e);
+        }
+
+        tx.commit()?;
+        // only now, as filling a directory changes its mtime; deepest first,
+        // so a read-only directory is only closed once its children are done
+        let mut dirs: Vec<&PlannedFile> = plan
+            .files
+            .iter()
+            .filter(|f| f.is_dir && f.action != Action::Skip)
+            .collect();
+        dirs.sort_by_key(|f| Reverse(f.target.components().count()));
+        for dir in dirs {
+            if let Err(e) = set_dir_metadata(dir) {
+                warn!(dir = %dir.target.display(), error = %e, "could not restore directory metadata");
+            }
+        }
+        info!(
+            created = plan.count(Action::Create),
+            overwritten = plan.count(Action::Overwrite),
+            skipped = plan.count(Action::Skip),
+            "restore committed"
+        );
+        progress.finish();
+        Ok(())
+    }
+
+    /// Write each planned file to its staged path using a pool of workers,
+    /// then make the links.
+    ///
+    /// Files are grouped by directory so each worker fills whole directories,
+    /// and every worker reads through its own handle on the archive. Links
+    /// come last, so nothing is ever written through one.
+    fn extract(
+        &self,
+        capsule: &Capsule,
+        staged: Vec<(&PlannedFile, PathBuf)>,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        let (links, staged): (Vec<_>, Vec<_>) = staged
+            .into_iter()
+            .partition(|(planned, _)| planned.is_symlink);
+        let mut groups: BTreeMap<PathBuf, Vec<(&PlannedFile, PathBuf)>> = BTreeMap::new();
+        for (planned, path) in staged {
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            groups.entry(dir).or_default().push((planned, path));
+        }
+        let groups: Vec<_> = groups.into_values().collect();
+
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let workers = self.jobs.clamp(1, groups.len().max(1));
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| -> Result<()> {
+                        let mut archive = capsule.archive()?;
+                        while !failed.load(Ordering::Relaxed) {
+                            let Some(group) = groups.get(next.fetch_add(1, Ordering::Relaxed))
+                            else {
+                                break;
+                            };
+                            for (planned, path) in group {
+                                let written = archive
+                                    .by_index(planned.index)
+                                    .map_err(Into::into)
+                                    .and_then(|mut zip_file| {
+                                        if planned.is_dir {
+                                            fs::create_dir_all(path)?;
+                                            return Ok(0);
+                                        }
+                                        let mut outfile = fs::File::create(path)?;
+                                        let written = io::copy(&mut zip_file, &mut outfile)?;
+                                        set_metadata(&outfile, planned)?;
+                                        Ok(written)
+                                    });
+                                if let Err(e) = written {
+                                    failed.store(true, Ordering::Relaxed);
+                                    return Err(e);
+                                }
+                                progress.inc(Path::new(&planned.name), planned.size);
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            handles.into_iter().try_for_each(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err(io::Error::other("extraction worker panicked").into()))
+            })
+        })?;
+
+        let mut archive = capsule.archive()?;
+        for (planned, path) in links {
+            let mut target = String::new();
+            archive
+                .by_index(planned.index)?
+                .read_to_string(&mut target)?;
+            symlink(&target, &path)?;
+            progress.inc(Path::new(&planned.name), planned.size);
+        }
+        Ok(())
+    }
+
+    /// Extract a single file from `capsule`, returning where it was written.
+    ///
+    /// `query` is resolved like in [`file_history`](crate::file_history). The
+    /// file goes back to its original location unless `dest` is given; a
+    /// `dest` that is an existing directory receives the file under its own
+    /// name. Parent directories are created as needed.
+    pub fn restore_file(
+        &self,
+        capsule: &Capsule,
+        query: &str,
+        dest: Option<&Path>,
+    ) -> Result<PathBuf> {
+        let files = capsule.files()?;
+        let entry = resolve_path(&files, query).ok_or_else(|| Error::NotInCapsule {
+            capsule: capsule.name().to_string(),
+            path: query.to_string(),
+        })?;
+
+        let outpath = match dest {
+            Some(dest) if dest.is_dir() => {
+                dest.join(entry.path.rsplit('/').next().unwrap_or(&entry.path))
+            }
+            Some(dest) => dest.to_path_buf(),
+            None => match SourceKind::of_archive_path(&entry.path) {
+                Some((kind, rest)) => self.target_dir(kind).join(rest),
+                None => self.home.join(&entry.path),
+            },
+        };
+        if let Some(parent) = outpath.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut outfile = fs::File::create(&outpath)?;
+        capsule.copy_file(&entry.path, &mut outfile)?;
+        Ok(outpath)
+    }
+
+    /// Extract the packer.nvim snapshots of a `create --plugins-only` capsule
+    /// into the cache directory, next to what's there, if the configuration
+    /// is being restored. Returns the paths written.
+    pub fn restore_snapshots(&self, capsule: &Capsule) -> Result<Vec<PathBuf>> {
+        if !self.dirs.is_empty() || !self.sources.contains(&SourceKind::Config) {
+            return Ok(Vec::new());
+        }
+        let prefix = format!(
+            "{}/{}/",
+            SourceKind::Cache.archive_dir(),
+            PACKER_SNAPSHOT_DIR
+        );
+        let mut written = Vec::new();
+        for entry in capsule.files()? {
+            let Some(rest) = entry.path.strip_prefix(prefix.as_str()) else {
+                continue;
+            };
+            if !self.filter.matches(&entry.path) {
+                continue;
+            }
+            let outpath = self
+                .target_dir(SourceKind::Cache)
+                .join(PACKER_SNAPSHOT_DIR)
+                .join(rest);
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut outfile = fs::File::create(&outpath)?;
+            capsule.copy_file(&entry.path, &mut outfile)?;
+            written.push(outpath);
+        }
+        Ok(written)
+    }
+}
+
+/// The archived link among the parent directories of the entry `name`, if any.
+fn linked_ancestor<'a>(name: &str, links: &'a HashSet<String>) -> Option<&'a str> {
+    name.match_indices('/')
+        .find_map(|(i, _)| links.get(&name[..i]))
+        .map(String::as_str)
+}
+
+/// Give an extracted file the permissions and mtime it was archived with.
+fn set_metadata(file: &fs::File, planned: &PlannedFile) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = planned.mode {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    if let Some(mtime) = planned.mtime {
+        file.set_modified(mtime)?;
+    }
+    Ok(())
+}
+
+/// Give a restored directory the permissions and mtime it was archived with.
+fn set_dir_metadata(planned: &PlannedFile) -> io::Result<()> {
+    // Windows can't open a directory as a file to set its mtime
+    #[cfg(unix)]
+    if let Some(mtime) = planned.mtime {
+        fs::File::open(&planned.target)?.set_modified(mtime)?;
+    }
+    if let Some(mode) = planned.mode {
+        set_mode(&planned.target, mode)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+pub(crate) fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    // a target outside the capsule may not exist, so guess from the name
+    let resolved = path.parent().unwrap_or(path).join(target);
+    if resolved.is_file() || !resolved.exists() && Path::new(target).extension().is_some() {
+        std::os::windows::fs::symlink_file(target, path)
+    } else {
+        std::os::windows::fs::symlink_dir(target, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::writer::CapsuleWriter;
+
+    /// A capsule of `.config/nvim` built by `add`.
+    fn capsule(dir: &Path, add: impl FnOnce(&mut CapsuleWriter)) -> Capsule {
+        let path = dir.join("nvim_capsule_20250513120000.zip");
+        testing::capsule(&path, &[".config/nvim"], add)
+    }
+
+    /// A capsule of the profile `tmux`, which backs up `.config/tmux`.
+    fn tmux_capsule(dir: &Path) -> Capsule {
+        let path = dir.join("tmux_capsule_20250513120000.zip");
+        testing::capsule(&path, &[".config/tmux"], |w| {
+            w.manifest_mut().profile = Some("tmux".to_string());
+            w.add_file(".config/tmux/tmux.conf", b"set -g mouse on")
+                .unwrap();
+        })
+    }
+
+    #[test]
+    fn finds_linked_ancestors() {
+        let links: HashSet<String> = [".config/nvim/lua".to_string()].into();
+        assert_eq!(
+            linked_ancestor(".config/nvim/lua/init.lua", &links),
+            Some(".config/nvim/lua")
+        );
+        assert_eq!(linked_ancestor(".config/nvim/lua", &links), None);
+        assert_eq!(linked_ancestor(".config/nvim/luarc.json", &links), None);
+    }
+
+    #[test]
+    fn refuses_entries_inside_an_archived_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule = capsule(dir.path(), |w| {
+            w.add_symlink(".config/nvim/x", "/elsewhere").unwrap();
+            w.add_file(".config/nvim/x/file", b"outside").unwrap();
+        });
+        let restorer = Restorer::new(dir.path().join("out")).plain_layout();
+        let err = restorer.plan(&capsule, ExistingDirs::Delete).unwrap_err();
+        assert!(
+            err.to_string().contains("inside the archived link"),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn makes_links_after_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        // the link comes first in the archive and points at a file after it
+        let capsule = capsule(dir.path(), |w| {
+            w.add_symlink(".config/nvim/alias.lua", "lua/init.lua")
+                .unwrap();
+            w.add_dir(".config/nvim/lua", 0o755).unwrap();
+            w.add_file(".config/nvim/lua/init.lua", b"-- init").unwrap();
+        });
+        let out = dir.path().join("out");
+        let restorer = Restorer::new(&out).plain_layout().jobs(1);
+        let plan = restorer.plan(&capsule, ExistingDirs::Keep).unwrap();
+        restorer
+            .execute(&capsule, &plan, ExistingDirs::Keep, &crate::NoProgress)
+            .unwrap();
+        let alias = out.join(".config/nvim/alias.lua");
+        assert!(fs::symlink_metadata(&alias).unwrap().is_symlink());
+        assert_eq!(fs::read(alias).unwrap(), b"-- init");
+    }
+
+    #[test]
+    fn restores_a_profile_capsule_into_its_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule = tmux_capsule(dir.path());
+        let home = dir.path().join("home");
+        Restorer::for_capsule(&home, &capsule)
+            .unwrap()
+            .restore(&capsule, ExistingDirs::Delete, &crate::NoProgress)
+            .unwrap();
+        assert_eq!(
+            fs::read(home.join(".config/tmux/tmux.conf")).unwrap(),
+            b"set -g mouse on"
+        );
+    }
+
+    #[test]
+    fn saves_the_current_state_of_a_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule = tmux_capsule(dir.path());
+        let home = dir.path().join("home");
+        fs::create_dir_all(home.join(".config/tmux")).unwrap();
+        fs::write(home.join(".config/tmux/notes.bak"), b"mine").unwrap();
+        // what the profile leaves out is replaced all the same
+        let config = Config {
+            profiles: [(
+                "tmux".to_string(),
+                Profile {
+                    sources: vec!["~/.config/tmux".to_string()],
+                    exclude: vec!["**/*.bak".to_string()],
+                    prefix: Some("tmux-".to_string()),
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let restorer = Restorer::for_capsule(&home, &capsule).unwrap();
+        let (archiver, naming) = restorer.current_state(&config, &capsule).unwrap();
+        let files: Vec<String> = archiver
+            .scan()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(files, [".config/tmux/notes.bak"]);
+        assert!(naming.stem(Local::now()).starts_with("tmux-"));
+    }
+
+    #[test]
+    fn previews_under_another_appname() {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule = capsule(dir.path(), |w| {
+            w.add_file(".config/nvim/init.lua", b"-- tried").unwrap();
+        });
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join(".config/nvim")).unwrap();
+        fs::write(root.join(".config/nvim/init.lua"), b"-- live").unwrap();
+        assert!(Restorer::preview(&root, &capsule, DEFAULT_APPNAME).is_err());
+        Restorer::preview(&root, &capsule, "nvim-try")
+            .unwrap()
+            .plain_layout()
+            .restore(&capsule, ExistingDirs::Delete, &crate::NoProgress)
+            .unwrap();
+        assert_eq!(
+            fs::read(root.join(".config/nvim-try/init.lua")).unwrap(),
+            b"-- tried"
+        );
+        assert_eq!(
+            fs::read(root.join(".config/nvim/init.lua")).unwrap(),
+            b"-- live"
+        );
+    }
+
+    #[test]
+    fn refuses_to_preview_a_profile_capsule() {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule = tmux_capsule(dir.path());
+        let home = dir.path().join("home");
+        fs::create_dir_all(home.join(".config/tmux")).unwrap();
+        fs::write(home.join(".config/tmux/tmux.conf"), b"live").unwrap();
+        let err = Restorer::preview(&home, &capsule, "nvim-try").unwrap_err();
+        assert!(err.to_string().contains("profile capsule"), "{}", err);
+        assert_eq!(
+            fs::read(home.join(".config/tmux/tmux.conf")).unwrap(),
+            b"live"
+        );
+    }
+
+    #[test]
+    fn restores_a_file_into_the_capsules_appname() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("nvim_capsule_nvim-minimal_20250513120000.zip");
+        let capsule = testing::capsule(&path, &[".config/nvim"], |w| {
+            w.manifest_mut().appname = "nvim-minimal".to_string();
+            w.add_file(".config/nvim/init.lua", b"-- minimal").unwrap();
+        });
+        let root = dir.path().join("root");
+        let dest = Restorer::for_capsule(&root, &capsule)
+            .unwrap()
+            .plain_layout()
+            .restore_file(&capsule, "init.lua", None)
+            .unwrap();
+        assert_eq!(dest, root.join(".config/nvim-minimal/init.lua"));
+        assert_eq!(fs::read(dest).unwrap(), b"-- minimal");
+        assert!(!root.join(".config/nvim").exists());
+    }
+}
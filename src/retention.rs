@@ -0,0 +1,344 @@
+use crate::age::Age;
+use crate::capsule::Capsule;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::size::ByteSize;
+use chrono::{DateTime, Local};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+/// Which capsules `prune` keeps. A capsule survives if any rule keeps it;
+/// pinned capsules always do.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Keep the N most recent capsules.
+    pub keep_last: Option<usize>,
+    /// Keep the newest capsule of each of the last N days that have one.
+    pub keep_daily: Option<usize>,
+    /// The same per ISO week.
+    pub keep_weekly: Option<usize>,
+    /// The same per calendar month.
+    pub keep_monthly: Option<usize>,
+    /// Keep everything younger than this.
+    pub keep_within: Option<Age>,
+    /// Then drop the oldest kept capsules until the rest fit into this.
+    pub max_total_size: Option<ByteSize>,
+}
+
+/// One capsule in a retention plan, with the rules that keep it (none if it
+/// is to be pruned).
+#[derive(Debug, Clone)]
+pub struct PruneItem {
+    pub capsule: Capsule,
+    pub size: u64,
+    pub kept_by: Vec<&'static str>,
+}
+
+impl PruneItem {
+    pub fn keep(&self) -> bool {
+        !self.kept_by.is_empty()
+    }
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none()
+            && self.keep_daily.is_none()
+            && self.keep_weekly.is_none()
+            && self.keep_monthly.is_none()
+            && self.keep_within.is_none()
+            && self.max_total_size.is_none()
+    }
+
+    /// Plan each timeline of `capsules` on its own, going by the names
+    /// `config` gives the capsules of each profile and `NVIM_APPNAME`, so one
+    /// timeline never pushes out the capsules of another. Returns the name of
+    /// every timeline with its plan, by name.
+    pub fn plan_timelines(
+        &self,
+        capsules: Vec<Capsule>,
+        config: &Config,
+        now: DateTime<Local>,
+    ) -> Result<Vec<(String, Vec<PruneItem>)>> {
+        self.check()?;
+        let mut timelines: BTreeMap<(String, String), Vec<Capsule>> = BTreeMap::new();
+        for capsule in capsules {
+            let naming = config.capsule_naming(&capsule)?;
+            timelines
+                .entry((naming.profile().to_string(), naming.prefix().to_string()))
+                .or_default()
+                .push(capsule);
+        }
+        timelines
+            .into_iter()
+            .map(|((name, _), capsules)| Ok((name, self.plan(capsules, now)?)))
+            .collect()
+    }
+
+    /// Decide for every capsule whether it is kept, newest first.
+    pub fn plan(&self, capsules: Vec<Capsule>, now: DateTime<Local>) -> Result<Vec<PruneItem>> {
+        self.check()?;
+        // a size limit on its own keeps everything that fits
+        let only_size = RetentionPolicy {
+            max_total_size: None,
+            ..self.clone()
+        }
+        .is_empty();
+
+        let mut items = Vec::new();
+        for capsule in capsules {
+            let mut kept_by = Vec::new();
+            if capsule.meta()?.pinned {
+                kept_by.push("pinned");
+            }
+            if only_size {
+                kept_by.push("size");
+            }
+            let size = capsule.size()?;
+            items.push(PruneItem {
+                capsule,
+                size,
+                kept_by,
+            });
+        }
+
+        // as in `CapsuleStore::list`, which tells apart capsules of the same second
+        items.sort_by_key(|i| Reverse((i.capsule.created(), i.capsule.modified())));
+
+        if let Some(n) = self.keep_last {
+            items
+                .iter_mut()
+                .take(n)
+                .for_each(|i| i.kept_by.push("last"));
+        }
+        for (rule, n, format) in [
+            ("daily", self.keep_daily, "%Y-%m-%d"),
+            ("weekly", self.keep_weekly, "%G-W%V"),
+            ("monthly", self.keep_monthly, "%Y-%m"),
+        ] {
+            let Some(n) = n else { continue };
+            let mut periods = HashSet::new();
+            for item in &mut items {
+                let Some(created) = item.capsule.created() else {
+                    continue;
+                };
+                if periods.len() == n {
+                    break;
+                }
+                if periods.insert(created.format(format).to_string()) {
+                    item.kept_by.push(rule);
+                }
+            }
+        }
+        if let Some(age) = self.keep_within {
+            for item in &mut items {
+                if item
+                    .capsule
+                    .created()
+                    .is_none_or(|t| !age.exceeded_by(t, now))
+                {
+                    item.kept_by.push("within");
+                }
+            }
+        }
+
+        if let Some(ByteSize(limit)) = self.max_total_size {
+            let mut total: u64 = items.iter().filter(|i| i.keep()).map(|i| i.size).sum();
+            for item in items.iter_mut().rev() {
+                if total <= limit {
+                    break;
+                }
+                if item.keep() && !item.kept_by.contains(&"pinned") {
+                    item.kept_by.clear();
+                    total -= item.size;
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.is_empty() {
+            return Err(Error::InvalidInput(
+                "no retention rules given (see `prune --help`)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::CapsuleMeta;
+    use crate::testing;
+    use chrono::{NaiveDateTime, TimeDelta};
+    use std::fs;
+
+    fn now() -> DateTime<Local> {
+        NaiveDateTime::parse_from_str("20250513120000", "%Y%m%d%H%M%S")
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+    }
+
+    /// Capsules of `size` bytes in a new directory, made the given spans
+    /// before [`now`].
+    fn capsules(
+        ago: impl IntoIterator<Item = TimeDelta>,
+        size: usize,
+    ) -> (tempfile::TempDir, Vec<Capsule>) {
+        let dir = tempfile::tempdir().unwrap();
+        let capsules = ago
+            .into_iter()
+            .map(|ago| {
+                let stamp = (now() - ago).format("%Y%m%d%H%M%S");
+                let path = dir.path().join(format!("nvim_capsule_{}.zip", stamp));
+                fs::write(&path, vec![0; size]).unwrap();
+                Capsule::from_path(path)
+            })
+            .collect();
+        (dir, capsules)
+    }
+
+    fn kept(items: &[PruneItem]) -> Vec<&str> {
+        items
+            .iter()
+            .filter(|i| i.keep())
+            .map(|i| i.capsule.name())
+            .collect()
+    }
+
+    #[test]
+    fn needs_a_rule() {
+        assert!(RetentionPolicy::default().plan(Vec::new(), now()).is_err());
+    }
+
+    #[test]
+    fn keeps_the_last_newest_first() {
+        let (_dir, capsules) = capsules((0..5).map(TimeDelta::days), 1);
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        let items = policy.plan(capsules, now()).unwrap();
+        assert_eq!(items.len(), 5);
+        assert_eq!(
+            kept(&items),
+            [
+                "nvim_capsule_20250513120000.zip",
+                "nvim_capsule_20250512120000.zip"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_newest_of_each_day() {
+        let (_dir, capsules) = capsules([0, 2, 24, 26, 48, 72].map(TimeDelta::hours), 1);
+        let policy = RetentionPolicy {
+            keep_daily: Some(2),
+            ..Default::default()
+        };
+        let items = policy.plan(capsules, now()).unwrap();
+        assert_eq!(
+            kept(&items),
+            [
+                "nvim_capsule_20250513120000.zip",
+                "nvim_capsule_20250512120000.zip"
+            ]
+        );
+        assert_eq!(items[0].kept_by, ["daily"]);
+    }
+
+    #[test]
+    fn keeps_within_an_age_and_pinned_ones() {
+        let (_dir, capsules) = capsules([40, 20, 3].map(TimeDelta::days), 1);
+        capsules[0]
+            .set_meta(&CapsuleMeta {
+                pinned: true,
+                ..Default::default()
+            })
+            .unwrap();
+        let policy = RetentionPolicy {
+            keep_within: Some("1w".parse().unwrap()),
+            ..Default::default()
+        };
+        let items = policy.plan(capsules, now()).unwrap();
+        assert_eq!(
+            kept(&items),
+            [
+                "nvim_capsule_20250510120000.zip",
+                "nvim_capsule_20250403120000.zip"
+            ]
+        );
+        assert_eq!(items[2].kept_by, ["pinned"]);
+    }
+
+    #[test]
+    fn drops_the_oldest_over_the_size_limit() {
+        let (_dir, capsules) = capsules((0..4).map(TimeDelta::days), 100);
+        let policy = RetentionPolicy {
+            max_total_size: Some(ByteSize(250)),
+            ..Default::default()
+        };
+        let items = policy.plan(capsules, now()).unwrap();
+        assert_eq!(
+            kept(&items),
+            [
+                "nvim_capsule_20250513120000.zip",
+                "nvim_capsule_20250512120000.zip"
+            ]
+        );
+    }
+
+    #[test]
+    fn plans_each_timeline_on_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule = |stem: &str, appname: &str, profile: Option<&str>| {
+            let path = dir.path().join(format!("{}.zip", stem));
+            testing::capsule(&path, &[".config/nvim"], |w| {
+                w.manifest_mut().appname = appname.to_string();
+                w.manifest_mut().profile = profile.map(str::to_string);
+            })
+        };
+        let capsules = vec![
+            capsule("nvim_capsule_20250510120000", "nvim", None),
+            capsule(
+                "nvim_capsule_nvim-minimal_20250511120000",
+                "nvim-minimal",
+                None,
+            ),
+            capsule("nvim_capsule_20250512120000", "nvim", None),
+            // a profile capsule has directories of its own
+            testing::capsule(
+                &dir.path().join("tmux_capsule_20250509120000.zip"),
+                &[".config/tmux"],
+                |w| w.manifest_mut().profile = Some("tmux".to_string()),
+            ),
+            capsule("nvim_capsule_20250513120000", "nvim", None),
+        ];
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            ..Default::default()
+        };
+        let timelines = policy
+            .plan_timelines(capsules, &Config::default(), now())
+            .unwrap();
+        let timelines: Vec<(&str, Vec<&str>, usize)> = timelines
+            .iter()
+            .map(|(name, items)| (name.as_str(), kept(items), items.len()))
+            .collect();
+        assert_eq!(
+            timelines,
+            [
+                ("nvim", vec!["nvim_capsule_20250513120000.zip"], 3),
+                (
+                    "nvim-minimal",
+                    vec!["nvim_capsule_nvim-minimal_20250511120000.zip"],
+                    1
+                ),
+                ("tmux", vec!["tmux_capsule_20250509120000.zip"], 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::capsule::{Capsule, CAPSULE_EXTENSION};
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::metadata::meta_path;
+use crate::remote::FetchedCapsule;
+use crate::sources::SourceKind;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// The part of a capsule holding one source directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct Section {
+    /// Source directory, relative to HOME, e.g. `.config/nvim`.
+    pub source: String,
+    pub file_count: u64,
+    /// Sum of the uncompressed file sizes in bytes.
+    pub size: u64,
+    /// Bytes the section takes up in the archive.
+    pub compressed_size: u64,
+}
+
+/// Whether the archive entry `path` belongs to the source directory `source`.
+fn in_source(path: &str, source: &str) -> bool {
+    path.strip_prefix(source)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl Capsule {
+    /// Files and bytes per source directory, in manifest order, going by the
+    /// central directory alone. Legacy capsules are split by Neovim directory.
+    pub fn sections(&self) -> Result<Vec<Section>> {
+        let mut archive = self.archive()?;
+        let sources = match Manifest::read_from(&mut archive)? {
+            Some(manifest) => manifest.sources,
+            None => SourceKind::ALL
+                .iter()
+                .map(|k| k.archive_dir().to_string())
+                .collect(),
+        };
+        let mut sections: Vec<Section> = sources
+            .into_iter()
+            .map(|source| Section {
+                source,
+                file_count: 0,
+                size: 0,
+                compressed_size: 0,
+            })
+            .collect();
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            let Some(section) = sections
+                .iter_mut()
+                .find(|s| in_source(entry.name(), &s.source))
+            else {
+                continue;
+            };
+            if !entry.is_dir() {
+                section.file_count += 1;
+                section.size += entry.size();
+            }
+            section.compressed_size += entry.compressed_size();
+        }
+        Ok(sections)
+    }
+
+    /// Copy the sections of the Neovim directories `only` into a capsule in a
+    /// scratch directory, along with its metadata, e.g. to send a capsule to
+    /// another machine without its cache. It is named after the directories
+    /// it keeps, `<name>-config.zip`, so it isn't mistaken for the whole one.
+    ///
+    /// The entries are copied as they are compressed, so the sections left
+    /// out are never read.
+    pub fn extract_sections(&self, only: &[SourceKind]) -> Result<FetchedCapsule> {
+        let mut archive = self.archive()?;
+        let mut manifest = Manifest::read_from(&mut archive)?.ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "{} has no manifest; run `nvimTimeMachine migrate` first",
+                self.name()
+            ))
+        })?;
+        if manifest.profile.is_some() {
+            return Err(Error::InvalidInput(
+                "--only selects Neovim directories; this is a profile capsule".to_string(),
+            ));
+        }
+        let sources: Vec<&str> = only.iter().map(|k| k.archive_dir()).collect();
+        let wanted = |path: &str| sources.iter().any(|s| in_source(path, s));
+
+        let dir = tempfile::Builder::new()
+            .prefix("nvim-tm-sections-")
+            .tempdir()?
+            .keep();
+        let extracted = FetchedCapsule {
+            capsule: Capsule::from_path(dir.join(self.sections_name(only))),
+            dir,
+        };
+        let mut zip = ZipWriter::new(fs::File::create(extracted.capsule.path())?);
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            if wanted(entry.name()) {
+                zip.raw_copy_file(entry)?;
+            }
+        }
+        manifest.sources.retain(|s| wanted(s));
+        manifest.files.retain(|f| wanted(&f.path));
+        manifest.symlinks.retain(|l| wanted(&l.path));
+        manifest.file_count = manifest.files.len() as u64;
+        manifest.total_size = manifest.files.iter().map(|f| f.size).sum();
+        let options: FileOptions<'_, ()> = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(0o644);
+        zip.start_file(MANIFEST_NAME, options)?;
+        zip.write_all(&manifest.to_json()?)?;
+        zip.finish()?;
+
+        if self.meta_path().exists() {
+            fs::copy(self.meta_path(), meta_path(extracted.capsule.path()))?;
+        }
+        Ok(extracted)
+    }
+
+    /// The name of the capsule cut down to the directories `only`.
+    fn sections_name(&self, only: &[SourceKind]) -> String {
+        let suffix = format!(".{}", CAPSULE_EXTENSION);
+        let stem = self.name().strip_suffix(&suffix).unwrap_or(self.name());
+        let mut name = stem.to_string();
+        for kind in SourceKind::ALL.iter().filter(|k| only.contains(k)) {
+            name.push('-');
+            name.push_str(kind.name());
+        }
+        name + &suffix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn extracts_sections_under_their_own_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvim_capsule_20250513120000.zip");
+        let sources = [".config/nvim", ".local/share/nvim"];
+        let capsule = testing::capsule(&path, &sources, |w| {
+            w.add_file(".config/nvim/init.lua", b"vim.o.number = true")
+                .unwrap();
+            w.add_file(".local/share/nvim/lazy/x.lua", b"").unwrap();
+        });
+
+        let extracted = capsule.extract_sections(&[SourceKind::Config]).unwrap();
+        let part = extracted.capsule();
+        assert_eq!(part.name(), "nvim_capsule_20250513120000-config.zip");
+        let manifest = part.manifest().unwrap().unwrap();
+        assert_eq!(manifest.sources, [".config/nvim"]);
+        assert_eq!(manifest.file_count, 1);
+
+        let scratch = part.path().parent().unwrap().to_path_buf();
+        drop(extracted);
+        assert!(!scratch.exists());
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A size given on the command line, like `500M` or `5GB` (binary units).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    const UNITS: [(&'static str, u64); 5] = [
+        ("B", 1),
+        ("K", 1 << 10),
+        ("M", 1 << 20),
+        ("G", 1 << 30),
+        ("T", 1 << 40),
+    ];
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, scale) = Self::UNITS
+            .iter()
+            .rev()
+            .find(|(_, scale)| self.0 >= *scale)
+            .unwrap_or(&Self::UNITS[0]);
+        if *scale == 1 {
+            write!(f, "{}B", self.0)
+        } else {
+            write!(f, "{:.1}{}iB", self.0 as f64 / *scale as f64, unit)
+        }
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid size \"{}\" (expected e.g. 500M, 5G or 1.5GB)", s);
+        let upper = s.trim().to_ascii_uppercase();
+        let digits = upper
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(upper.len());
+        let (amount, unit) = upper.split_at(digits);
+        let amount: f64 = amount.parse().map_err(|_| invalid())?;
+        let unit = unit.trim();
+        let unit = unit
+            .strip_suffix("IB")
+            .or_else(|| unit.strip_suffix('B').filter(|u| !u.is_empty()))
+            .unwrap_or(unit);
+        let scale = match unit {
+            "" => 1,
+            _ => Self::UNITS
+                .iter()
+                .find(|(u, _)| *u == unit)
+                .map(|(_, scale)| *scale)
+                .ok_or_else(invalid)?,
+        };
+        Ok(ByteSize((amount * scale as f64) as u64))
+    }
+}
+
+/// A size in a config file, like `"2G"`, or a plain number of bytes.
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!("1024".parse(), Ok(ByteSize(1024)));
+        assert_eq!("500M".parse(), Ok(ByteSize(500 << 20)));
+        assert_eq!("5GB".parse(), Ok(ByteSize(5 << 30)));
+        assert_eq!("2gib".parse(), Ok(ByteSize(2 << 30)));
+        assert_eq!("1.5G".parse(), Ok(ByteSize(3 << 29)));
+        assert_eq!(" 10 K ".parse(), Ok(ByteSize(10 << 10)));
+        assert_eq!("7B".parse(), Ok(ByteSize(7)));
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        for s in ["", "M", "5X", "5MM", "1..5G", "-5M"] {
+            assert!(s.parse::<ByteSize>().is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn displays_sizes() {
+        assert_eq!(ByteSize(512).to_string(), "512B");
+        assert_eq!(ByteSize(1536).to_string(), "1.5KiB");
+        assert_eq!(ByteSize(5 << 30).to_string(), "5.0GiB");
+    }
+
+    #[test]
+    fn deserializes_text_or_bytes() {
+        let size: ByteSize = serde_json::from_str("\"2G\"").unwrap();
+        assert_eq!(size, ByteSize(2 << 30));
+        let size: ByteSize = serde_json::from_str("4096").unwrap();
+        assert_eq!(size, ByteSize(4096));
+        assert!(serde_json::from_str::<ByteSize>("\"lots\"").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use crate::archiver::mode;
+use crate::capsule::{Capsule, CAPSULE_EXTENSION};
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::naming::CapsuleNaming;
+use crate::remote::FetchedCapsule;
+use crate::sources::archive_path;
+use crate::writer::CapsuleWriter;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Name of the directory inside the store holding the snapshots.
+pub const SNAPSHOT_DIR_NAME: &str = "snapshots";
+
+/// Suffix of a snapshot directory still being written.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Summary of a snapshot, as shown by `list --snapshots`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub created: DateTime<Local>,
+    pub file_count: u64,
+    pub total_size: u64,
+    /// Bytes in files no other snapshot links to, freed by deleting this one.
+    pub unique_size: u64,
+}
+
+/// An uncompressed capsule: a directory tree of the archived files with the
+/// manifest next to them, written by
+/// [`Archiver::snapshot`](crate::Archiver::snapshot). Files that didn't
+/// change from one snapshot to the next are hard links to the same data.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    path: PathBuf,
+    name: String,
+}
+
+/// The directory of snapshots inside a [`CapsuleStore`](crate::CapsuleStore).
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    /// The snapshot in the directory `path`.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Snapshot { path, name }
+    }
+
+    /// Name of the directory, e.g. `nvim_capsule_20250513120000`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creation time, going by the timestamp in the name.
+    pub fn created(&self) -> Option<DateTime<Local>> {
+        // the same naming scheme as archives
+        Capsule::from_path(&self.path).timestamp_from_name()
+    }
+
+    pub fn manifest(&self) -> Result<Manifest> {
+        Manifest::from_json(&fs::read(self.path.join(MANIFEST_NAME))?)
+    }
+
+    pub fn info(&self) -> Result<SnapshotInfo> {
+        let manifest = self.manifest()?;
+        let mut unique_size = 0;
+        for entry in &manifest.files {
+            let meta = fs::symlink_metadata(self.path.join(&entry.path))?;
+            if links(&meta) == 1 {
+                unique_size += meta.len();
+            }
+        }
+        Ok(SnapshotInfo {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            created: self.created().unwrap_or(manifest.created),
+            file_count: manifest.file_count,
+            total_size: manifest.total_size,
+            unique_size,
+        })
+    }
+
+    /// Pack the snapshot into a capsule archive in a scratch directory, to
+    /// restore it like any other capsule.
+    pub fn pack(&self) -> Result<FetchedCapsule> {
+        let dir = env::temp_dir().join(format!("nvim-tm-snapshot-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        let packed = FetchedCapsule {
+            capsule: Capsule::from_path(dir.join(format!("{}.{}", self.name, CAPSULE_EXTENSION))),
+            dir,
+        };
+        let manifest = self.manifest()?;
+        let mut writer = CapsuleWriter::create(packed.capsule.path(), manifest.sources.clone())?;
+        *writer.manifest_mut() = Manifest {
+            file_count: 0,
+            total_size: 0,
+            files: Vec::new(),
+            symlinks: Vec::new(),
+            ..manifest.clone()
+        };
+        for entry in &manifest.files {
+            let path = self.path.join(&entry.path);
+            let meta = fs::metadata(&path)?;
+            writer.add_file_with(
+                &entry.path,
+                &fs::read(&path)?,
+                mode(&meta),
+                meta.modified()?,
+            )?;
+        }
+        for link in &manifest.symlinks {
+            writer.add_symlink(&link.path, &link.target)?;
+        }
+        // so empty directories come back too
+        for source in &manifest.sources {
+            let root = self.path.join(source);
+            for entry in WalkDir::new(&root).min_depth(1).sort_by_file_name() {
+                let entry = entry.map_err(io::Error::from)?;
+                if entry.file_type().is_dir() {
+                    let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                    let meta = entry.metadata().map_err(io::Error::from)?;
+                    writer.add_dir_with(
+                        &archive_path(source, rel),
+                        mode(&meta),
+                        meta.modified()?,
+                    )?;
+                }
+            }
+        }
+        writer.finish()?;
+        Ok(packed)
+    }
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SnapshotStore { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// All finished snapshots, oldest first.
+    pub fn list(&self) -> Result<Vec<Snapshot>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut snapshots: Vec<Snapshot> = entries
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| p.join(MANIFEST_NAME).is_file())
+            .filter(|p| !p.to_string_lossy().ends_with(PARTIAL_SUFFIX))
+            .map(Snapshot::from_path)
+            .collect();
+        snapshots.sort_by(|a, b| (a.created(), &a.name).cmp(&(b.created(), &b.name)));
+        Ok(snapshots)
+    }
+
+    /// The snapshot called `name`, or `latest`.
+    pub fn find(&self, name: &str) -> Result<Snapshot> {
+        let mut snapshots = self.list()?;
+        let found = if name == "latest" {
+            snapshots.pop()
+        } else {
+            snapshots.into_iter().find(|s| s.name() == name)
+        };
+        found.ok_or_else(|| Error::CapsuleNotFound(name.to_string()))
+    }
+
+    /// The newest snapshot with a name of `naming`, for the next one to link
+    /// its unchanged files to.
+    pub fn latest_named(&self, naming: &CapsuleNaming) -> Result<Option<Snapshot>> {
+        Ok(self.list()?.into_iter().rfind(|s| naming.matches(s.name())))
+    }
+
+    /// Create the snapshot directory and return a fresh path for a new
+    /// snapshot named by `naming`.
+    pub fn new_snapshot_path_named(&self, naming: &CapsuleNaming) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let stem = naming.stem(Local::now());
+        let mut path = self.dir.join(&stem);
+        for n in 1.. {
+            if !path.exists() {
+                break;
+            }
+            path = self.dir.join(format!("{}-{}", stem, n));
+        }
+        Ok(path)
+    }
+}
+
+/// Where [`Archiver::snapshot`](crate::Archiver::snapshot) writes before
+/// moving the finished tree to `dest`.
+pub(crate) fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_owned();
+    name.push(PARTIAL_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Whether the file at `path` has the given size, permissions and
+/// modification time, so a hard link to it carries them all.
+pub(crate) fn same_file_metadata(path: &Path, meta: &fs::Metadata) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|old| {
+        old.is_file()
+            && old.len() == meta.len()
+            && mode(&old) == mode(meta)
+            && old.modified().ok() == meta.modified().ok()
+    })
+}
+
+/// Give a freshly written file its original modification time and permissions.
+pub(crate) fn set_file_metadata(path: &Path, mode: u32, mtime: SystemTime) -> io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(mtime)?;
+    set_mode(path, mode)
+}
+
+#[cfg(unix)]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+/// Number of hard links to a file; always 1 where that isn't known.
+fn links(meta: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        meta.nlink()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        1
+    }
+}
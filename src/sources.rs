@@ -0,0 +1,193 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Default Neovim application name, i.e. the `nvim` in `~/.config/nvim`.
+pub const DEFAULT_APPNAME: &str = "nvim";
+
+/// Archive path of the file at `rel` inside the archive directory `dir`.
+pub(crate) fn archive_path(dir: &str, rel: &Path) -> String {
+    let mut name = dir.to_string();
+    for part in rel.components() {
+        name.push('/');
+        name.push_str(&part.as_os_str().to_string_lossy());
+    }
+    name
+}
+
+/// `NVIM_APPNAME`s with a configuration on this machine: the directories next
+/// to `~/.config/nvim` holding an `init.lua` or `init.vim`, sorted.
+pub fn find_appnames(home: &Path) -> Vec<String> {
+    let config = SourceKind::Config.dir(home, DEFAULT_APPNAME);
+    let Some(parent) = config.parent() else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = fs::read_dir(parent)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| {
+            ["init.lua", "init.vim"]
+                .iter()
+                .any(|f| e.path().join(f).is_file())
+        })
+        .filter_map(|e| e.file_name().to_str().map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+/// One of the Neovim directories captured in a capsule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SourceKind {
+    /// `~/.local/share/nvim`: plugins and other data.
+    Data,
+    /// `~/.config/nvim`: the configuration itself.
+    Config,
+    /// `~/.cache/nvim`.
+    Cache,
+    /// `~/.local/state/nvim`: undo history, shada, swap files and logs.
+    State,
+}
+
+impl SourceKind {
+    pub const ALL: [SourceKind; 4] = [
+        SourceKind::Data,
+        SourceKind::Config,
+        SourceKind::Cache,
+        SourceKind::State,
+    ];
+
+    /// Short name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceKind::Data => "data",
+            SourceKind::Config => "config",
+            SourceKind::Cache => "cache",
+            SourceKind::State => "state",
+        }
+    }
+
+    /// Parent of the directory, relative to HOME (on Unix).
+    pub fn base_dir(self) -> &'static str {
+        match self {
+            SourceKind::Data => ".local/share",
+            SourceKind::Config => ".config",
+            SourceKind::Cache => ".cache",
+            SourceKind::State => ".local/state",
+        }
+    }
+
+    /// XDG base directory variable that relocates the parent directory.
+    pub fn env_var(self) -> &'static str {
+        match self {
+            SourceKind::Data => "XDG_DATA_HOME",
+            SourceKind::Config => "XDG_CONFIG_HOME",
+            SourceKind::Cache => "XDG_CACHE_HOME",
+            SourceKind::State => "XDG_STATE_HOME",
+        }
+    }
+
+    /// Where the directory's files live inside a capsule, relative to its root.
+    /// This is the Unix layout on every platform, so capsules can be moved
+    /// between machines.
+    pub fn archive_dir(self) -> &'static str {
+        match self {
+            SourceKind::Data => ".local/share/nvim",
+            SourceKind::Config => ".config/nvim",
+            SourceKind::Cache => ".cache/nvim",
+            SourceKind::State => ".local/state/nvim",
+        }
+    }
+
+    /// The directory on this machine for the Neovim app name `appname`, where
+    /// Neovim's `stdpath()` puts it: below the XDG base directory if its
+    /// variable is set, otherwise in the platform's default place.
+    pub fn dir(self, home: &Path, appname: &str) -> PathBuf {
+        // like Neovim, ignore relative values
+        let xdg = env::var_os(self.env_var())
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute());
+        if cfg!(windows) {
+            let local = || {
+                env::var_os("LOCALAPPDATA")
+                    .map_or_else(|| home.join("AppData").join("Local"), PathBuf::from)
+            };
+            match self {
+                SourceKind::Data | SourceKind::State => {
+                    xdg.unwrap_or_else(local).join(format!("{}-data", appname))
+                }
+                SourceKind::Config => xdg.unwrap_or_else(local).join(appname),
+                SourceKind::Cache => xdg.unwrap_or_else(env::temp_dir).join(appname),
+            }
+        } else {
+            xdg.unwrap_or_else(|| home.join(self.base_dir()))
+                .join(appname)
+        }
+    }
+
+    /// The directory in the capsule's own layout below `root`, e.g.
+    /// `<root>/.config/<appname>`, wherever Neovim keeps it on this machine.
+    pub fn plain_dir(self, root: &Path, appname: &str) -> PathBuf {
+        root.join(self.base_dir()).join(appname)
+    }
+
+    /// The kinds in `kinds` whose directory isn't also that of an earlier one,
+    /// as on Windows, where data and state share `nvim-data`.
+    pub fn distinct(kinds: &[SourceKind], home: &Path, appname: &str) -> Vec<SourceKind> {
+        let mut seen = Vec::new();
+        kinds
+            .iter()
+            .copied()
+            .filter(|k| {
+                let dir = k.dir(home, appname);
+                let new = !seen.contains(&dir);
+                seen.push(dir);
+                new
+            })
+            .collect()
+    }
+
+    /// Where the archive entry `path` belongs on this machine.
+    pub fn live_path(home: &Path, appname: &str, path: &str) -> PathBuf {
+        match Self::of_archive_path(path) {
+            Some((kind, rest)) => kind.dir(home, appname).join(rest),
+            None => home.join(path),
+        }
+    }
+
+    /// Split an archive path into its source kind and the path inside that directory.
+    pub fn of_archive_path(path: &str) -> Option<(SourceKind, &str)> {
+        Self::ALL.into_iter().find_map(|kind| {
+            path.strip_prefix(kind.archive_dir())
+                .and_then(|rest| rest.strip_prefix('/'))
+                .map(|rest| (kind, rest))
+        })
+    }
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|k| k.name()).collect();
+                format!(
+                    "unknown source \"{}\" (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
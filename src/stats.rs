@@ -0,0 +1,107 @@
+use crate::capsule::Capsule;
+use crate::error::Result;
+use crate::sources::SourceKind;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Size figures of one capsule in [`StoreStats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct CapsuleStats {
+    pub name: String,
+    pub created: Option<DateTime<Local>>,
+    /// Size of the archive itself.
+    pub size: u64,
+    /// Uncompressed size of the archived files.
+    pub content_size: u64,
+    pub file_count: u64,
+    /// Archive size change against the previous capsule.
+    pub growth: Option<i64>,
+}
+
+/// A file or directory with its (total) size.
+#[derive(Debug, Clone, Serialize)]
+pub struct PathSize {
+    pub path: String,
+    pub size: u64,
+}
+
+/// Where the space in a store goes, as reported by `stats`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StoreStats {
+    /// Sum of all archive sizes.
+    pub total_size: u64,
+    pub capsules: Vec<CapsuleStats>,
+    /// Biggest files and directories in the newest capsule.
+    pub largest_files: Vec<PathSize>,
+    pub largest_dirs: Vec<PathSize>,
+    /// Uncompressed bytes across all capsules.
+    pub content_size: u64,
+    /// The part of `content_size` that is distinct file contents; the rest is
+    /// the same file stored again in another capsule.
+    pub unique_content_size: u64,
+}
+
+impl StoreStats {
+    /// Bytes that storing each distinct file only once would save.
+    pub fn dedup_potential(&self) -> u64 {
+        self.content_size - self.unique_content_size
+    }
+}
+
+/// Gather [`StoreStats`] for `capsules` (oldest first), listing the `top`
+/// largest entries of the last one.
+pub fn store_stats(capsules: &[Capsule], top: usize) -> Result<StoreStats> {
+    let mut stats = StoreStats::default();
+    let mut seen = HashSet::new();
+    let mut previous = None;
+    let mut latest = Vec::new();
+    for capsule in capsules {
+        let size = capsule.size()?;
+        let files = capsule.files()?;
+        let content_size = files.iter().map(|f| f.size).sum();
+        for file in &files {
+            if seen.insert(file.sha256.clone()) {
+                stats.unique_content_size += file.size;
+            }
+        }
+        stats.total_size += size;
+        stats.content_size += content_size;
+        stats.capsules.push(CapsuleStats {
+            name: capsule.name().to_string(),
+            created: capsule.created(),
+            size,
+            content_size,
+            file_count: files.len() as u64,
+            growth: previous.map(|p: u64| size as i64 - p as i64),
+        });
+        previous = Some(size);
+        latest = files;
+    }
+
+    // directories one and two levels into each Neovim directory, e.g.
+    // `.local/share/nvim/lazy` and `.local/share/nvim/lazy/telescope.nvim`
+    let mut dirs: HashMap<String, u64> = HashMap::new();
+    for file in &latest {
+        let Some((kind, rest)) = SourceKind::of_archive_path(&file.path) else {
+            continue;
+        };
+        let parts: Vec<&str> = rest.split('/').collect();
+        for depth in 1..parts.len().min(3) {
+            let dir = format!("{}/{}", kind.archive_dir(), parts[..depth].join("/"));
+            *dirs.entry(dir).or_default() += file.size;
+        }
+    }
+    stats.largest_dirs = largest(dirs.into_iter(), top);
+    stats.largest_files = largest(latest.into_iter().map(|f| (f.path, f.size)), top);
+    Ok(stats)
+}
+
+pub(crate) fn largest(entries: impl Iterator<Item = (String, u64)>, top: usize) -> Vec<PathSize> {
+    let mut entries: Vec<PathSize> = entries
+        .map(|(path, size)| PathSize { path, size })
+        .collect();
+    entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    entries.truncate(top);
+    entries
+}
@@ -0,0 +1,537 @@
+use crate::capsule::{Capsule, CAPSULE_EXTENSION};
+use crate::error::{Error, Result};
+use crate::git::{GitHistory, GIT_HISTORY_DIR_NAME};
+use crate::index::CapsuleIndex;
+use crate::journal::{journal_path, PART_SUFFIX};
+use crate::metadata::move_meta;
+use crate::naming::CapsuleNaming;
+use crate::retention::RetentionPolicy;
+use crate::snapshot::{SnapshotStore, SNAPSHOT_DIR_NAME};
+use crate::sources::DEFAULT_APPNAME;
+use crate::volume::volume_path;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// Name of the capsule directory inside HOME.
+pub const CAPSULE_DIR_NAME: &str = ".nvim_capsules";
+
+/// Name of the log directory inside the store.
+pub const LOG_DIR_NAME: &str = "logs";
+
+/// Name of the directory inside the store that unreadable capsules are
+/// moved to.
+pub const CORRUPT_DIR_NAME: &str = "corrupt";
+
+/// Lock file taken while a process works on the store.
+const LOCK_FILE_NAME: &str = ".lock";
+
+/// File name prefix of v2 capsules (with an embedded manifest).
+pub const CAPSULE_PREFIX: &str = "nvim_capsule_";
+
+/// File name prefix of legacy v1 capsules.
+pub const LEGACY_PREFIX: &str = "nvim_backup_";
+
+/// File name prefix of the capsules of the Neovim app name `appname`.
+pub fn capsule_prefix(appname: &str) -> String {
+    if appname == DEFAULT_APPNAME {
+        CAPSULE_PREFIX.to_string()
+    } else {
+        format!("{}{}_", CAPSULE_PREFIX, appname)
+    }
+}
+
+/// Exclusive hold on a store, released when dropped.
+#[derive(Debug)]
+pub struct StoreLock {
+    _file: fs::File,
+}
+
+/// A capsule moved out of the way by [`CapsuleStore::quarantine`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Quarantined {
+    pub name: String,
+    /// Where the archive is now.
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Directory holding all time capsules.
+#[derive(Debug, Clone)]
+pub struct CapsuleStore {
+    dir: PathBuf,
+}
+
+impl CapsuleStore {
+    /// Use `dir` as the capsule directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        CapsuleStore { dir: dir.into() }
+    }
+
+    /// The default store at `~/.nvim_capsules` for the given HOME.
+    pub fn in_home(home: &Path) -> Self {
+        Self::new(home.join(CAPSULE_DIR_NAME))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where log files are kept, inside the store.
+    pub fn log_dir(&self) -> PathBuf {
+        self.dir.join(LOG_DIR_NAME)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.dir.exists()
+    }
+
+    /// Take the store lock, so only one process at a time creates, changes
+    /// or restores capsules. Fails with [`Error::LockHeld`] instead of waiting.
+    pub fn lock(&self) -> Result<StoreLock> {
+        fs::create_dir_all(&self.dir)?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(LOCK_FILE_NAME))?;
+        match file.try_lock() {
+            Ok(()) => Ok(StoreLock { _file: file }),
+            Err(fs::TryLockError::WouldBlock) => Err(Error::LockHeld(self.dir.clone())),
+            Err(fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Like [`lock`](Self::lock), but `None` if another process holds the
+    /// lock, for read-only commands that only tidy up when nothing else is
+    /// going on.
+    pub fn try_lock(&self) -> Result<Option<StoreLock>> {
+        match self.lock() {
+            Ok(lock) => Ok(Some(lock)),
+            Err(Error::LockHeld(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// All capsules in the store, oldest first. A missing store is empty.
+    pub fn list(&self) -> Result<Vec<Capsule>> {
+        if !self.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut capsules: Vec<Capsule> = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| Capsule::is_capsule_path(p))
+            .map(Capsule::from_path)
+            .collect();
+
+        // sort by creation time (oldest first), so copied or touched capsules
+        // with a timestamped name keep their place
+        capsules.sort_by_key(|c| (c.created(), c.modified()));
+        Ok(capsules)
+    }
+
+    /// Find a capsule by file name, with or without the extension, or by one
+    /// of the aliases `latest`, `previous` and `latest~N` (N before the latest).
+    pub fn find(&self, name: &str) -> Result<Capsule> {
+        let suffix = format!(".{}", CAPSULE_EXTENSION);
+        let mut capsules = self.list()?;
+        if let Some(i) = capsules
+            .iter()
+            .position(|c| c.name() == name || c.name().strip_suffix(&suffix) == Some(name))
+        {
+            return Ok(capsules.swap_remove(i));
+        }
+
+        let back = match name {
+            "latest" => Some(0),
+            "previous" => Some(1),
+            _ => name.strip_prefix("latest~").and_then(|n| n.parse().ok()),
+        };
+        back.and_then(|back: usize| capsules.len().checked_sub(back + 1))
+            .map(|i| capsules.swap_remove(i))
+            .ok_or_else(|| Error::CapsuleNotFound(name.to_string()))
+    }
+
+    /// The newest capsule with a name of `naming`.
+    pub fn latest_named(&self, naming: &CapsuleNaming) -> Result<Option<Capsule>> {
+        Ok(self.list()?.into_iter().rfind(|c| naming.matches(c.name())))
+    }
+
+    /// The capsule with a name of `naming` whose creation was interrupted
+    /// most recently, as the path it gets once finished with
+    /// [`Archiver::resume`](crate::Archiver::resume).
+    pub fn interrupted(&self, naming: &CapsuleNaming) -> Result<Option<PathBuf>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        let mut names: Vec<String> = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter(|e| journal_path(&e.path()).is_file())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().into_owned();
+                name.strip_suffix(PART_SUFFIX).map(str::to_string)
+            })
+            .filter(|name| naming.matches(name))
+            .collect();
+        // the timestamp in the name orders them
+        names.sort_by_key(|name| Capsule::from_path(name).timestamp_from_name());
+        Ok(names.pop().map(|name| self.dir.join(name)))
+    }
+
+    /// The capsule at 1-based `index` in [`list`](Self::list) order, as
+    /// numbered by `nvimTimeMachine list`.
+    pub fn nth(&self, index: usize) -> Result<Capsule> {
+        let mut capsules = self.list()?;
+        let count = capsules.len();
+        if count == 0 {
+            return Err(Error::NoCapsules);
+        }
+        if index == 0 || index > count {
+            return Err(Error::NoSuchIndex { index, count });
+        }
+        Ok(capsules.swap_remove(index - 1))
+    }
+
+    /// The newest capsule created at or before `moment`.
+    pub fn as_of(&self, moment: DateTime<Local>) -> Result<Capsule> {
+        self.list()?
+            .into_iter()
+            .filter(|c| c.created().is_some_and(|t| t <= moment))
+            .max_by_key(|c| c.created())
+            .ok_or_else(|| Error::NoneAsOf(moment.format("%Y-%m-%d %H:%M").to_string()))
+    }
+
+    /// The most recent capsule.
+    pub fn latest(&self) -> Result<Capsule> {
+        self.list()?.pop().ok_or(Error::NoCapsules)
+    }
+
+    /// Remove `capsule`, its volumes and its metadata from disk.
+    pub fn delete(&self, capsule: &Capsule) -> Result<()> {
+        for path in capsule.volumes() {
+            fs::remove_file(path)?;
+        }
+        capsule.set_meta(&Default::default())?;
+        if let Err(e) = self.index().and_then(|i| i.remove(capsule.name())) {
+            warn!(capsule = capsule.name(), error = %e, "could not update index");
+        }
+        info!(capsule = capsule.name(), "deleted capsule");
+        Ok(())
+    }
+
+    /// Apply `policy` to the capsules with a name of `naming` alone, leaving
+    /// the other timelines be, and delete what it doesn't keep, except the
+    /// capsules at `spare`. Returns the deleted capsules.
+    pub fn prune_named(
+        &self,
+        _lock: &StoreLock,
+        policy: &RetentionPolicy,
+        naming: &CapsuleNaming,
+        spare: &[PathBuf],
+    ) -> Result<Vec<Capsule>> {
+        let capsules = self
+            .list()?
+            .into_iter()
+            .filter(|c| naming.matches(c.name()))
+            .collect();
+        let mut pruned = Vec::new();
+        for item in policy.plan(capsules, Local::now())? {
+            if !item.keep() && !spare.iter().any(|p| p == item.capsule.path()) {
+                self.delete(&item.capsule)?;
+                pruned.push(item.capsule);
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Where [`quarantine`](Self::quarantine) moves unreadable capsules.
+    pub fn corrupt_dir(&self) -> PathBuf {
+        self.dir.join(CORRUPT_DIR_NAME)
+    }
+
+    /// Move `capsule`, its volumes and its metadata to the `corrupt`
+    /// directory, next to a `<name>.reason` file saying when and why, so it
+    /// is no longer listed or offered for restoring. Takes the store lock, so
+    /// no capsule is moved while another process writes it.
+    pub fn quarantine(
+        &self,
+        _lock: &StoreLock,
+        capsule: &Capsule,
+        reason: &str,
+    ) -> Result<Quarantined> {
+        let dir = self.corrupt_dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(capsule.name());
+        for (n, volume) in capsule.volumes().iter().enumerate() {
+            fs::rename(volume, volume_path(&path, n + 1))?;
+        }
+        move_meta(capsule, &path)?;
+        fs::write(
+            dir.join(format!("{}.reason", capsule.name())),
+            format!("{}  {}\n", Local::now().format("%Y-%m-%d %H:%M:%S"), reason),
+        )?;
+        if let Err(e) = self.index().and_then(|i| i.remove(capsule.name())) {
+            warn!(capsule = capsule.name(), error = %e, "could not update index");
+        }
+        info!(capsule = capsule.name(), reason, "quarantined capsule");
+        Ok(Quarantined {
+            name: capsule.name().to_string(),
+            path,
+            reason: reason.to_string(),
+        })
+    }
+
+    /// [`Quarantine`](Self::quarantine) every capsule whose archive can't be
+    /// opened, such as one cut short by a full disk.
+    pub fn quarantine_unreadable(&self, lock: &StoreLock) -> Result<Vec<Quarantined>> {
+        let mut quarantined = Vec::new();
+        for capsule in self.list()? {
+            if let Err(Error::BadArchive { source, .. }) = capsule.manifest() {
+                quarantined.push(self.quarantine(lock, &capsule, &source.to_string())?);
+            }
+        }
+        Ok(quarantined)
+    }
+
+    /// Give `capsule` a new file name, such as `pre-lazy-migration.zip`, as
+    /// checked by [`named_path`](Self::named_path).
+    pub fn rename(&self, capsule: &Capsule, new_name: &str) -> Result<Capsule> {
+        let new_path = self.named_path(new_name)?;
+        let new_name = new_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for (n, path) in capsule.volumes().iter().enumerate() {
+            fs::rename(path, volume_path(&new_path, n + 1))?;
+        }
+        move_meta(capsule, &new_path)?;
+        if let Err(e) = self
+            .index()
+            .and_then(|i| i.rename(capsule.name(), &new_name))
+        {
+            warn!(capsule = capsule.name(), error = %e, "could not update index");
+        }
+        info!(from = capsule.name(), to = %new_name, "renamed capsule");
+        Ok(Capsule::from_path(new_path))
+    }
+
+    /// The path of a capsule called `name` in the store. The extension is
+    /// added if left out; anything else that would hide the capsule from the
+    /// store, or an existing name, is rejected.
+    pub fn named_path(&self, name: &str) -> Result<PathBuf> {
+        let suffix = format!(".{}", CAPSULE_EXTENSION);
+        let name = if name.ends_with(&suffix) {
+            name.to_string()
+        } else {
+            format!("{}{}", name, suffix)
+        };
+        let stem = &name[..name.len() - suffix.len()];
+        if stem.is_empty() || stem.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(Error::InvalidInput(format!(
+                "invalid capsule name \"{}\"",
+                name
+            )));
+        }
+
+        let path = self.dir.join(&name);
+        if path.exists() {
+            return Err(Error::AlreadyExists(path));
+        }
+        Ok(path)
+    }
+
+    /// Create the store directory and keep macOS Time Machine from backing
+    /// it up, as the capsules are backups already. Does nothing but create
+    /// the directory on other platforms.
+    pub fn exclude_from_time_machine(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        if !cfg!(target_os = "macos") {
+            return Ok(());
+        }
+        // a sticky exclusion: it moves along with the directory
+        let status = Command::new("tmutil")
+            .arg("addexclusion")
+            .arg(&self.dir)
+            .status()?;
+        if !status.success() {
+            return Err(
+                io::Error::other(format!("tmutil addexclusion failed ({})", status)).into(),
+            );
+        }
+        info!(dir = %self.dir.display(), "excluded store from Time Machine");
+        Ok(())
+    }
+
+    /// The file list index of this store.
+    pub fn index(&self) -> Result<CapsuleIndex> {
+        CapsuleIndex::open(self)
+    }
+
+    /// The companion git repository of the config directories.
+    pub fn git_history(&self) -> GitHistory {
+        GitHistory::new(self.dir.join(GIT_HISTORY_DIR_NAME))
+    }
+
+    /// The snapshots kept in the store's `snapshots` directory.
+    pub fn snapshots(&self) -> SnapshotStore {
+        SnapshotStore::new(self.dir.join(SNAPSHOT_DIR_NAME))
+    }
+
+    /// Add a newly written capsule to the index. A failure is only logged, as
+    /// the index catches up the next time it is used.
+    pub fn index_capsule(&self, capsule: &Capsule) {
+        if let Err(e) = self.index().and_then(|i| i.update(capsule)) {
+            warn!(capsule = capsule.name(), error = %e, "could not index capsule");
+        }
+    }
+
+    /// Create the store directory and return a fresh timestamped path for a new capsule.
+    pub fn new_capsule_path(&self) -> Result<PathBuf> {
+        self.new_capsule_path_for(DEFAULT_APPNAME)
+    }
+
+    /// Like [`new_capsule_path`](Self::new_capsule_path), with the app name in
+    /// the file name unless it is the default, e.g.
+    /// `nvim_capsule_nvim-minimal_20250513120000.zip`.
+    pub fn new_capsule_path_for(&self, appname: &str) -> Result<PathBuf> {
+        self.new_capsule_path_named(&CapsuleNaming::with_prefix(&capsule_prefix(appname)))
+    }
+
+    /// A fresh path for a new capsule named by `naming`.
+    pub fn new_capsule_path_named(&self, naming: &CapsuleNaming) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let stem = naming.stem(Local::now());
+        // a second capsule within the same second must not replace the first
+        let mut path = self.dir.join(format!("{}.{}", stem, CAPSULE_EXTENSION));
+        for n in 1.. {
+            if !path.exists() {
+                break;
+            }
+            path = self
+                .dir
+                .join(format!("{}-{}.{}", stem, n, CAPSULE_EXTENSION));
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::CapsuleWriter;
+
+    /// A store holding empty capsules with the names `stems`.
+    fn store(stems: &[&str]) -> (tempfile::TempDir, CapsuleStore) {
+        let dir = tempfile::tempdir().unwrap();
+        for stem in stems {
+            fs::write(dir.path().join(format!("{}.zip", stem)), b"").unwrap();
+        }
+        let store = CapsuleStore::new(dir.path());
+        (dir, store)
+    }
+
+    #[test]
+    fn finds_by_name() {
+        let (_dir, store) = store(&["nvim_capsule_20250101000000", "pre-lazy"]);
+        assert_eq!(store.find("pre-lazy").unwrap().name(), "pre-lazy.zip");
+        assert_eq!(store.find("pre-lazy.zip").unwrap().name(), "pre-lazy.zip");
+        assert!(matches!(
+            store.find("missing"),
+            Err(Error::CapsuleNotFound(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn finds_by_alias() {
+        let (_dir, store) = store(&[
+            "nvim_capsule_20250103000000",
+            "nvim_capsule_20250101000000",
+            "nvim_capsule_20250102000000",
+        ]);
+        let find = |name| store.find(name).unwrap().name().to_string();
+        assert_eq!(find("latest"), "nvim_capsule_20250103000000.zip");
+        assert_eq!(find("latest~0"), "nvim_capsule_20250103000000.zip");
+        assert_eq!(find("previous"), "nvim_capsule_20250102000000.zip");
+        assert_eq!(find("latest~1"), "nvim_capsule_20250102000000.zip");
+        assert_eq!(find("latest~2"), "nvim_capsule_20250101000000.zip");
+        assert!(store.find("latest~3").is_err());
+        assert!(store.find("latest~x").is_err());
+    }
+
+    #[test]
+    fn an_alias_loses_to_a_capsule_of_that_name() {
+        let (_dir, store) = store(&["nvim_capsule_20250101000000", "latest"]);
+        assert_eq!(store.find("latest").unwrap().name(), "latest.zip");
+    }
+
+    #[test]
+    fn nth_counts_from_one() {
+        let (_dir, store) = store(&["nvim_capsule_20250101000000"]);
+        assert!(store.nth(1).is_ok());
+        assert!(matches!(store.nth(0), Err(Error::NoSuchIndex { .. })));
+        assert!(matches!(store.nth(2), Err(Error::NoSuchIndex { .. })));
+    }
+
+    #[test]
+    fn prunes_one_timeline_sparing_the_new_capsule() {
+        let (dir, store) = store(&[
+            "nvim_capsule_20250101000000",
+            "nvim_capsule_20250102000000",
+            "tmux_capsule_20250101120000",
+            "nvim_capsule_20250103000000",
+        ]);
+        let lock = store.lock().unwrap();
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            ..Default::default()
+        };
+        let naming = CapsuleNaming::with_prefix(CAPSULE_PREFIX);
+        let spare = [dir.path().join("nvim_capsule_20250101000000.zip")];
+        let pruned = store.prune_named(&lock, &policy, &naming, &spare).unwrap();
+        let pruned: Vec<&str> = pruned.iter().map(Capsule::name).collect();
+        assert_eq!(pruned, ["nvim_capsule_20250102000000.zip"]);
+        let left: Vec<String> = store
+            .list()
+            .unwrap()
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect();
+        assert_eq!(
+            left,
+            [
+                "nvim_capsule_20250101000000.zip",
+                "tmux_capsule_20250101120000.zip",
+                "nvim_capsule_20250103000000.zip"
+            ]
+        );
+    }
+
+    #[test]
+    fn quarantines_a_capsule_with_a_truncated_volume() {
+        let (dir, store) = store(&[]);
+        let path = dir.path().join("nvim_capsule_20250101000000.zip");
+        let mut writer =
+            CapsuleWriter::create_split(&path, vec![".config/nvim".to_string()], 64).unwrap();
+        writer
+            .add_file(".config/nvim/init.lua", &[b'x'; 300])
+            .unwrap();
+        writer.finish().unwrap();
+        let capsule = Capsule::from_path(&path);
+        assert!(capsule.volumes().len() > 2);
+        fs::write(volume_path(&path, 2), b"cut").unwrap();
+        assert!(matches!(capsule.manifest(), Err(Error::BadArchive { .. })));
+
+        let lock = store.lock().unwrap();
+        let quarantined = store.quarantine_unreadable(&lock).unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert!(store.list().unwrap().is_empty());
+        assert!(!volume_path(&path, 2).exists());
+        assert!(volume_path(&store.corrupt_dir().join(capsule.name()), 2).exists());
+    }
+}
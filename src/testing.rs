@@ -0,0 +1,19 @@
+//! Fixtures shared by the unit tests.
+
+use crate::capsule::Capsule;
+use crate::writer::CapsuleWriter;
+use std::path::Path;
+
+/// A capsule at `path` of the directories `sources`, holding what `add`
+/// writes.
+pub(crate) fn capsule(
+    path: &Path,
+    sources: &[&str],
+    add: impl FnOnce(&mut CapsuleWriter),
+) -> Capsule {
+    let sources = sources.iter().map(|s| s.to_string()).collect();
+    let mut writer = CapsuleWriter::create(path, sources).unwrap();
+    add(&mut writer);
+    writer.finish().unwrap();
+    Capsule::from_path(path)
+}
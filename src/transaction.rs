@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Stages restored files next to their targets and swaps them in atomically.
+///
+/// Directories being replaced wholesale are extracted into a hidden sibling
+/// (`.nvim.tm-staging-<ts>`) and swapped in with a rename. Individual files
+/// layered over an existing tree are staged in a scratch directory and moved
+/// into place one by one, with the files they replace kept aside until the
+/// commit succeeds. Nothing live is touched until everything has been
+/// extracted, and any failure while swapping puts the previous state back.
+pub(crate) struct Transaction {
+    ts: String,
+    scratch: PathBuf,
+    dirs: Vec<DirSwap>,
+    /// Individually staged files: (staged copy, target).
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+/// A directory replaced wholesale.
+struct DirSwap {
+    target: PathBuf,
+    staging: PathBuf,
+    /// Where the current directory is moved to.
+    aside: PathBuf,
+    /// Whether `aside` is deleted after a successful commit.
+    remove: bool,
+}
+
+/// A committed step that can be undone.
+enum Undo {
+    /// `from` was renamed to `to`.
+    Moved { from: PathBuf, to: PathBuf },
+    /// `path` did not exist before.
+    Created(PathBuf),
+}
+
+impl Transaction {
+    /// Start a transaction with scratch space inside `root`.
+    pub(crate) fn new(root: &Path, ts: &str) -> Self {
+        Transaction {
+            ts: ts.to_string(),
+            scratch: root.join(format!(".nvim-tm-restore-{}", ts)),
+            dirs: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Replace `target` as a whole on commit. The current directory is renamed
+    /// to `backup`, or removed if that is `None`.
+    pub(crate) fn replace_dir(&mut self, target: &Path, backup: Option<PathBuf>) {
+        debug!(dir = %target.display(), backup = ?backup, "replacing directory");
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let hidden =
+            |what: &str| target.with_file_name(format!(".{}.tm-{}-{}", name, what, self.ts));
+        self.dirs.push(DirSwap {
+            target: target.to_path_buf(),
+            staging: hidden("staging"),
+            remove: backup.is_none(),
+            aside: backup.unwrap_or_else(|| hidden("old")),
+        });
+    }
+
+    /// Where to extract the file that should end up at `target`.
+    pub(crate) fn stage(&mut self, target: &Path) -> io::Result<PathBuf> {
+        let in_dir = self.dirs.iter().find_map(|d| {
+            target
+                .strip_prefix(&d.target)
+                .ok()
+                .map(|r| d.staging.join(r))
+        });
+        let staged = match in_dir {
+            Some(staged) => staged,
+            None => {
+                let staged = self
+                    .scratch
+                    .join("staged")
+                    .join(self.files.len().to_string());
+                self.files.push((staged.clone(), target.to_path_buf()));
+                staged
+            }
+        };
+        if let Some(parent) = staged.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(staged)
+    }
+
+    /// Swap everything into place, or roll back and return the error.
+    pub(crate) fn commit(self) -> io::Result<()> {
+        let mut undo = Vec::new();
+        match self.swap(&mut undo) {
+            Ok(removed) => {
+                for dir in removed {
+                    fs::remove_dir_all(dir)?;
+                }
+                self.cleanup();
+                Ok(())
+            }
+            Err(e) => {
+                warn!(error = %e, steps = undo.len(), "swap failed, rolling back");
+                for step in undo.into_iter().rev() {
+                    // best effort: keep undoing even if a single step fails
+                    let _ = match step {
+                        Undo::Moved { from, to } => move_path(&to, &from),
+                        Undo::Created(path) if path.is_dir() => fs::remove_dir_all(&path),
+                        Undo::Created(path) => fs::remove_file(&path),
+                    };
+                }
+                self.abort();
+                Err(e)
+            }
+        }
+    }
+
+    /// Throw away everything staged so far.
+    pub(crate) fn abort(&self) {
+        for dir in &self.dirs {
+            let _ = fs::remove_dir_all(&dir.staging);
+        }
+        self.cleanup();
+    }
+
+    fn cleanup(&self) {
+        let _ = fs::remove_dir_all(&self.scratch);
+    }
+
+    /// Perform the swaps, returning the old directories left to delete.
+    fn swap(&self, undo: &mut Vec<Undo>) -> io::Result<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        for dir in &self.dirs {
+            if dir.target.exists() {
+                move_path(&dir.target, &dir.aside)?;
+                undo.push(Undo::Moved {
+                    from: dir.target.clone(),
+                    to: dir.aside.clone(),
+                });
+                if dir.remove {
+                    removed.push(dir.aside.clone());
+                }
+            }
+            if dir.staging.exists() {
+                move_path(&dir.staging, &dir.target)?;
+                undo.push(Undo::Created(dir.target.clone()));
+            }
+        }
+
+        let replaced = self.scratch.join("replaced");
+        for (i, (staged, target)) in self.files.iter().enumerate() {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // a staged empty directory whose target was created in the meantime
+            if staged.is_dir() && target.is_dir() {
+                continue;
+            }
+            if fs::symlink_metadata(target).is_ok() {
+                fs::create_dir_all(&replaced)?;
+                let aside = replaced.join(i.to_string());
+                move_path(target, &aside)?;
+                undo.push(Undo::Moved {
+                    from: target.clone(),
+                    to: aside,
+                });
+            }
+            move_path(staged, target)?;
+            undo.push(Undo::Created(target.clone()));
+        }
+        Ok(removed)
+    }
+}
+
+/// Rename, falling back to copy-and-delete for files across filesystems.
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if from.is_file() && e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        Err(e) => Err(e),
+    }
+}
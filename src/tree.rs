@@ -0,0 +1,74 @@
+use crate::history::candidate_paths;
+use crate::manifest::ManifestEntry;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A directory or file in the tree view of a capsule.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    /// File size, or the total size of everything below a directory.
+    pub size: u64,
+    /// Number of files at or below this node.
+    pub files: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    pub fn is_dir(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    fn insert(&mut self, parts: &[&str], size: u64) {
+        self.size += size;
+        self.files += 1;
+        if let Some((first, rest)) = parts.split_first() {
+            self.children
+                .entry(first.to_string())
+                .or_insert_with(|| TreeNode {
+                    name: first.to_string(),
+                    ..TreeNode::default()
+                })
+                .insert(rest, size);
+        }
+    }
+}
+
+/// Build a directory tree of `files`, optionally limited to `subpath`.
+///
+/// `subpath` is resolved like [`candidate_paths`]: relative to HOME or to one
+/// of the Neovim directories. Returns `None` if nothing lives under it.
+pub fn build_tree(files: &[ManifestEntry], subpath: Option<&str>) -> Option<TreeNode> {
+    let prefix = match subpath.map(|s| s.trim_end_matches('/')) {
+        None | Some("") => String::new(),
+        Some(query) => candidate_paths(query).into_iter().find(|c| {
+            files
+                .iter()
+                .any(|f| f.path == *c || f.path.starts_with(&format!("{}/", c)))
+        })?,
+    };
+
+    let mut root = TreeNode {
+        name: if prefix.is_empty() {
+            "~".to_string()
+        } else {
+            format!("~/{}", prefix)
+        },
+        ..TreeNode::default()
+    };
+    for file in files {
+        let rest = if prefix.is_empty() {
+            Some(file.path.as_str())
+        } else if file.path == prefix {
+            Some("")
+        } else {
+            file.path.strip_prefix(&format!("{}/", prefix))
+        };
+        if let Some(rest) = rest {
+            let parts: Vec<&str> = rest.split('/').filter(|p| !p.is_empty()).collect();
+            root.insert(&parts, file.size);
+        }
+    }
+    Some(root)
+}
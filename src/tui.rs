@@ -0,0 +1,316 @@
+//! Full-screen front end, started with `nvimTimeMachine tui`.
+
+use nvim_time_machine::{diff_files, Archiver, Capsule, CapsuleStore, Change, NoProgress, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::{Path, PathBuf};
+
+/// What to do after the dashboard was closed.
+pub enum Exit {
+    Quit,
+    /// Restore this capsule, saving the current setup as a capsule first.
+    Restore(String),
+}
+
+#[derive(PartialEq)]
+enum Preview {
+    Contents,
+    /// Changes from the capsule to the live directories.
+    Diff,
+}
+
+enum Input {
+    /// Tags typed so far; `-tag` removes one.
+    Tag(String),
+    ConfirmDelete,
+    ConfirmRestore,
+}
+
+struct App {
+    home: PathBuf,
+    store: CapsuleStore,
+    capsules: Vec<Capsule>,
+    rows: Vec<String>,
+    list: ListState,
+    preview: Preview,
+    lines: Vec<Line<'static>>,
+    scroll: u16,
+    input: Option<Input>,
+    status: String,
+}
+
+/// Run the dashboard until the user quits or picks a capsule to restore.
+pub fn run(home: &Path) -> Result<Exit> {
+    let mut app = App {
+        home: home.to_path_buf(),
+        store: CapsuleStore::in_home(home),
+        capsules: Vec::new(),
+        rows: Vec::new(),
+        list: ListState::default(),
+        preview: Preview::Contents,
+        lines: Vec::new(),
+        scroll: 0,
+        input: None,
+        status: String::new(),
+    };
+    app.reload()?;
+    let mut terminal = ratatui::init();
+    let exit = app.event_loop(&mut terminal);
+    ratatui::restore();
+    exit
+}
+
+impl App {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<Exit> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            // raw mode turns Ctrl-C into a key press
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Ok(Exit::Quit);
+            }
+            if let Some(input) = self.input.take() {
+                if let Some(exit) = self.handle_input(input, key.code)? {
+                    return Ok(exit);
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(Exit::Quit),
+                KeyCode::Down | KeyCode::Char('j') => self.select(1)?,
+                KeyCode::Up | KeyCode::Char('k') => self.select(-1)?,
+                KeyCode::PageDown | KeyCode::Char('J') => {
+                    self.scroll = self.scroll.saturating_add(10)
+                }
+                KeyCode::PageUp | KeyCode::Char('K') => {
+                    self.scroll = self.scroll.saturating_sub(10)
+                }
+                KeyCode::Tab | KeyCode::Char('d') => {
+                    self.preview = match self.preview {
+                        Preview::Contents => Preview::Diff,
+                        Preview::Diff => Preview::Contents,
+                    };
+                    self.load_preview()?;
+                }
+                KeyCode::Char('c') => self.create(terminal)?,
+                KeyCode::Char('r') | KeyCode::Enter if self.selected().is_some() => {
+                    self.input = Some(Input::ConfirmRestore);
+                }
+                KeyCode::Char('x') | KeyCode::Delete if self.selected().is_some() => {
+                    self.input = Some(Input::ConfirmDelete);
+                }
+                KeyCode::Char('t') if self.selected().is_some() => {
+                    self.input = Some(Input::Tag(String::new()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_input(&mut self, input: Input, key: KeyCode) -> Result<Option<Exit>> {
+        match (input, key) {
+            (Input::ConfirmRestore, KeyCode::Char('y')) => {
+                if let Some(capsule) = self.selected() {
+                    return Ok(Some(Exit::Restore(capsule.name().to_string())));
+                }
+            }
+            (Input::ConfirmRestore, _) => self.status = "Nothing restored.".to_string(),
+            (Input::ConfirmDelete, KeyCode::Char('y')) => {
+                let _lock = self.store.lock()?;
+                if let Some(capsule) = self.selected().cloned() {
+                    self.store.delete(&capsule)?;
+                    self.status = format!("Deleted \"{}\"", capsule.name());
+                }
+                self.reload()?;
+            }
+            (Input::ConfirmDelete, _) => self.status = "Nothing deleted.".to_string(),
+            (Input::Tag(text), KeyCode::Enter) => self.apply_tags(&text)?,
+            (Input::Tag(_), KeyCode::Esc) => {}
+            (Input::Tag(mut text), KeyCode::Backspace) => {
+                text.pop();
+                self.input = Some(Input::Tag(text));
+            }
+            (Input::Tag(mut text), KeyCode::Char(c)) => {
+                text.push(c);
+                self.input = Some(Input::Tag(text));
+            }
+            (input @ Input::Tag(_), _) => self.input = Some(input),
+        }
+        Ok(None)
+    }
+
+    fn apply_tags(&mut self, text: &str) -> Result<()> {
+        let Some(capsule) = self.selected().cloned() else {
+            return Ok(());
+        };
+        let _lock = self.store.lock()?;
+        let mut meta = capsule.meta()?;
+        for tag in text.split_whitespace() {
+            match tag.strip_prefix('-') {
+                Some(tag) => {
+                    meta.tags.remove(tag);
+                }
+                None => {
+                    if let Err(e) = meta.add_tag(tag) {
+                        self.status = e.to_string();
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        capsule.set_meta(&meta)?;
+        self.status = format!("Tagged \"{}\"", capsule.name());
+        self.reload()
+    }
+
+    fn create(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        self.status = "Creating a capsule...".to_string();
+        terminal.draw(|frame| self.draw(frame))?;
+        let _lock = self.store.lock()?;
+        let path = self.store.new_capsule_path()?;
+        let manifest = Archiver::new(&self.home).create(&path, &NoProgress)?;
+        self.store.index_capsule(&Capsule::from_path(&path));
+        self.status = format!(
+            "Created \"{}\" ({} files)",
+            Capsule::from_path(&path).name(),
+            manifest.file_count
+        );
+        self.reload()?;
+        // select the new capsule, at the top of the list
+        self.list.select(Some(0));
+        self.load_preview()
+    }
+
+    /// Re-read the store, newest capsule first.
+    fn reload(&mut self) -> Result<()> {
+        self.capsules = self.store.list()?;
+        self.capsules.reverse();
+        self.rows = self
+            .capsules
+            .iter()
+            .map(crate::capsule_row)
+            .collect::<Result<_>>()?;
+        let selected = self.list.selected().unwrap_or(0);
+        self.list.select(match self.capsules.len() {
+            0 => None,
+            n => Some(selected.min(n - 1)),
+        });
+        self.load_preview()
+    }
+
+    fn selected(&self) -> Option<&Capsule> {
+        self.list.selected().and_then(|i| self.capsules.get(i))
+    }
+
+    fn select(&mut self, delta: isize) -> Result<()> {
+        if self.capsules.is_empty() {
+            return Ok(());
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.capsules.len() as isize - 1);
+        self.list.select(Some(next as usize));
+        self.load_preview()
+    }
+
+    fn load_preview(&mut self) -> Result<()> {
+        self.scroll = 0;
+        let Some(capsule) = self.selected() else {
+            self.lines = vec![Line::from("No capsules yet; press c to create one.")];
+            return Ok(());
+        };
+        let files = match capsule.files() {
+            Ok(files) => files,
+            Err(e) => {
+                self.lines = vec![Line::from(e.to_string()).red()];
+                return Ok(());
+            }
+        };
+        self.lines = match self.preview {
+            Preview::Contents => files
+                .iter()
+                .map(|f| Line::from(format!("{:>10}  {}", human(f.size), f.path)))
+                .collect(),
+            Preview::Diff => {
+                let changes = diff_files(&files, &Archiver::new(&self.home).scan()?);
+                if changes.is_empty() {
+                    vec![Line::from("No changes since this capsule.").green()]
+                } else {
+                    changes
+                        .iter()
+                        .map(|c| match c.change {
+                            Change::Added => Line::from(format!("A  {}", c.path)).green(),
+                            Change::Removed => Line::from(format!("D  {}", c.path)).red(),
+                            Change::Modified => Line::from(format!("M  {}", c.path)).yellow(),
+                        })
+                        .collect()
+                }
+            }
+        };
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .map(|r| ListItem::new(r.as_str()))
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" Capsules ({}) ", self.capsules.len())))
+            .highlight_style(Style::new().bg(Color::Blue).add_modifier(Modifier::BOLD));
+        frame.render_stateful_widget(list, left, &mut self.list);
+
+        let title = match self.preview {
+            Preview::Contents => " Contents ",
+            Preview::Diff => " Changes to live ",
+        };
+        let preview = Paragraph::new(self.lines.clone())
+            .block(Block::bordered().title(title))
+            .scroll((self.scroll, 0));
+        frame.render_widget(preview, right);
+
+        let status_line = match &self.input {
+            Some(Input::Tag(text)) => format!("Tags to add (-tag removes): {}_", text),
+            Some(Input::ConfirmRestore) => format!(
+                "Restore \"{}\"? The current setup is saved as a capsule first. [y/N]",
+                self.selected().map_or("", |c| c.name())
+            ),
+            Some(Input::ConfirmDelete) => format!(
+                "Delete \"{}\"? [y/N]",
+                self.selected().map_or("", |c| c.name())
+            ),
+            None => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line).yellow(), status);
+        frame.render_widget(
+            Paragraph::new(
+                "j/k move  J/K scroll  d/tab diff  c create  r restore  x delete  t tag  q quit",
+            )
+            .dim(),
+            help,
+        );
+    }
+}
+
+fn human(bytes: u64) -> String {
+    indicatif::HumanBytes(bytes).to_string()
+}
@@ -0,0 +1,95 @@
+use crate::capsule::Capsule;
+use crate::error::Result;
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::progress::Progress;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use tracing::warn;
+
+/// An archive entry that failed [`Capsule::verify`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DamagedEntry {
+    pub path: String,
+    pub problem: String,
+}
+
+impl Capsule {
+    /// Read every entry of the archive in full, checking its CRC and, for
+    /// capsules with a manifest, its size and SHA-256. Returns the damaged
+    /// entries; an archive that can't be opened at all is an error.
+    pub fn verify(&self, progress: &dyn Progress) -> Result<Vec<DamagedEntry>> {
+        let mut archive = self.archive()?;
+        let mut expected: HashMap<String, (u64, String)> = Manifest::read_from(&mut archive)?
+            .map(|m| {
+                m.files
+                    .into_iter()
+                    .map(|f| (f.path, (f.size, f.sha256)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let total_bytes = (0..archive.len())
+            .filter_map(|i| archive.by_index_raw(i).ok().map(|e| e.size()))
+            .sum();
+        progress.start(archive.len() as u64, total_bytes);
+
+        let names: Vec<String> = (0..archive.len())
+            .map(|i| {
+                archive
+                    .name_for_index(i)
+                    .map_or_else(|| format!("entry #{}", i), str::to_string)
+            })
+            .collect();
+        let mut damaged = Vec::new();
+        for (i, name) in names.into_iter().enumerate() {
+            let mut entry = match archive.by_index(i) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    damaged.push(DamagedEntry {
+                        path: name,
+                        problem: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let path = entry.name().to_string();
+            if entry.is_dir() {
+                continue;
+            }
+            // reading to the end makes the zip reader check the CRC
+            let mut hasher = Sha256::new();
+            let read = io::copy(&mut entry, &mut hasher);
+            let sha256 = format!("{:x}", hasher.finalize());
+            progress.inc(Path::new(&path), entry.size());
+            let problem = match (read, expected.remove(&path)) {
+                (Err(e), _) => Some(e.to_string()),
+                (Ok(size), Some((want_size, _))) if size != want_size => Some(format!(
+                    "{} bytes instead of {} from the manifest",
+                    size, want_size
+                )),
+                (Ok(_), Some((_, want_sha))) if sha256 != want_sha => {
+                    Some("checksum doesn't match the manifest".to_string())
+                }
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                warn!(capsule = self.name(), entry = %path, %problem, "damaged entry");
+                damaged.push(DamagedEntry { path, problem });
+            }
+        }
+
+        let mut missing: Vec<String> = expected
+            .into_keys()
+            .filter(|p| p != MANIFEST_NAME)
+            .collect();
+        missing.sort();
+        damaged.extend(missing.into_iter().map(|path| DamagedEntry {
+            path,
+            problem: "listed in the manifest but missing from the archive".to_string(),
+        }));
+        progress.finish();
+        Ok(damaged)
+    }
+}
@@ -0,0 +1,278 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The archive of a capsule, which may be split into volumes of a fixed size:
+/// `<name>.zip` holds the first bytes, `<name>.zip.002`, `<name>.zip.003`, ...
+/// the rest. Reads, writes and seeks go across volumes as if they were one
+/// file, so the volumes concatenated are a plain zip archive.
+pub(crate) struct Volumes {
+    path: PathBuf,
+    files: Vec<fs::File>,
+    /// Size of every volume but the last; `u64::MAX` for a single file.
+    volume_size: u64,
+    len: u64,
+    pos: u64,
+    /// Volume whose file cursor is at `pos`, so it needs no seek.
+    synced: Option<usize>,
+}
+
+/// Path of volume `n` (counting from 1) of the archive at `path`.
+pub(crate) fn volume_path(path: &Path, n: usize) -> PathBuf {
+    if n == 1 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{:03}", n));
+    path.with_file_name(name)
+}
+
+/// The archive at `path` followed by its other volumes, if it has any.
+pub(crate) fn volume_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.to_path_buf()];
+    for n in 2.. {
+        let next = volume_path(path, n);
+        if !next.exists() {
+            break;
+        }
+        paths.push(next);
+    }
+    paths
+}
+
+impl Volumes {
+    /// Open the archive at `path` with all of its volumes for reading.
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let paths = volume_paths(path);
+        let mut files = Vec::new();
+        let mut sizes = Vec::new();
+        for path in &paths {
+            let file = fs::File::open(path)?;
+            sizes.push(file.metadata()?.len());
+            files.push(file);
+        }
+        let volume_size = if files.len() > 1 { sizes[0] } else { u64::MAX };
+        // only the last volume may be shorter
+        if let Some(i) = sizes[..sizes.len() - 1]
+            .iter()
+            .position(|&s| s != volume_size)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("volume {} is truncated", paths[i].display()),
+            ));
+        }
+        Ok(Volumes {
+            path: path.to_path_buf(),
+            files,
+            volume_size,
+            len: sizes.iter().sum(),
+            pos: 0,
+            synced: Some(0),
+        })
+    }
+
+    /// Create an archive at `path` that starts a new volume every
+    /// `volume_size` bytes, replacing volumes left over from an older one.
+    pub(crate) fn create(path: &Path, volume_size: u64) -> io::Result<Self> {
+        for old in volume_paths(path).iter().skip(1) {
+            fs::remove_file(old)?;
+        }
+        Ok(Volumes {
+            path: path.to_path_buf(),
+            files: vec![Self::create_volume(path)?],
+            volume_size: volume_size.max(1),
+            len: 0,
+            pos: 0,
+            synced: Some(0),
+        })
+    }
+
+    fn create_volume(path: &Path) -> io::Result<fs::File> {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+
+    /// The volume holding byte `pos`, with its file positioned there, and the
+    /// number of bytes left in that volume.
+    fn seek_volume(&mut self, create: bool) -> io::Result<Option<(&mut fs::File, u64)>> {
+        let index = (self.pos / self.volume_size) as usize;
+        let offset = self.pos % self.volume_size;
+        while create && self.files.len() <= index {
+            let path = volume_path(&self.path, self.files.len() + 1);
+            self.files.push(Self::create_volume(&path)?);
+        }
+        let Some(file) = self.files.get_mut(index) else {
+            return Ok(None);
+        };
+        if self.synced != Some(index) {
+            file.seek(SeekFrom::Start(offset))?;
+            self.synced = Some(index);
+        }
+        Ok(Some((file, self.volume_size - offset)))
+    }
+}
+
+impl Read for Volumes {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((file, room)) = self.seek_volume(false)? else {
+            return Ok(0);
+        };
+        let n = (buf.len() as u64).min(room) as usize;
+        let read = file.read(&mut buf[..n])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for Volumes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some((file, room)) = self.seek_volume(true)? else {
+            return Ok(0);
+        };
+        let n = (buf.len() as u64).min(room) as usize;
+        let written = file.write(&buf[..n])?;
+        self.pos += written as u64;
+        self.len = self.len.max(self.pos);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.files.iter_mut().try_for_each(|f| f.flush())
+    }
+}
+
+impl Seek for Volumes {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        let Some(pos) = pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the archive",
+            ));
+        };
+        if pos != self.pos {
+            self.pos = pos;
+            self.synced = None;
+        }
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `len` bytes that differ from their neighbours.
+    fn bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// 25 bytes written to `capsule.zip` in `dir` as volumes of 10.
+    fn split(dir: &Path) -> (PathBuf, Vec<u8>) {
+        let path = dir.join("capsule.zip");
+        let data = bytes(25);
+        Volumes::create(&path, 10)
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+        (path, data)
+    }
+
+    fn read_all(path: &Path) -> Vec<u8> {
+        let mut read = Vec::new();
+        Volumes::open(path).unwrap().read_to_end(&mut read).unwrap();
+        read
+    }
+
+    #[test]
+    fn splits_into_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, data) = split(dir.path());
+        let paths = volume_paths(&path);
+        assert_eq!(
+            paths,
+            [
+                path.clone(),
+                dir.path().join("capsule.zip.002"),
+                dir.path().join("capsule.zip.003"),
+            ]
+        );
+        let sizes: Vec<u64> = paths
+            .iter()
+            .map(|p| fs::metadata(p).unwrap().len())
+            .collect();
+        assert_eq!(sizes, [10, 10, 5]);
+        assert_eq!(read_all(&path), data);
+    }
+
+    #[test]
+    fn seeks_across_volume_boundaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, data) = split(dir.path());
+        let mut volumes = Volumes::open(&path).unwrap();
+        let mut read_at = |pos: SeekFrom, len: usize| {
+            volumes.seek(pos).unwrap();
+            let mut buf = vec![0; len];
+            volumes.read_exact(&mut buf).unwrap();
+            buf
+        };
+        // a read straddling the end of the first volume
+        assert_eq!(read_at(SeekFrom::Start(8), 4), data[8..12]);
+        assert_eq!(read_at(SeekFrom::End(-3), 3), data[22..]);
+        assert_eq!(read_at(SeekFrom::Start(10), 1), data[10..11]);
+        assert_eq!(read_at(SeekFrom::Current(-11), 11), data[0..11]);
+        assert_eq!(read_at(SeekFrom::Start(19), 2), data[19..21]);
+        assert!(volumes.seek(SeekFrom::Current(-100)).is_err());
+        // past the end, there's nothing to read
+        volumes.seek(SeekFrom::Start(40)).unwrap();
+        assert_eq!(volumes.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn rewrites_in_place_across_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capsule.zip");
+        let mut data = bytes(25);
+        let mut volumes = Volumes::create(&path, 10).unwrap();
+        volumes.write_all(&data).unwrap();
+        // like zip going back to fill in a local header
+        volumes.seek(SeekFrom::Start(8)).unwrap();
+        volumes.write_all(&[0xff; 4]).unwrap();
+        volumes.seek(SeekFrom::End(0)).unwrap();
+        volumes.write_all(&[0xee; 2]).unwrap();
+        drop(volumes);
+
+        data[8..12].fill(0xff);
+        data.extend([0xee; 2]);
+        assert_eq!(read_all(&path), data);
+    }
+
+    #[test]
+    fn creating_removes_old_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = split(dir.path());
+        Volumes::create(&path, 10)
+            .unwrap()
+            .write_all(&bytes(5))
+            .unwrap();
+        assert_eq!(volume_paths(&path), [path]);
+    }
+
+    #[test]
+    fn rejects_a_truncated_volume() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = split(dir.path());
+        fs::write(volume_path(&path, 2), bytes(7)).unwrap();
+        let err = Volumes::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
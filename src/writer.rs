@@ -0,0 +1,179 @@
+use crate::error::Result;
+use crate::journal::{Journal, JournalEntry};
+use crate::manifest::{Manifest, ManifestEntry, SymlinkEntry, MANIFEST_NAME};
+use crate::volume::Volumes;
+use chrono::{DateTime, Datelike, Local, Timelike};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::{FileOptions, FullFileOptions};
+use zip::{CompressionMethod, ZipWriter};
+
+/// Header ID of the Info-ZIP extended timestamp field, which holds the
+/// modification time in UTC seconds.
+const EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+/// Low-level writer for a v2 capsule: file entries plus the manifest.
+pub struct CapsuleWriter {
+    zip: ZipWriter<Volumes>,
+    manifest: Manifest,
+    /// Modification time of entries that don't have one of their own.
+    default_mtime: Option<SystemTime>,
+    journal: Option<Journal>,
+}
+
+impl CapsuleWriter {
+    /// Start a new capsule at `dest` covering `sources` (relative to HOME).
+    pub fn create(dest: &Path, sources: Vec<String>) -> Result<Self> {
+        Self::create_split(dest, sources, u64::MAX)
+    }
+
+    /// Like [`create`](Self::create), starting a new volume (`<dest>.002`,
+    /// `<dest>.003`, ...) every `volume_size` bytes.
+    pub fn create_split(dest: &Path, sources: Vec<String>, volume_size: u64) -> Result<Self> {
+        Ok(CapsuleWriter {
+            zip: ZipWriter::new(Volumes::create(dest, volume_size)?),
+            manifest: Manifest::new(sources),
+            default_mtime: None,
+            journal: None,
+        })
+    }
+
+    /// Record every entry added from now on in `journal`.
+    pub(crate) fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    /// Stamp directories, links, the manifest and files added without a
+    /// modification time with `mtime` rather than the current time, so the
+    /// same input always gives the same archive.
+    pub fn set_default_mtime(&mut self, mtime: SystemTime) {
+        self.default_mtime = Some(mtime);
+    }
+
+    /// The manifest being built; its metadata fields may be adjusted before [`finish`](Self::finish).
+    pub fn manifest_mut(&mut self) -> &mut Manifest {
+        &mut self.manifest
+    }
+
+    /// Add a file at `path` (relative to HOME, `/`-separated).
+    pub fn add_file(&mut self, path: &str, contents: &[u8]) -> Result<()> {
+        self.zip.start_file(path, self.options(0o755, None))?;
+        self.write_file(path, contents, 0o755, None)
+    }
+
+    /// Add a file like [`add_file`](Self::add_file), with the unix
+    /// permissions `mode` and modification time `mtime` of the original.
+    pub fn add_file_with(
+        &mut self,
+        path: &str,
+        contents: &[u8],
+        mode: u32,
+        mtime: SystemTime,
+    ) -> Result<()> {
+        self.zip.start_file(path, self.options(mode, Some(mtime)))?;
+        self.write_file(path, contents, mode, Some(mtime))
+    }
+
+    fn write_file(
+        &mut self,
+        path: &str,
+        contents: &[u8],
+        mode: u32,
+        mtime: Option<SystemTime>,
+    ) -> Result<()> {
+        self.zip.write_all(contents)?;
+        let sha256 = format!("{:x}", Sha256::digest(contents));
+        self.record(JournalEntry::File {
+            path: path.to_string(),
+            sha256: sha256.clone(),
+            mode,
+            mtime,
+        })?;
+        self.manifest.push(ManifestEntry {
+            path: path.to_string(),
+            size: contents.len() as u64,
+            sha256,
+        });
+        Ok(())
+    }
+
+    /// Add a directory at `path`, so it's restored even when empty.
+    pub fn add_dir(&mut self, path: &str, mode: u32) -> Result<()> {
+        self.write_dir(path, mode, None)
+    }
+
+    /// Add a directory like [`add_dir`](Self::add_dir), with the modification
+    /// time `mtime` of the original.
+    pub fn add_dir_with(&mut self, path: &str, mode: u32, mtime: SystemTime) -> Result<()> {
+        self.write_dir(path, mode, Some(mtime))
+    }
+
+    fn write_dir(&mut self, path: &str, mode: u32, mtime: Option<SystemTime>) -> Result<()> {
+        self.zip.add_directory(path, self.options(mode, mtime))?;
+        self.record(JournalEntry::Dir {
+            path: path.to_string(),
+            mode,
+            mtime,
+        })
+    }
+
+    /// Add a symbolic link at `path` pointing to `target`.
+    pub fn add_symlink(&mut self, path: &str, target: &str) -> Result<()> {
+        self.zip
+            .add_symlink(path, target, self.options(0o777, None))?;
+        self.record(JournalEntry::Symlink {
+            path: path.to_string(),
+            target: target.to_string(),
+        })?;
+        self.manifest.symlinks.push(SymlinkEntry {
+            path: path.to_string(),
+            target: target.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Write the manifest and close the archive.
+    pub fn finish(mut self) -> Result<Manifest> {
+        self.zip
+            .start_file(MANIFEST_NAME, self.options(0o644, None))?;
+        self.zip.write_all(&self.manifest.to_json()?)?;
+        self.zip.finish()?;
+        Ok(self.manifest)
+    }
+
+    fn record(&mut self, entry: JournalEntry) -> Result<()> {
+        match &mut self.journal {
+            Some(journal) => journal.record(&entry),
+            None => Ok(()),
+        }
+    }
+
+    fn options(&self, mode: u32, mtime: Option<SystemTime>) -> FullFileOptions<'static> {
+        let mut options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(mode);
+        let Some(mtime) = mtime.or(self.default_mtime) else {
+            return options;
+        };
+        // the DOS timestamp has no time zone and only even seconds
+        let local = DateTime::<Local>::from(mtime);
+        if let Ok(dos) = zip::DateTime::from_date_and_time(
+            local.year().clamp(1980, 2107) as u16,
+            local.month() as u8,
+            local.day() as u8,
+            local.hour() as u8,
+            local.minute() as u8,
+            local.second() as u8,
+        ) {
+            options = options.last_modified_time(dos);
+        }
+        let secs = mtime.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as u32;
+        let mut field = vec![1u8];
+        field.extend_from_slice(&secs.to_le_bytes());
+        // can't fail: the field is valid and far from the size limit
+        let _ = options.add_extra_data(EXTENDED_TIMESTAMP, field.into_boxed_slice(), false);
+        options
+    }
+}
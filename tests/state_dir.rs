@@ -0,0 +1,66 @@
+//! The state directory (`~/.local/state/nvim`: shada, undo history, logs) is
+//! one of the default sources, so a plain `create` and `restore` bring it back.
+#![cfg(unix)]
+
+use nvim_time_machine::{CapsuleStore, SourceKind};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Run the binary against `home` only, without asking anything.
+fn run(home: &Path, args: &[&str]) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nvimTimeMachine"));
+    for kind in SourceKind::ALL {
+        command.env_remove(kind.env_var());
+    }
+    let output = command
+        .env("HOME", home)
+        .env("NVIM_TM_NONINTERACTIVE", "1")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn creates_and_restores_the_state_dir() {
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join(".config/nvim");
+    let state = home.path().join(".local/state/nvim");
+    fs::create_dir_all(&config).unwrap();
+    fs::create_dir_all(state.join("undo")).unwrap();
+    fs::write(config.join("init.lua"), "vim.o.undofile = true\n").unwrap();
+    fs::create_dir_all(state.join("shada")).unwrap();
+    fs::write(state.join("shada/main.shada"), b"marks").unwrap();
+    fs::write(state.join("undo/%init.lua"), b"undo tree").unwrap();
+
+    run(home.path(), &["create"]);
+    let capsule = CapsuleStore::in_home(home.path()).latest().unwrap();
+    let manifest = capsule.manifest().unwrap().unwrap();
+    assert!(manifest.sources.iter().any(|s| s == ".local/state/nvim"));
+    let files: Vec<String> = capsule
+        .files()
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+    assert!(files
+        .iter()
+        .any(|f| f == ".local/state/nvim/shada/main.shada"));
+    assert!(files
+        .iter()
+        .any(|f| f == ".local/state/nvim/undo/%init.lua"));
+
+    fs::remove_dir_all(&state).unwrap();
+    run(home.path(), &["restore", "--name", capsule.name()]);
+    assert_eq!(fs::read(state.join("shada/main.shada")).unwrap(), b"marks");
+    assert_eq!(
+        fs::read(state.join("undo/%init.lua")).unwrap(),
+        b"undo tree"
+    );
+}